@@ -0,0 +1,351 @@
+//! Ending a game by resigning, agreeing to a draw or claiming one, from a
+//! panel below the move list. The computer answers draw offers itself.
+
+use bevy::{log, prelude::*};
+
+use crate::{
+    ai,
+    history::GameHistory,
+    layout::{ACTIONS_PANEL_HEIGHT, SIDE_PANEL_WIDTH},
+    menu::{GameMode, GameSetup},
+    rules::{EndReason, GameResult, Outcome, PieceColor},
+    AppState, Board, GameEntity, GameOutcome, FONT,
+};
+
+const PANEL_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const PROMPT_COLOR: Color = Color::rgb(0.95, 0.8, 0.3);
+
+/// The player who offered a draw which has not been answered yet.
+#[derive(Resource, Debug, Default)]
+pub struct DrawOffer(pub Option<PieceColor>);
+
+/// The player who declined the last draw offer, shown until the next move.
+#[derive(Resource, Debug, Default)]
+struct DrawDeclined(Option<PieceColor>);
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum ActionButton {
+    Resign(PieceColor),
+    OfferDraw(PieceColor),
+    AcceptDraw,
+    DeclineDraw,
+    ClaimDraw,
+}
+
+/// Row of the buttons of one player.
+#[derive(Component)]
+struct PlayerActions(PieceColor);
+
+/// Question to the other player while a draw is offered.
+#[derive(Component)]
+struct DrawPrompt;
+
+#[derive(Component)]
+struct DrawPromptText;
+
+/// Result and reason of the game once it is over, or a declined draw offer
+/// while it goes on.
+#[derive(Component)]
+struct ResultText;
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DrawOffer>()
+            .init_resource::<DrawDeclined>()
+            .add_system(spawn_actions_panel.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(withdraw_draw_offer.in_schedule(OnEnter(AppState::InGame)))
+            .add_systems(
+                (action_buttons, expire_draw_offer, update_actions_panel)
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn spawn_actions_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    setup: Res<GameSetup>,
+) {
+    // Puzzles have their own panel
+    if let GameMode::Puzzle { .. } = setup.mode {
+        return;
+    }
+
+    let font = asset_server.load(FONT);
+    let text_style = |color| TextStyle {
+        font: font.clone(),
+        font_size: 18.,
+        color,
+    };
+    let row = || NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            gap: Size::all(Val::Px(6.)),
+            ..default()
+        },
+        ..default()
+    };
+    let spawn_button = |parent: &mut ChildBuilder, button: ActionButton, label: &str| {
+        parent
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Auto, Val::Px(26.)),
+                        flex_grow: 1.,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: BUTTON_COLOR.into(),
+                    ..default()
+                },
+                button,
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, text_style(TEXT_COLOR)));
+            });
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(
+                        Val::Px(SIDE_PANEL_WIDTH - 20.),
+                        Val::Px(ACTIONS_PANEL_HEIGHT),
+                    ),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        bottom: Val::Px(10.),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::FlexEnd,
+                    padding: UiRect::all(Val::Px(8.)),
+                    gap: Size::all(Val::Px(6.)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            for color in [PieceColor::Black, PieceColor::White] {
+                parent
+                    .spawn((row(), PlayerActions(color)))
+                    .with_children(|parent| {
+                        parent.spawn(
+                            TextBundle::from_section(
+                                format!("{:?}", color),
+                                text_style(TEXT_COLOR),
+                            )
+                            .with_style(Style {
+                                size: Size::new(Val::Px(60.), Val::Auto),
+                                ..default()
+                            }),
+                        );
+                        spawn_button(parent, ActionButton::Resign(color), "Resign");
+                        spawn_button(parent, ActionButton::OfferDraw(color), "Draw?");
+                    });
+            }
+
+            parent.spawn(row()).with_children(|parent| {
+                spawn_button(parent, ActionButton::ClaimDraw, "Claim draw");
+            });
+
+            parent.spawn((row(), DrawPrompt)).with_children(|parent| {
+                parent.spawn((
+                    TextBundle::from_section("", text_style(PROMPT_COLOR)),
+                    DrawPromptText,
+                ));
+                spawn_button(parent, ActionButton::AcceptDraw, "Accept");
+                spawn_button(parent, ActionButton::DeclineDraw, "Decline");
+            });
+
+            parent.spawn((
+                TextBundle::from_section("", text_style(PROMPT_COLOR)),
+                ResultText,
+            ));
+        });
+}
+
+fn withdraw_draw_offer(mut offer: ResMut<DrawOffer>, mut declined: ResMut<DrawDeclined>) {
+    offer.0 = None;
+    declined.0 = None;
+}
+
+fn end_game(outcome: &mut GameOutcome, result: GameResult, reason: EndReason) {
+    let end = Outcome { result, reason };
+    log::info!("Game over: {}", end);
+    outcome.0 = Some(end);
+}
+
+#[allow(clippy::type_complexity)]
+fn action_buttons(
+    mut buttons: Query<(&Interaction, &ActionButton, &mut BackgroundColor), Changed<Interaction>>,
+    setup: Res<GameSetup>,
+    board: Res<Board>,
+    history: Res<GameHistory>,
+    mut offer: ResMut<DrawOffer>,
+    mut declined: ResMut<DrawDeclined>,
+    mut outcome: ResMut<GameOutcome>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {}
+            Interaction::Hovered => {
+                *color = BUTTON_HOVERED_COLOR.into();
+                continue;
+            }
+            Interaction::None => {
+                *color = BUTTON_COLOR.into();
+                continue;
+            }
+        }
+
+        if outcome.0.is_some() {
+            continue;
+        }
+
+        match *button {
+            ActionButton::Resign(color) => {
+                end_game(
+                    &mut outcome,
+                    GameResult::Win(color.opposite()),
+                    EndReason::Resignation,
+                );
+            }
+            ActionButton::OfferDraw(color) => match setup.computer() {
+                Some((computer, _)) if computer == color.opposite() => {
+                    if ai::accepts_draw(&board.position, computer) {
+                        end_game(&mut outcome, GameResult::Draw, EndReason::Agreement);
+                    } else {
+                        log::info!("The computer declines the draw");
+                        declined.0 = Some(computer);
+                    }
+                }
+                _ => {
+                    log::info!("{:?} offers a draw", color);
+                    offer.0 = Some(color);
+                    declined.0 = None;
+                }
+            },
+            ActionButton::AcceptDraw => {
+                offer.0 = None;
+                end_game(&mut outcome, GameResult::Draw, EndReason::Agreement);
+            }
+            ActionButton::DeclineDraw => {
+                declined.0 = offer.0.take().map(PieceColor::opposite);
+            }
+            ActionButton::ClaimDraw => {
+                if let Some(reason) = history.draw_claim() {
+                    end_game(&mut outcome, GameResult::Draw, reason);
+                }
+            }
+        }
+    }
+}
+
+/// A draw offer lapses when the other player moves instead of answering,
+/// and a declined one is forgotten after any move.
+fn expire_draw_offer(
+    history: Res<GameHistory>,
+    mut offer: ResMut<DrawOffer>,
+    mut declined: ResMut<DrawDeclined>,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    if declined.0.is_some() {
+        declined.0 = None;
+    }
+    let (Some(offered_by), Some(last)) = (offer.0, history.moves.last()) else {
+        return;
+    };
+
+    let moved = last.position.side_to_move.opposite();
+    if moved != offered_by {
+        offer.0 = None;
+    }
+}
+
+/// Show the buttons which can be used in the current state of the game, or
+/// the result once it is over.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn update_actions_panel(
+    setup: Res<GameSetup>,
+    history: Res<GameHistory>,
+    offer: Res<DrawOffer>,
+    declined: Res<DrawDeclined>,
+    outcome: Res<GameOutcome>,
+    mut rows: Query<(&PlayerActions, &mut Style), Without<ActionButton>>,
+    mut buttons: Query<(&ActionButton, &mut Style), Without<PlayerActions>>,
+    mut prompt: Query<
+        &mut Style,
+        (
+            With<DrawPrompt>,
+            Without<ActionButton>,
+            Without<PlayerActions>,
+        ),
+    >,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<DrawPromptText>>,
+        Query<&mut Text, With<ResultText>>,
+    )>,
+) {
+    if !history.is_changed()
+        && !offer.is_changed()
+        && !declined.is_changed()
+        && !outcome.is_changed()
+    {
+        return;
+    }
+
+    let display = |shown: bool| if shown { Display::Flex } else { Display::None };
+    let playing = outcome.0.is_none();
+    let computer = setup.computer().map(|(color, _)| color);
+
+    for (row, mut style) in rows.iter_mut() {
+        style.display = display(playing && computer != Some(row.0));
+    }
+
+    for (button, mut style) in buttons.iter_mut() {
+        style.display = match button {
+            ActionButton::OfferDraw(_) => display(offer.0.is_none()),
+            ActionButton::ClaimDraw => display(playing && history.draw_claim().is_some()),
+            _ => Display::Flex,
+        };
+    }
+
+    for mut style in prompt.iter_mut() {
+        style.display = display(playing && offer.0.is_some());
+    }
+
+    if let Some(offered_by) = offer.0 {
+        for mut text in texts.p0().iter_mut() {
+            text.sections[0].value = format!("{:?} offers a draw", offered_by);
+        }
+    }
+
+    let result = match (outcome.0, declined.0) {
+        (Some(end), _) => end.to_string(),
+        (None, Some(color)) if computer == Some(color) => {
+            "The computer declines the draw".to_string()
+        }
+        (None, Some(color)) => format!("{:?} declines the draw", color),
+        (None, None) => String::new(),
+    };
+    for mut text in texts.p1().iter_mut() {
+        text.sections[0].value = result.clone();
+    }
+}
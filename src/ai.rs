@@ -0,0 +1,335 @@
+//! Computer opponent: an alpha-beta search over material and piece placement,
+//! run in the background while the game goes on.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::{
+    log,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future;
+
+use crate::{
+    animation::AnimationSpeed,
+    clock::ChessClock,
+    history::GameHistory,
+    menu::GameSetup,
+    move_piece,
+    rules::{Move, MoveType, PieceColor, PieceType, Position, COLS, ROWS},
+    AppState, Board, GameOutcome,
+};
+
+/// Score of being checkmated, less the number of plies it takes so that
+/// faster mates are preferred.
+const MATE: i32 = 100_000;
+
+pub const MIN_STRENGTH: u8 = 1;
+pub const MAX_STRENGTH: u8 = 5;
+
+/// Search depth in plies for each strength.
+const DEPTH: [u32; MAX_STRENGTH as usize] = [1, 2, 2, 3, 3];
+
+/// Random amount up to which the score of each move is raised at each
+/// strength, so that weaker levels sometimes miss the best move.
+const NOISE: [i32; MAX_STRENGTH as usize] = [250, 120, 50, 15, 0];
+
+/// The computer accepts a draw when it is behind by more than this many
+/// centipawns.
+const DRAW_ACCEPTANCE: i32 = 150;
+
+/// Search for the move of the computer, while it is thinking.
+#[derive(Resource, Default)]
+struct Thinking(Option<Task<Option<Move>>>);
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Thinking>()
+            .add_system(stop_thinking.in_schedule(OnExit(AppState::InGame)))
+            .add_systems(
+                (start_thinking, play_computer_move)
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+/// Best move of the side to move at the given strength, between
+/// [`MIN_STRENGTH`] and [`MAX_STRENGTH`]. `seed` picks between moves the
+/// weaker levels consider about as good.
+pub fn best_move(position: &Position, strength: u8, seed: u64) -> Option<Move> {
+    let level = (strength.clamp(MIN_STRENGTH, MAX_STRENGTH) - 1) as usize;
+    let depth = DEPTH[level];
+    let noise = NOISE[level];
+    let mut rng = XorShift(seed | 1);
+
+    let mut best: Option<(Move, i32)> = None;
+    for m in ordered_moves(position) {
+        let mut after = position.clone();
+        after.make_move(m);
+
+        // Moves which cannot beat the best one even with the most noise added
+        // need not be searched exactly
+        let alpha = best.map_or(-MATE - 1, |(_, score)| score - noise);
+        let score = -negamax(&after, depth - 1, -MATE - 1, -alpha, 1);
+        let score = score + rng.below(noise);
+
+        if best.is_none_or(|(_, best)| score > best) {
+            best = Some((m, score));
+        }
+    }
+
+    best.map(|(m, _)| m)
+}
+
+/// Whether the computer playing `color` takes a draw offered in the
+/// position.
+pub fn accepts_draw(position: &Position, color: PieceColor) -> bool {
+    let score = evaluate(position);
+    let score = if position.side_to_move == color {
+        score
+    } else {
+        -score
+    };
+
+    score < -DRAW_ACCEPTANCE
+}
+
+/// Score of the position for the side to move, searching `depth` plies and
+/// then every capture.
+fn negamax(position: &Position, depth: u32, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+    if depth == 0 {
+        return quiescence(position, alpha, beta);
+    }
+
+    let moves = ordered_moves(position);
+    if moves.is_empty() {
+        return if position.is_in_check(position.side_to_move) {
+            -MATE + ply
+        } else {
+            0
+        };
+    }
+
+    for m in moves {
+        let mut after = position.clone();
+        after.make_move(m);
+
+        let score = -negamax(&after, depth - 1, -beta, -alpha, ply + 1);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+
+    alpha
+}
+
+/// Play out the captures of the position, so that a piece is not counted as
+/// won when it can be taken back.
+fn quiescence(position: &Position, mut alpha: i32, beta: i32) -> i32 {
+    let standing = evaluate(position);
+    if standing >= beta {
+        return beta;
+    }
+    alpha = alpha.max(standing);
+
+    for m in ordered_moves(position)
+        .into_iter()
+        .take_while(|m| m.move_type == MoveType::Capture)
+    {
+        let mut after = position.clone();
+        after.make_move(m);
+
+        let score = -quiescence(&after, -beta, -alpha);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+
+    alpha
+}
+
+/// Legal moves with captures first, taking the most valuable pieces first,
+/// so that the search can cut off the other moves sooner.
+fn ordered_moves(position: &Position) -> Vec<Move> {
+    let mut moves = position.legal_moves();
+    moves.sort_by_key(|m| match m.move_type {
+        MoveType::Capture => {
+            -(position
+                .piece_at(m.x, m.y)
+                .map_or(0, |piece| piece.piece_type.value()) as i32)
+        }
+        _ => 1,
+    });
+    moves
+}
+
+/// Material in centipawns and a bonus for knights, bishops and pawns near
+/// the center and for advanced pawns, for the side to move.
+fn evaluate(position: &Position) -> i32 {
+    let mut score = 0;
+
+    for piece in position.pieces() {
+        let center_distance = (piece.x as f32 - (ROWS - 1) as f32 / 2.).abs()
+            + (piece.y as f32 - (COLS - 1) as f32 / 2.).abs();
+        let centrality = (7. - center_distance) as i32;
+
+        let placement = match piece.piece_type {
+            PieceType::Knight | PieceType::Bishop => centrality * 4,
+            PieceType::Pawn => {
+                let advance = piece.x.abs_diff(piece.piece_color.pawns_row()) as i32;
+                advance * 6 + centrality
+            }
+            _ => 0,
+        };
+
+        let value = piece.piece_type.value() as i32 * 100 + placement;
+        if piece.piece_color == position.side_to_move {
+            score += value;
+        } else {
+            score -= value;
+        }
+    }
+
+    score
+}
+
+/// Small random number generator for the noise of the weaker levels.
+struct XorShift(u64);
+
+impl XorShift {
+    /// Random number in `0..bound`, or 0 if `bound` is not positive.
+    fn below(&mut self, bound: i32) -> i32 {
+        if bound <= 0 {
+            return 0;
+        }
+
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as i32
+    }
+}
+
+/// Start searching for a move when it is the computer's turn.
+fn start_thinking(
+    setup: Res<GameSetup>,
+    board: Res<Board>,
+    clock: Res<ChessClock>,
+    outcome: Res<GameOutcome>,
+    mut thinking: ResMut<Thinking>,
+) {
+    if thinking.0.is_some() || outcome.0.is_some() || clock.paused {
+        return;
+    }
+    let Some((color, strength)) = setup.computer() else {
+        return;
+    };
+    if board.position.side_to_move != color {
+        return;
+    }
+
+    let position = board.position.clone();
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+
+    thinking.0 = Some(
+        AsyncComputeTaskPool::get().spawn(async move { best_move(&position, strength, seed) }),
+    );
+}
+
+/// Play the move of the computer once it has been found.
+#[allow(clippy::too_many_arguments)]
+fn play_computer_move(
+    mut commands: Commands,
+    animation_speed: Res<AnimationSpeed>,
+    clock: Res<ChessClock>,
+    mut thinking: ResMut<Thinking>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<GameHistory>,
+    mut outcome: ResMut<GameOutcome>,
+) {
+    if clock.paused || outcome.0.is_some() {
+        return;
+    }
+    if !thinking.0.as_ref().is_some_and(Task::is_finished) {
+        return;
+    }
+    let Some(task) = thinking.0.take() else {
+        return;
+    };
+
+    if let Some(m) = future::block_on(task) {
+        log::info!(
+            "Computer plays {}",
+            crate::notation::san(&board.position, m)
+        );
+        move_piece(
+            &mut commands,
+            *animation_speed,
+            m,
+            &mut board,
+            &mut history,
+            &mut outcome,
+        );
+    }
+}
+
+/// Drop the search of a game which has been left.
+fn stop_thinking(mut thinking: ResMut<Thinking>) {
+    thinking.0 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{parse_fen, san};
+
+    fn best_san(fen: &str, strength: u8) -> String {
+        let position = parse_fen(fen).unwrap();
+        let m = best_move(&position, strength, 1).unwrap();
+        san(&position, m)
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        for strength in MIN_STRENGTH..=MAX_STRENGTH {
+            if NOISE[(strength - 1) as usize] < 100 {
+                assert_eq!(
+                    best_san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", strength),
+                    "Ra8#"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn takes_a_hanging_queen() {
+        assert_eq!(
+            best_san("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", MAX_STRENGTH),
+            "Rxd5"
+        );
+    }
+
+    #[test]
+    fn accepts_a_draw_only_when_behind() {
+        let position = parse_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+
+        assert!(accepts_draw(&position, PieceColor::Black));
+        assert!(!accepts_draw(&position, PieceColor::White));
+    }
+
+    #[test]
+    fn does_not_give_away_material() {
+        // The knight on e5 is defended by the pawn on d6
+        assert_ne!(
+            best_san("4k3/8/3p4/4n3/8/8/4Q3/4K3 w - - 0 1", MAX_STRENGTH),
+            "Qxe5"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Eased tweening of pieces between tiles and fading out of captured pieces.
+
+use bevy::{log, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{layout::BoardLayout, Piece};
+
+/// Z coordinate of a piece resting on its tile.
+pub const PIECE_Z: f32 = 1.0;
+
+/// Z coordinate of a piece while it travels, so it slides over the others.
+const MOVING_PIECE_Z: f32 = 2.0;
+
+/// How fast pieces travel between tiles. [`AnimationSpeed::Off`] snaps them
+/// into place and despawns captured pieces immediately.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnimationSpeed {
+    Off,
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+}
+
+impl AnimationSpeed {
+    /// Duration of a single move animation in seconds, `None` if animations
+    /// are disabled.
+    pub fn duration(self) -> Option<f32> {
+        match self {
+            Self::Off => None,
+            Self::Slow => Some(0.45),
+            Self::Normal => Some(0.25),
+            Self::Fast => Some(0.12),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Slow,
+            Self::Slow => Self::Normal,
+            Self::Normal => Self::Fast,
+            Self::Fast => Self::Off,
+        }
+    }
+}
+
+/// Moves the piece sprite from `from` to `to` over `duration` seconds.
+#[derive(Component, Debug)]
+pub struct PieceTween {
+    from: Vec3,
+    to: Vec3,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Fades the captured piece sprite out and despawns it afterwards.
+#[derive(Component, Debug)]
+pub struct FadeOut {
+    /// Square the piece was taken on.
+    square: (usize, usize),
+    elapsed: f32,
+    duration: f32,
+}
+
+impl FadeOut {
+    pub fn square(&self) -> (usize, usize) {
+        self.square
+    }
+}
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AnimationSpeed>()
+            .add_system(cycle_animation_speed)
+            .add_system(start_piece_tweens)
+            .add_system(animate_piece_tweens.after(start_piece_tweens))
+            .add_system(fade_out_captured);
+    }
+}
+
+/// Remove the piece captured on `square` from the game, fading its sprite
+/// out if animations are enabled.
+pub fn capture_piece(
+    commands: &mut Commands,
+    speed: AnimationSpeed,
+    entity: Entity,
+    square: (usize, usize),
+) {
+    match speed.duration() {
+        Some(duration) => {
+            commands.entity(entity).remove::<Piece>().insert(FadeOut {
+                square,
+                elapsed: 0.,
+                duration,
+            });
+        }
+        None => commands.entity(entity).despawn(),
+    }
+}
+
+/// Start a tween for every piece whose position on the board has changed.
+fn start_piece_tweens(
+    mut commands: Commands,
+    speed: Res<AnimationSpeed>,
+    layout: Res<BoardLayout>,
+    mut pieces: Query<(Entity, &Piece, &mut Transform), Changed<Piece>>,
+) {
+    for (entity, piece, mut transform) in pieces.iter_mut() {
+        let target = layout.square_translation(piece.x, piece.y).extend(PIECE_Z);
+
+        if transform.translation.truncate() == target.truncate() {
+            continue;
+        }
+
+        match speed.duration() {
+            Some(duration) => {
+                commands.entity(entity).insert(PieceTween {
+                    from: transform.translation.truncate().extend(MOVING_PIECE_Z),
+                    to: target,
+                    elapsed: 0.,
+                    duration,
+                });
+            }
+            None => {
+                commands.entity(entity).remove::<PieceTween>();
+                transform.translation = target;
+            }
+        }
+    }
+}
+
+fn animate_piece_tweens(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tweens: Query<(Entity, &mut PieceTween, &mut Transform)>,
+) {
+    for (entity, mut tween, mut transform) in tweens.iter_mut() {
+        tween.elapsed += time.delta_seconds();
+
+        let t = (tween.elapsed / tween.duration).min(1.);
+        if t >= 1. {
+            transform.translation = tween.to;
+            commands.entity(entity).remove::<PieceTween>();
+            continue;
+        }
+
+        let position = tween.from.lerp(tween.to, ease_in_out(t));
+        transform.translation = position.truncate().extend(MOVING_PIECE_Z);
+    }
+}
+
+fn fade_out_captured(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut fading: Query<(Entity, &mut FadeOut, &mut Sprite)>,
+) {
+    for (entity, mut fade, mut sprite) in fading.iter_mut() {
+        fade.elapsed += time.delta_seconds();
+
+        let t = (fade.elapsed / fade.duration).min(1.);
+        if t >= 1. {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        sprite.color.set_a(1. - t);
+    }
+}
+
+fn cycle_animation_speed(keys: Res<Input<KeyCode>>, mut speed: ResMut<AnimationSpeed>) {
+    if keys.just_pressed(KeyCode::A) {
+        *speed = speed.next();
+        log::info!("Animation speed: {:?}", *speed);
+    }
+}
+
+/// Cubic ease-in-out curve on `t` in `0..=1`.
+fn ease_in_out(t: f32) -> f32 {
+    if t < 0.5 {
+        4. * t * t * t
+    } else {
+        1. - (-2. * t + 2.).powi(3) / 2.
+    }
+}
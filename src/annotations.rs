@@ -0,0 +1,436 @@
+//! Arrows and circles drawn on the board with the right mouse button: a
+//! drag draws an arrow, a click circles a square. Holding `Shift`, `Alt` or
+//! both picks red, blue or yellow instead of green. Drawings belong to the
+//! position shown, a left click on the board wipes them, and they are kept
+//! in exported games as `[%cal]` and `[%csl]` comments.
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    sprite::MaterialMesh2dBundle,
+    utils::HashMap,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    history::{GameHistory, HistoryView},
+    layout::BoardLayout,
+    notation,
+    premove::cancel_premoves,
+    AppState, ChessConfig, GameEntity,
+};
+
+/// Height of the drawings, above the pieces standing on the board and below
+/// the ones being moved.
+const ANNOTATION_Z: f32 = 1.5;
+
+/// Number of segments of the circles.
+const CIRCLE_SEGMENTS: usize = 32;
+
+type Square = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnnotationColor {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl AnnotationColor {
+    const ALL: [Self; 4] = [Self::Green, Self::Red, Self::Blue, Self::Yellow];
+
+    fn from_modifiers(shift: bool, alt: bool) -> Self {
+        match (shift, alt) {
+            (false, false) => Self::Green,
+            (true, false) => Self::Red,
+            (false, true) => Self::Blue,
+            (true, true) => Self::Yellow,
+        }
+    }
+
+    /// Letter of the color in `[%cal]` and `[%csl]` comments.
+    fn letter(self) -> char {
+        match self {
+            Self::Green => 'G',
+            Self::Red => 'R',
+            Self::Blue => 'B',
+            Self::Yellow => 'Y',
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Green => Color::rgba(0.1, 0.6, 0.15, 0.8),
+            Self::Red => Color::rgba(0.75, 0.15, 0.15, 0.8),
+            Self::Blue => Color::rgba(0.1, 0.35, 0.75, 0.8),
+            Self::Yellow => Color::rgba(0.9, 0.7, 0.05, 0.8),
+        }
+    }
+}
+
+/// Circles and arrows drawn on one position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Drawing {
+    circles: Vec<(Square, AnnotationColor)>,
+    arrows: Vec<(Square, Square, AnnotationColor)>,
+}
+
+impl Drawing {
+    /// Circle `square`, or remove its circle if it already has one of that
+    /// color.
+    fn toggle_circle(&mut self, square: Square, color: AnnotationColor) {
+        let existing = self.circles.iter().position(|&(s, _)| s == square);
+        match existing.map(|i| self.circles.remove(i)) {
+            Some((_, old)) if old == color => {}
+            _ => self.circles.push((square, color)),
+        }
+    }
+
+    /// Draw an arrow from `from` to `to`, or remove it if it is already
+    /// drawn in that color.
+    fn toggle_arrow(&mut self, from: Square, to: Square, color: AnnotationColor) {
+        let existing = self
+            .arrows
+            .iter()
+            .position(|&(f, t, _)| f == from && t == to);
+        match existing.map(|i| self.arrows.remove(i)) {
+            Some((_, _, old)) if old == color => {}
+            _ => self.arrows.push((from, to, color)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.circles.is_empty() && self.arrows.is_empty()
+    }
+
+    /// Squares with a circle, whatever its color.
+    pub fn circled(&self) -> impl Iterator<Item = Square> + '_ {
+        self.circles.iter().map(|&(square, _)| square)
+    }
+
+    /// Squares the arrows go from and to, whatever their color.
+    pub fn arrows(&self) -> impl Iterator<Item = (Square, Square)> + '_ {
+        self.arrows.iter().map(|&(from, to, _)| (from, to))
+    }
+
+    /// The drawing as PGN comment commands, like `[%csl Gd4][%cal Re2e4]`.
+    pub fn comment(&self) -> Option<String> {
+        let mut comment = String::new();
+
+        if !self.circles.is_empty() {
+            let circles: Vec<String> = self
+                .circles
+                .iter()
+                .map(|&((x, y), color)| {
+                    format!("{}{}", color.letter(), notation::square_name(x, y))
+                })
+                .collect();
+            comment += &format!("[%csl {}]", circles.join(","));
+        }
+
+        if !self.arrows.is_empty() {
+            let arrows: Vec<String> = self
+                .arrows
+                .iter()
+                .map(|&(from, to, color)| {
+                    format!(
+                        "{}{}{}",
+                        color.letter(),
+                        notation::square_name(from.0, from.1),
+                        notation::square_name(to.0, to.1)
+                    )
+                })
+                .collect();
+            comment += &format!("[%cal {}]", arrows.join(","));
+        }
+
+        (!comment.is_empty()).then_some(comment)
+    }
+}
+
+/// Drawings of the positions of the game, by the number of moves played to
+/// reach them.
+#[derive(Resource, Debug, Default)]
+pub struct Annotations {
+    drawings: HashMap<usize, Drawing>,
+    /// Square the right button was pressed on.
+    drag_start: Option<Square>,
+}
+
+impl Annotations {
+    /// The drawing of the position after `ply` moves.
+    pub fn drawing(&self, ply: usize) -> Option<&Drawing> {
+        self.drawings.get(&ply)
+    }
+
+    /// The drawing of the position after `ply` moves as a PGN comment.
+    pub fn comment(&self, ply: usize) -> Option<String> {
+        self.drawings.get(&ply).and_then(Drawing::comment)
+    }
+
+    /// Follow the right button over `square` on the position after `ply`
+    /// moves: a press starts a drawing, and the release circles the square
+    /// or draws an arrow to it.
+    pub(crate) fn follow_right_button(
+        &mut self,
+        mouse: &Input<MouseButton>,
+        square: Option<Square>,
+        ply: usize,
+        color: AnnotationColor,
+    ) {
+        if mouse.just_pressed(MouseButton::Right) {
+            self.drag_start = square;
+        }
+
+        if mouse.just_released(MouseButton::Right) {
+            let (Some(from), Some(to)) = (self.drag_start.take(), square) else {
+                return;
+            };
+
+            let drawing = self.drawings.entry(ply).or_default();
+            if from == to {
+                drawing.toggle_circle(to, color);
+            } else {
+                drawing.toggle_arrow(from, to, color);
+            }
+            if drawing.is_empty() {
+                self.drawings.remove(&ply);
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AnnotationMaterials(HashMap<AnnotationColor, Handle<ColorMaterial>>);
+
+#[derive(Component)]
+struct AnnotationMesh;
+
+pub struct AnnotationsPlugin;
+
+impl Plugin for AnnotationsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Annotations>()
+            .add_startup_system(create_annotation_materials)
+            .add_system(clear_annotations.in_schedule(OnEnter(AppState::InGame)))
+            .add_systems(
+                (
+                    // After a right click cancelling premoves is taken
+                    draw_annotations
+                        .after(cancel_premoves)
+                        .run_if(mouse_input_enabled),
+                    show_annotations,
+                )
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn create_annotation_materials(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let handles = AnnotationColor::ALL
+        .into_iter()
+        .map(|color| (color, materials.add(color.color().into())))
+        .collect();
+    commands.insert_resource(AnnotationMaterials(handles));
+}
+
+fn clear_annotations(mut annotations: ResMut<Annotations>) {
+    *annotations = Annotations::default();
+}
+
+/// Square under the mouse, if it is over the board.
+fn hovered_square(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+    layout: &BoardLayout,
+) -> Option<Square> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, transform) = cameras.get_single().ok()?;
+    let position = camera.viewport_to_world_2d(transform, cursor)?;
+    layout.square_at(position)
+}
+
+fn mouse_input_enabled(config: Res<ChessConfig>) -> bool {
+    config.input.mouse
+}
+
+/// Draw with the right button, and wipe the drawing with a left click on the
+/// board. Right clicks cancelling premoves draw nothing.
+#[allow(clippy::too_many_arguments)]
+fn draw_annotations(
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    layout: Res<BoardLayout>,
+    history: Res<GameHistory>,
+    view: Res<HistoryView>,
+    mut annotations: ResMut<Annotations>,
+) {
+    let ply = view.shown_ply(&history);
+    let square = hovered_square(&windows, &cameras, &layout);
+
+    if mouse.just_pressed(MouseButton::Left)
+        && square.is_some()
+        && annotations.drawings.contains_key(&ply)
+    {
+        annotations.drawings.remove(&ply);
+    }
+
+    let color = AnnotationColor::from_modifiers(
+        keys.any_pressed([KeyCode::LShift, KeyCode::RShift]),
+        keys.any_pressed([KeyCode::LAlt, KeyCode::RAlt]),
+    );
+    annotations.follow_right_button(&mouse, square, ply, color);
+}
+
+/// Respawn the meshes of the drawing of the position shown whenever it or
+/// the board changes.
+#[allow(clippy::too_many_arguments)]
+fn show_annotations(
+    mut commands: Commands,
+    annotations: Res<Annotations>,
+    layout: Res<BoardLayout>,
+    history: Res<GameHistory>,
+    view: Res<HistoryView>,
+    materials: Res<AnnotationMaterials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    existing: Query<Entity, With<AnnotationMesh>>,
+) {
+    if !annotations.is_changed()
+        && !layout.is_changed()
+        && !history.is_changed()
+        && !view.is_changed()
+    {
+        return;
+    }
+
+    for entity in existing.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let Some(drawing) = annotations.drawings.get(&view.shown_ply(&history)) else {
+        return;
+    };
+
+    let mut spawn = |mesh: Mesh, translation: Vec2, angle: f32, color: AnnotationColor| {
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(mesh).into(),
+                material: materials.0[&color].clone(),
+                transform: Transform::from_translation(translation.extend(ANNOTATION_Z))
+                    .with_rotation(Quat::from_rotation_z(angle)),
+                ..default()
+            },
+            AnnotationMesh,
+            GameEntity,
+        ));
+    };
+
+    for &((x, y), color) in &drawing.circles {
+        let translation = layout.square_translation(x, y);
+        spawn(circle_mesh(layout.tile_size), translation, 0., color);
+    }
+
+    for &(from, to, color) in &drawing.arrows {
+        let start = layout.square_translation(from.0, from.1);
+        let direction = layout.square_translation(to.0, to.1) - start;
+        spawn(
+            arrow_mesh(direction.length(), layout.tile_size),
+            start,
+            direction.y.atan2(direction.x),
+            color,
+        );
+    }
+}
+
+/// Mesh made of `triangles`, flat in the XY plane.
+fn flat_mesh(positions: Vec<[f32; 3]>, triangles: Vec<u32>) -> Mesh {
+    let count = positions.len();
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; count]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 0.]; count]);
+    mesh.set_indices(Some(Indices::U32(triangles)));
+    mesh
+}
+
+/// Ring around the edge of a tile of `tile_size`, centered on the origin.
+fn circle_mesh(tile_size: f32) -> Mesh {
+    let (outer, inner) = (tile_size * 0.47, tile_size * 0.41);
+
+    let positions = (0..CIRCLE_SEGMENTS)
+        .flat_map(|i| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+            [
+                [cos * outer, sin * outer, 0.],
+                [cos * inner, sin * inner, 0.],
+            ]
+        })
+        .collect();
+
+    let n = CIRCLE_SEGMENTS as u32;
+    let triangles = (0..n)
+        .flat_map(|i| {
+            let (outer, inner) = (2 * i, 2 * i + 1);
+            let (next_outer, next_inner) = (2 * ((i + 1) % n), 2 * ((i + 1) % n) + 1);
+            [outer, next_outer, inner, inner, next_outer, next_inner]
+        })
+        .collect();
+
+    flat_mesh(positions, triangles)
+}
+
+/// Arrow pointing along the X axis from the origin to short of `length`,
+/// sized for tiles of `tile_size`.
+fn arrow_mesh(length: f32, tile_size: f32) -> Mesh {
+    let tip = length - tile_size * 0.15;
+    let shaft = tile_size * 0.09;
+    let head = tile_size * 0.25;
+    let neck = tip - tile_size * 0.4;
+
+    let positions = vec![
+        [0., -shaft, 0.],
+        [neck, -shaft, 0.],
+        [neck, shaft, 0.],
+        [0., shaft, 0.],
+        [neck, -head, 0.],
+        [tip, 0., 0.],
+        [neck, head, 0.],
+    ];
+    let triangles = vec![0, 1, 2, 0, 2, 3, 4, 5, 6];
+
+    flat_mesh(positions, triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_square;
+
+    #[test]
+    fn drawings_are_written_as_pgn_commands() {
+        let square = |name| parse_square(name).unwrap();
+        let mut drawing = Drawing::default();
+        assert_eq!(drawing.comment(), None);
+
+        drawing.toggle_circle(square("d4"), AnnotationColor::Green);
+        drawing.toggle_circle(square("e5"), AnnotationColor::Red);
+        drawing.toggle_arrow(square("e2"), square("e4"), AnnotationColor::Yellow);
+        assert_eq!(
+            drawing.comment().as_deref(),
+            Some("[%csl Gd4,Re5][%cal Ye2e4]")
+        );
+
+        // Drawing the same again removes it, in another color replaces it
+        drawing.toggle_circle(square("d4"), AnnotationColor::Green);
+        drawing.toggle_arrow(square("e2"), square("e4"), AnnotationColor::Blue);
+        assert_eq!(drawing.comment().as_deref(), Some("[%csl Re5][%cal Be2e4]"));
+    }
+}
@@ -0,0 +1,620 @@
+//! Terminal front end for playing over SSH or wherever the game window
+//! cannot open. The board is drawn with Unicode pieces on colored squares,
+//! next to the move list and the clocks. Moves are typed in SAN or
+//! coordinate notation and checked by the same rules as the game, against
+//! another person at the same keyboard or against the computer.
+
+use std::{
+    env,
+    io::{self, Write},
+    process,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bevy_chess::{
+    ai::{self, MAX_STRENGTH, MIN_STRENGTH},
+    clock::{self, Clock, TimeControl},
+    history::GameHistory,
+    is_dark_square, notation,
+    rules::{EndReason, GameResult, Move, Outcome, PieceColor, PieceType, Position, COLS, ROWS},
+};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    },
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+const USAGE: &str = "\
+Usage: chess-tui [options]
+
+Options:
+  --computer white|black  let the computer play a side
+  --level N               strength of the computer, from 1 to 5 (default 3)
+  --time CONTROL          time control as written in the game, e.g. \"3 +2s\"
+  --fen FEN               start from a position instead of the initial one
+  --flip                  draw the board with black at the bottom
+  --help                  show this message
+
+Type moves like Nf3, exd5, O-O, e8=Q or g1f3 and press Enter. The commands
+flip, draw, resign and quit are typed the same way; Esc also quits.";
+
+/// How often the screen is drawn while nothing happens, for the clocks.
+const FRAME: Duration = Duration::from_millis(100);
+
+/// Columns of the move list, right of the board.
+const MOVE_LIST_COLUMN: u16 = 32;
+
+const LIGHT_SQUARE: Color = Color::Rgb {
+    r: 240,
+    g: 217,
+    b: 181,
+};
+const DARK_SQUARE: Color = Color::Rgb {
+    r: 181,
+    g: 136,
+    b: 99,
+};
+const LIGHT_LAST_MOVE: Color = Color::Rgb {
+    r: 205,
+    g: 210,
+    b: 106,
+};
+const DARK_LAST_MOVE: Color = Color::Rgb {
+    r: 170,
+    g: 162,
+    b: 58,
+};
+const CHECK_SQUARE: Color = Color::Rgb {
+    r: 220,
+    g: 80,
+    b: 60,
+};
+const WHITE_PIECE: Color = Color::Rgb {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+const BLACK_PIECE: Color = Color::Rgb { r: 0, g: 0, b: 0 };
+const LOW_TIME: Duration = Duration::from_secs(10);
+
+/// How the game is played, from the command line.
+#[derive(Debug)]
+struct Options {
+    /// Side and strength of the computer, if it plays.
+    computer: Option<(PieceColor, u8)>,
+    time_control: Option<TimeControl>,
+    start: Position,
+    flipped: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut side = None;
+    let mut strength = (MIN_STRENGTH + MAX_STRENGTH) / 2;
+    let mut options = Options {
+        computer: None,
+        time_control: None,
+        start: Position::default(),
+        flipped: false,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--computer" => {
+                side = Some(match value()?.to_lowercase().as_str() {
+                    "white" => PieceColor::White,
+                    "black" => PieceColor::Black,
+                    other => return Err(format!("no side called {}", other)),
+                });
+            }
+            "--level" => {
+                strength = value()?
+                    .parse()
+                    .ok()
+                    .filter(|level| (MIN_STRENGTH..=MAX_STRENGTH).contains(level))
+                    .ok_or(format!(
+                        "the level goes from {} to {}",
+                        MIN_STRENGTH, MAX_STRENGTH
+                    ))?;
+            }
+            "--time" => {
+                let name = value()?;
+                let presets = TimeControl::presets();
+                options.time_control = presets
+                    .iter()
+                    .flatten()
+                    .find(|control| control.to_string() == name.trim())
+                    .cloned();
+                if options.time_control.is_none() && name != "untimed" {
+                    let names: Vec<_> = presets.iter().flatten().map(|c| c.to_string()).collect();
+                    return Err(format!(
+                        "no time control {}, use one of: untimed, {}",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            }
+            "--fen" => {
+                options.start = notation::parse_fen(&value()?).map_err(|err| err.to_string())?;
+            }
+            "--flip" => options.flipped = true,
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+
+    options.computer = side.map(|side| (side, strength));
+    // The human plays from the bottom
+    if let Some((computer, _)) = options.computer {
+        options.flipped = computer == PieceColor::White;
+    }
+    Ok(options)
+}
+
+struct Game {
+    history: GameHistory,
+    position: Position,
+    computer: Option<(PieceColor, u8)>,
+    clock: Option<Clock>,
+    outcome: Option<Outcome>,
+    /// The player who offered a draw which has not been answered yet.
+    draw_offer: Option<PieceColor>,
+    /// Search for the move of the computer, while it is thinking.
+    thinking: Option<JoinHandle<Option<Move>>>,
+    flipped: bool,
+    input: String,
+    message: String,
+    last_tick: Instant,
+    quit: bool,
+}
+
+impl Game {
+    fn new(options: Options) -> Self {
+        let start = options.start;
+        Self {
+            history: GameHistory {
+                start: start.clone(),
+                moves: Vec::new(),
+            },
+            clock: options
+                .time_control
+                .map(|control| Clock::new(control, start.side_to_move)),
+            position: start,
+            computer: options.computer,
+            outcome: None,
+            draw_offer: None,
+            thinking: None,
+            flipped: options.flipped,
+            input: String::new(),
+            message: String::new(),
+            last_tick: Instant::now(),
+            quit: false,
+        }
+    }
+
+    fn computer_to_move(&self) -> bool {
+        self.computer
+            .is_some_and(|(color, _)| color == self.position.side_to_move)
+    }
+
+    fn end(&mut self, result: GameResult, reason: EndReason) {
+        self.outcome = Some(Outcome { result, reason });
+    }
+
+    fn play(&mut self, m: Move) {
+        self.history.record(&self.position, m);
+        self.position.make_move(m);
+        if let Some(clock) = self.clock.as_mut() {
+            clock.complete_move();
+        }
+        if self.draw_offer == Some(self.position.side_to_move) {
+            self.draw_offer = None;
+        }
+        self.outcome = self.position.outcome();
+    }
+
+    /// Run the clock of the player to move. The clocks start after the
+    /// first move.
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+
+        if self.outcome.is_some() || self.history.moves.is_empty() {
+            return;
+        }
+        let Some(clock) = self.clock.as_mut() else {
+            return;
+        };
+        if clock.tick(elapsed) {
+            self.outcome = Some(clock::timeout_outcome(&self.position, clock.active()));
+        }
+    }
+
+    /// Start the search for the computer's move on its turn, and play the
+    /// move once it is found.
+    fn update_computer(&mut self) {
+        if self.outcome.is_some() {
+            self.thinking = None;
+            return;
+        }
+        let Some((_, strength)) = self.computer.filter(|_| self.computer_to_move()) else {
+            return;
+        };
+
+        match self.thinking.take() {
+            None => {
+                let position = self.position.clone();
+                let seed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_nanos() as u64);
+                self.thinking = Some(thread::spawn(move || {
+                    ai::best_move(&position, strength, seed)
+                }));
+            }
+            Some(task) if task.is_finished() => {
+                if let Ok(Some(m)) = task.join() {
+                    self.message = format!("Computer plays {}", notation::san(&self.position, m));
+                    self.play(m);
+                }
+            }
+            Some(task) => self.thinking = Some(task),
+        }
+    }
+
+    /// Play the typed move or run the typed command.
+    fn submit(&mut self) {
+        let text = std::mem::take(&mut self.input);
+        let side = self.position.side_to_move;
+        self.message.clear();
+
+        match text.trim() {
+            "" => {}
+            "quit" | "exit" => self.quit = true,
+            "flip" => self.flipped = !self.flipped,
+            _ if self.outcome.is_some() => self.message = "The game is over".to_string(),
+            _ if self.computer_to_move() => {
+                self.message = "Wait for the computer's move".to_string();
+            }
+            "resign" => self.end(GameResult::Win(side.opposite()), EndReason::Resignation),
+            "draw" => {
+                if let Some(reason) = self.history.draw_claim() {
+                    self.end(GameResult::Draw, reason);
+                } else if self.draw_offer == Some(side.opposite()) {
+                    self.end(GameResult::Draw, EndReason::Agreement);
+                } else if self.computer.is_some() {
+                    if ai::accepts_draw(&self.position, side.opposite()) {
+                        self.end(GameResult::Draw, EndReason::Agreement);
+                    } else {
+                        self.message = "The computer declines the draw".to_string();
+                    }
+                } else {
+                    self.draw_offer = Some(side);
+                    self.message = format!("{:?} offers a draw, type draw to accept", side);
+                }
+            }
+            text => match notation::parse_move(&self.position, text) {
+                Ok(m) => self.play(m),
+                Err(err) => self.message = format!("{}: {}", text, err),
+            },
+        }
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        match code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Enter => self.submit(),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => match c {
+                'c' => self.quit = true,
+                // Some terminals send a line feed for Enter
+                'j' | 'm' => self.submit(),
+                _ => {}
+            },
+            KeyCode::Char(c) if self.input.len() < 20 => self.input.push(c),
+            _ => {}
+        }
+    }
+
+    fn status(&self) -> String {
+        let side = self.position.side_to_move;
+        match self.outcome {
+            Some(outcome) => format!("Game over: {}", outcome),
+            None if self.computer_to_move() => "The computer is thinking...".to_string(),
+            None if self.position.is_in_check(side) => format!("{:?} to move, in check", side),
+            None => format!("{:?} to move", side),
+        }
+    }
+}
+
+fn piece_symbol(piece_type: PieceType) -> char {
+    // The filled symbols for both sides, told apart by their color
+    match piece_type {
+        PieceType::King => '♚',
+        PieceType::Queen => '♛',
+        PieceType::Rook => '♜',
+        PieceType::Bishop => '♝',
+        PieceType::Knight => '♞',
+        PieceType::Pawn => '♟',
+    }
+}
+
+/// Moves of the game as numbered lines, `1. e4 e5`, starting with `1...`
+/// when Black moved first.
+fn move_list(history: &GameHistory) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut number = history.start.fullmove_number;
+
+    for (i, entry) in history.moves.iter().enumerate() {
+        let white = history.position_at(i).side_to_move == PieceColor::White;
+        if white {
+            lines.push(format!("{:>3}. {:<8}", number, entry.san));
+        } else if i == 0 {
+            lines.push(format!("{:>3}... {:<8}", number, entry.san));
+        } else {
+            match lines.last_mut() {
+                Some(line) => line.push_str(&entry.san),
+                None => lines.push(entry.san.clone()),
+            }
+        }
+        if !white {
+            number += 1;
+        }
+    }
+
+    lines
+}
+
+fn clock_line(game: &Game, color: PieceColor) -> String {
+    let name = match game.computer {
+        Some((computer, strength)) if computer == color => {
+            format!("{:?} (computer, level {})", color, strength)
+        }
+        _ => format!("{:?}", color),
+    };
+    match &game.clock {
+        Some(clock) => format!(
+            "{:<28}{:>8}",
+            name,
+            clock::format_time(clock.remaining(color))
+        ),
+        None => name,
+    }
+}
+
+fn draw(out: &mut impl Write, game: &Game) -> io::Result<()> {
+    let (top, bottom) = if game.flipped {
+        (PieceColor::White, PieceColor::Black)
+    } else {
+        (PieceColor::Black, PieceColor::White)
+    };
+    let last_move = game.history.moves.last().map(|entry| entry.m);
+    let side = game.position.side_to_move;
+    let checked_king = game
+        .position
+        .king_square(side)
+        .filter(|_| game.position.is_in_check(side));
+    let clock_color = |color: PieceColor| match &game.clock {
+        Some(clock) if clock.remaining(color) < LOW_TIME => Color::Red,
+        Some(clock) if clock.active() == color && game.outcome.is_none() => Color::White,
+        _ => Color::Grey,
+    };
+
+    queue!(out, MoveTo(0, 0), Clear(ClearType::All))?;
+    queue!(
+        out,
+        SetForegroundColor(clock_color(top)),
+        Print(clock_line(game, top)),
+        ResetColor
+    )?;
+
+    for line in 0..ROWS {
+        let row = if game.flipped { line } else { ROWS - 1 - line };
+        queue!(
+            out,
+            MoveTo(0, line as u16 + 1),
+            Print(format!(" {} ", row + 1))
+        )?;
+
+        for col in 0..COLS {
+            let col = if game.flipped { COLS - 1 - col } else { col };
+            let dark = is_dark_square(row, col);
+            let moved = last_move.is_some_and(|m| m.from() == (row, col) || m.to() == (row, col));
+            let background = match (checked_king == Some((row, col)), moved, dark) {
+                (true, _, _) => CHECK_SQUARE,
+                (false, true, true) => DARK_LAST_MOVE,
+                (false, true, false) => LIGHT_LAST_MOVE,
+                (false, false, true) => DARK_SQUARE,
+                (false, false, false) => LIGHT_SQUARE,
+            };
+
+            queue!(out, SetBackgroundColor(background))?;
+            match game.position.piece_at(row, col) {
+                Some(piece) => {
+                    let color = match piece.piece_color {
+                        PieceColor::White => WHITE_PIECE,
+                        PieceColor::Black => BLACK_PIECE,
+                    };
+                    queue!(
+                        out,
+                        SetForegroundColor(color),
+                        Print(format!(" {} ", piece_symbol(piece.piece_type)))
+                    )?;
+                }
+                None => queue!(out, Print("   "))?,
+            }
+        }
+        queue!(out, ResetColor)?;
+    }
+
+    let files: String = (0..COLS)
+        .map(|col| if game.flipped { COLS - 1 - col } else { col })
+        .map(|col| format!(" {} ", (b'a' + col as u8) as char))
+        .collect();
+    queue!(
+        out,
+        MoveTo(0, ROWS as u16 + 1),
+        Print(format!("   {}", files))
+    )?;
+    queue!(
+        out,
+        MoveTo(0, ROWS as u16 + 2),
+        SetForegroundColor(clock_color(bottom)),
+        Print(clock_line(game, bottom)),
+        ResetColor
+    )?;
+
+    // The last moves which fit next to the board
+    let moves = move_list(&game.history);
+    let shown = ROWS + 1;
+    queue!(
+        out,
+        MoveTo(MOVE_LIST_COLUMN, 0),
+        SetAttribute(Attribute::Bold),
+        Print("Moves"),
+        SetAttribute(Attribute::Reset)
+    )?;
+    for (i, line) in moves
+        .iter()
+        .skip(moves.len().saturating_sub(shown))
+        .enumerate()
+    {
+        queue!(out, MoveTo(MOVE_LIST_COLUMN, i as u16 + 1), Print(line))?;
+    }
+
+    let status_row = ROWS as u16 + 4;
+    queue!(out, MoveTo(0, status_row), Print(game.status()))?;
+    queue!(
+        out,
+        MoveTo(0, status_row + 1),
+        SetForegroundColor(Color::Yellow),
+        Print(&game.message),
+        ResetColor
+    )?;
+    queue!(
+        out,
+        MoveTo(0, status_row + 3),
+        Print(format!("> {}", game.input)),
+        Show
+    )?;
+
+    out.flush()
+}
+
+/// Puts the terminal back as it was, even when the game panics.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn run(options: Options) -> io::Result<()> {
+    let mut game = Game::new(options);
+    let _terminal = RawTerminal::enter()?;
+    let mut out = io::stdout();
+
+    while !game.quit {
+        game.tick();
+        game.update_computer();
+        draw(&mut out, &game)?;
+
+        if !event::poll(FRAME)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Release {
+                game.handle_key(key.code, key.modifiers);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("chess-tui: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("chess-tui: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn options_are_read_from_the_command_line() {
+        let options = parse_args(args("--computer white --level 5 --flip")).unwrap();
+        assert_eq!(options.computer, Some((PieceColor::White, 5)));
+        assert!(options.flipped);
+
+        let options = parse_args(vec!["--time".to_string(), "3 +2s".to_string()]).unwrap();
+        assert_eq!(options.time_control.unwrap().to_string(), "3 +2s");
+        assert_eq!(options.computer, None);
+
+        assert!(parse_args(args("--level 9")).is_err());
+        assert!(parse_args(args("--time 7")).is_err());
+        assert!(parse_args(args("--fen")).is_err());
+    }
+
+    #[test]
+    fn typed_moves_follow_the_rules() {
+        let mut game = Game::new(parse_args(Vec::new()).unwrap());
+        for text in ["e4", "e5", "Ke3", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7"] {
+            game.input = text.to_string();
+            game.submit();
+        }
+
+        // The king cannot jump to e3, every other move is played
+        assert_eq!(game.history.moves.len(), 7);
+        assert_eq!(
+            game.outcome.map(|end| end.reason),
+            Some(EndReason::Checkmate)
+        );
+        assert_eq!(
+            move_list(&game.history),
+            vec![
+                "  1. e4      e5",
+                "  2. Qh5     Nc6",
+                "  3. Bc4     Nf6",
+                "  4. Qxf7#   "
+            ]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! Chess clocks for sudden death, Fischer increment, Bronstein and simple
+//! delay time controls, in one or more stages.
+
+use std::{fmt, time::Duration};
+
+use bevy::{log, prelude::*, sprite::Anchor};
+
+use crate::{
+    history::GameHistory,
+    layout::{BoardLayout, TRAY_HEIGHT},
+    rules::{EndReason, GameResult, Outcome, PieceColor, Position},
+    AppState, Board, GameEntity, GameOutcome, PauseState, FONT,
+};
+
+/// Below this much time left the clock is drawn in [`LOW_TIME_COLOR`] and
+/// shows tenths of seconds.
+const LOW_TIME: Duration = Duration::from_secs(10);
+
+const CLOCK_Z: f32 = 1.0;
+const CLOCK_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+const RUNNING_CLOCK_COLOR: Color = Color::rgb(0.95, 0.95, 0.95);
+const LOW_TIME_COLOR: Color = Color::rgb(0.95, 0.3, 0.2);
+
+/// Part of a time control: `moves` moves to be played in `seconds`, or the
+/// rest of the game if `moves` is `None`. The time of a stage is added to
+/// what is left from the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    pub moves: Option<u32>,
+    pub seconds: u64,
+}
+
+/// Time given back to a player for each move, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Increment {
+    None,
+    /// Added after every move.
+    Fischer(u64),
+    /// Added after every move, but never more than the time the move took.
+    Bronstein(u64),
+    /// The clock only starts running after this long on every move.
+    Delay(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub stages: Vec<Stage>,
+    pub increment: Increment,
+}
+
+impl TimeControl {
+    pub fn sudden_death(minutes: u64) -> Self {
+        Self {
+            stages: vec![Stage {
+                moves: None,
+                seconds: minutes * 60,
+            }],
+            increment: Increment::None,
+        }
+    }
+
+    pub fn with_increment(self, increment: Increment) -> Self {
+        Self { increment, ..self }
+    }
+
+    /// Time controls to choose from, `None` being an untimed game.
+    pub fn presets() -> Vec<Option<Self>> {
+        vec![
+            None,
+            Some(Self::sudden_death(1)),
+            Some(Self::sudden_death(3).with_increment(Increment::Fischer(2))),
+            Some(Self::sudden_death(5).with_increment(Increment::Delay(3))),
+            Some(Self::sudden_death(10)),
+            Some(Self::sudden_death(15).with_increment(Increment::Bronstein(10))),
+            Some(
+                Self {
+                    stages: vec![
+                        Stage {
+                            moves: Some(40),
+                            seconds: 90 * 60,
+                        },
+                        Stage {
+                            moves: None,
+                            seconds: 30 * 60,
+                        },
+                    ],
+                    increment: Increment::None,
+                }
+                .with_increment(Increment::Fischer(30)),
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for TimeControl {
+    /// Written like `40/90 30 +30s`: minutes per stage, prefixed by the number
+    /// of moves for all but the last stage, followed by the increment.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{}/", moves)?;
+            }
+            write!(f, "{}", stage.seconds / 60)?;
+        }
+
+        match self.increment {
+            Increment::None => Ok(()),
+            Increment::Fischer(seconds) => write!(f, " +{}s", seconds),
+            Increment::Bronstein(seconds) => write!(f, " +{}s Bronstein", seconds),
+            Increment::Delay(seconds) => write!(f, " {}s delay", seconds),
+        }
+    }
+}
+
+/// Time left for both players under a time control.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    stage: [usize; 2],
+    moves_in_stage: [u32; 2],
+    active: PieceColor,
+    /// Time the active player has spent on the current move.
+    spent: Duration,
+}
+
+impl Clock {
+    /// Clocks for a game in which `first` makes the first move.
+    pub fn new(control: TimeControl, first: PieceColor) -> Self {
+        let time = Duration::from_secs(control.stages.first().map_or(0, |s| s.seconds));
+
+        Self {
+            control,
+            remaining: [time; 2],
+            stage: [0; 2],
+            moves_in_stage: [0; 2],
+            active: first,
+            spent: Duration::ZERO,
+        }
+    }
+
+    pub fn remaining(&self, color: PieceColor) -> Duration {
+        self.remaining[index(color)]
+    }
+
+    /// The player whose clock is running.
+    pub fn active(&self) -> PieceColor {
+        self.active
+    }
+
+    /// Run the clock of the active player for `elapsed`. Returns true if the
+    /// player has run out of time.
+    pub fn tick(&mut self, elapsed: Duration) -> bool {
+        let used = match self.control.increment {
+            Increment::Delay(delay) => {
+                let delay = Duration::from_secs(delay);
+                (self.spent + elapsed).saturating_sub(delay) - self.spent.saturating_sub(delay)
+            }
+            _ => elapsed,
+        };
+        self.spent += elapsed;
+
+        let remaining = &mut self.remaining[index(self.active)];
+        *remaining = remaining.saturating_sub(used);
+        remaining.is_zero()
+    }
+
+    /// The active player has made a move: add the increment, start the next
+    /// stage if the player has played its moves, and switch to the other
+    /// clock.
+    pub fn complete_move(&mut self) {
+        let i = index(self.active);
+
+        self.remaining[i] += match self.control.increment {
+            Increment::Fischer(seconds) => Duration::from_secs(seconds),
+            Increment::Bronstein(seconds) => self.spent.min(Duration::from_secs(seconds)),
+            Increment::None | Increment::Delay(_) => Duration::ZERO,
+        };
+
+        self.moves_in_stage[i] += 1;
+        let stage = self.control.stages[self.stage[i]];
+        if stage.moves == Some(self.moves_in_stage[i]) {
+            if let Some(next) = self.control.stages.get(self.stage[i] + 1) {
+                self.stage[i] += 1;
+                self.moves_in_stage[i] = 0;
+                self.remaining[i] += Duration::from_secs(next.seconds);
+            }
+        }
+
+        self.active = self.active.opposite();
+        self.spent = Duration::ZERO;
+    }
+}
+
+const fn index(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+    }
+}
+
+/// Clocks of the game, `None` in untimed games. The clocks start after the
+/// first move and are stopped while paused or once the game is over.
+#[derive(Resource, Debug, Default)]
+pub struct ChessClock {
+    pub clock: Option<Clock>,
+    pub paused: bool,
+    /// Number of moves of the game the clocks have been switched for.
+    plies: usize,
+}
+
+impl ChessClock {
+    pub fn new(clock: Option<Clock>) -> Self {
+        Self { clock, ..default() }
+    }
+}
+
+#[derive(Component)]
+struct ClockText {
+    color: PieceColor,
+}
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChessClock>()
+            .add_system(spawn_clock_texts.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(pause_clock.in_schedule(OnEnter(PauseState::Paused)))
+            .add_system(resume_clock.in_schedule(OnExit(PauseState::Paused)))
+            .add_systems(
+                (switch_clock, tick_clock, update_clock_texts)
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn spawn_clock_texts(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    for color in [PieceColor::White, PieceColor::Black] {
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: TRAY_HEIGHT,
+                        color: CLOCK_COLOR,
+                    },
+                ),
+                text_anchor: Anchor::CenterRight,
+                ..default()
+            },
+            ClockText { color },
+            GameEntity,
+        ));
+    }
+}
+
+fn pause_clock(mut clock: ResMut<ChessClock>) {
+    clock.paused = true;
+}
+
+fn resume_clock(mut clock: ResMut<ChessClock>) {
+    clock.paused = false;
+}
+
+/// Switch the clocks for every move added to the game.
+fn switch_clock(history: Res<GameHistory>, mut clock: ResMut<ChessClock>) {
+    if !history.is_changed() {
+        return;
+    }
+
+    let clock = clock.as_mut();
+    let plies = history.moves.len();
+    if let Some(running) = clock.clock.as_mut() {
+        for _ in clock.plies..plies {
+            running.complete_move();
+        }
+    }
+    clock.plies = plies;
+}
+
+/// Run the clock of the player to move, ending the game when it runs out.
+fn tick_clock(
+    time: Res<Time>,
+    board: Res<Board>,
+    mut clock: ResMut<ChessClock>,
+    mut outcome: ResMut<GameOutcome>,
+) {
+    if clock.paused || clock.plies == 0 || outcome.0.is_some() {
+        return;
+    }
+    let Some(running) = clock.clock.as_mut() else {
+        return;
+    };
+
+    if running.tick(time.delta()) {
+        let flagged = running.active();
+        outcome.0 = Some(timeout_outcome(&board.position, flagged));
+        log::info!("{:?} ran out of time: {:?}", flagged, outcome.0);
+    }
+}
+
+/// End of a game in which `flagged` ran out of time. The opponent wins on
+/// time unless they could not have checkmated.
+pub fn timeout_outcome(position: &Position, flagged: PieceColor) -> Outcome {
+    let opponent = flagged.opposite();
+
+    if position.has_insufficient_material(opponent) {
+        Outcome {
+            result: GameResult::Draw,
+            reason: EndReason::TimeoutVsInsufficientMaterial,
+        }
+    } else {
+        Outcome {
+            result: GameResult::Win(opponent),
+            reason: EndReason::Timeout,
+        }
+    }
+}
+
+fn update_clock_texts(
+    clock: Res<ChessClock>,
+    layout: Res<BoardLayout>,
+    outcome: Res<GameOutcome>,
+    mut texts: Query<(&ClockText, &mut Text, &mut Transform, &mut Visibility)>,
+) {
+    for (clock_text, mut text, mut transform, mut visibility) in texts.iter_mut() {
+        let Some(running) = &clock.clock else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+
+        // White plays from the bottom unless the board is flipped
+        let top = (clock_text.color == PieceColor::White) == layout.flipped;
+        let x = layout.center.x + layout.board_size() / 2.;
+        transform.translation = Vec3::new(x, layout.tray_y(top), CLOCK_Z);
+
+        let remaining = running.remaining(clock_text.color);
+        let is_running = running.active() == clock_text.color
+            && clock.plies > 0
+            && !clock.paused
+            && outcome.0.is_none();
+
+        let section = &mut text.sections[0];
+        section.value = format_time(remaining);
+        if clock.paused {
+            section.value.push_str(" (paused)");
+        }
+        section.style.color = if remaining < LOW_TIME {
+            LOW_TIME_COLOR
+        } else if is_running {
+            RUNNING_CLOCK_COLOR
+        } else {
+            CLOCK_COLOR
+        };
+    }
+}
+
+/// Time left as `h:mm:ss`, `m:ss` or, when running low, `s.t`.
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+
+    if time < LOW_TIME {
+        format!("{}.{}", seconds, time.subsec_millis() / 100)
+    } else if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn fischer_increment_is_added_after_each_move() {
+        let mut clock = Clock::new(
+            TimeControl::sudden_death(3).with_increment(Increment::Fischer(2)),
+            PieceColor::White,
+        );
+
+        clock.tick(10 * SECOND);
+        clock.complete_move();
+
+        assert_eq!(clock.remaining(PieceColor::White), 172 * SECOND);
+        assert_eq!(clock.active(), PieceColor::Black);
+    }
+
+    #[test]
+    fn bronstein_gives_back_at_most_the_time_used() {
+        let mut clock = Clock::new(
+            TimeControl::sudden_death(1).with_increment(Increment::Bronstein(5)),
+            PieceColor::White,
+        );
+
+        clock.tick(3 * SECOND);
+        clock.complete_move();
+        assert_eq!(clock.remaining(PieceColor::White), 60 * SECOND);
+
+        clock.tick(8 * SECOND);
+        clock.complete_move();
+        assert_eq!(clock.remaining(PieceColor::Black), 57 * SECOND);
+    }
+
+    #[test]
+    fn delay_runs_before_the_clock() {
+        let mut clock = Clock::new(
+            TimeControl::sudden_death(1).with_increment(Increment::Delay(3)),
+            PieceColor::White,
+        );
+
+        clock.tick(2 * SECOND);
+        assert_eq!(clock.remaining(PieceColor::White), 60 * SECOND);
+
+        clock.tick(2 * SECOND);
+        assert_eq!(clock.remaining(PieceColor::White), 59 * SECOND);
+    }
+
+    #[test]
+    fn next_stage_time_is_added_after_its_moves() {
+        let control = TimeControl {
+            stages: vec![
+                Stage {
+                    moves: Some(2),
+                    seconds: 60,
+                },
+                Stage {
+                    moves: None,
+                    seconds: 30,
+                },
+            ],
+            increment: Increment::None,
+        };
+        let mut clock = Clock::new(control, PieceColor::White);
+        for _ in 0..4 {
+            clock.complete_move();
+        }
+
+        assert_eq!(clock.remaining(PieceColor::White), 90 * SECOND);
+        assert_eq!(clock.remaining(PieceColor::Black), 90 * SECOND);
+    }
+
+    #[test]
+    fn multi_stage_control_is_written_per_stage() {
+        let control = TimeControl::presets().pop().flatten().unwrap();
+
+        assert_eq!(control.to_string(), "40/90 30 +30s");
+    }
+
+    #[test]
+    fn running_out_of_time_flags() {
+        let mut clock = Clock::new(TimeControl::sudden_death(1), PieceColor::White);
+
+        assert!(!clock.tick(59 * SECOND));
+        assert!(clock.tick(2 * SECOND));
+        assert_eq!(clock.remaining(PieceColor::White), Duration::ZERO);
+    }
+
+    #[test]
+    fn black_to_move_starts_the_black_clock() {
+        let mut clock = Clock::new(TimeControl::sudden_death(1), PieceColor::Black);
+
+        clock.tick(5 * SECOND);
+        assert_eq!(clock.remaining(PieceColor::Black), 55 * SECOND);
+        assert_eq!(clock.remaining(PieceColor::White), 60 * SECOND);
+    }
+}
@@ -0,0 +1,166 @@
+//! A cursor moved square by square with the arrow keys or a gamepad d-pad,
+//! so that the game can be played without a mouse. `Space` or the south
+//! face button chooses the square under the cursor as a click would,
+//! `Backspace` or the east face button drops the selection. `Left` and
+//! `Right` step through the moves played until the cursor is shown.
+
+use bevy::prelude::*;
+
+use crate::{
+    highlight::{HighlightLayer, Highlights},
+    layout::BoardLayout,
+    AppState, Board, SelectSquare, SelectedPiece, SelectedTile,
+};
+
+/// Square under the cursor, shown once the cursor has been used and hidden
+/// again when the mouse is.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct BoardCursor {
+    square: Option<(usize, usize)>,
+    visible: bool,
+}
+
+impl BoardCursor {
+    /// Whether the arrow keys move the cursor, rather than `Left` and
+    /// `Right` stepping through the history.
+    pub(crate) fn is_shown(&self) -> bool {
+        self.visible
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CursorAction {
+    Move { up: i32, right: i32 },
+    Select,
+    Cancel,
+}
+
+const UP: CursorAction = CursorAction::Move { up: 1, right: 0 };
+const DOWN: CursorAction = CursorAction::Move { up: -1, right: 0 };
+const LEFT: CursorAction = CursorAction::Move { up: 0, right: -1 };
+const RIGHT: CursorAction = CursorAction::Move { up: 0, right: 1 };
+
+const KEY_ACTIONS: [(KeyCode, CursorAction); 6] = [
+    (KeyCode::Up, UP),
+    (KeyCode::Down, DOWN),
+    (KeyCode::Left, LEFT),
+    (KeyCode::Right, RIGHT),
+    (KeyCode::Space, CursorAction::Select),
+    (KeyCode::Back, CursorAction::Cancel),
+];
+
+const GAMEPAD_ACTIONS: [(GamepadButtonType, CursorAction); 6] = [
+    (GamepadButtonType::DPadUp, UP),
+    (GamepadButtonType::DPadDown, DOWN),
+    (GamepadButtonType::DPadLeft, LEFT),
+    (GamepadButtonType::DPadRight, RIGHT),
+    (GamepadButtonType::South, CursorAction::Select),
+    (GamepadButtonType::East, CursorAction::Cancel),
+];
+
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoardCursor>()
+            .add_system(reset_cursor.in_schedule(OnEnter(AppState::InGame)))
+            .add_systems(
+                (move_cursor, hide_cursor_on_click, show_cursor)
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn reset_cursor(mut cursor: ResMut<BoardCursor>) {
+    *cursor = BoardCursor::default();
+}
+
+/// Move the cursor or act on its square. The first use only shows the
+/// cursor, on the king of the side to move.
+#[allow(clippy::too_many_arguments)]
+fn move_cursor(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    board: Res<Board>,
+    layout: Res<BoardLayout>,
+    mut cursor: ResMut<BoardCursor>,
+    mut selections: EventWriter<SelectSquare>,
+    mut selected_tile: ResMut<SelectedTile>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut highlights: ResMut<Highlights>,
+) {
+    let history_keys = [KeyCode::Left, KeyCode::Right];
+    let key_actions = KEY_ACTIONS
+        .iter()
+        .filter(|(key, _)| keys.just_pressed(*key))
+        .filter(|(key, _)| cursor.visible || !history_keys.contains(key))
+        .map(|&(_, action)| action);
+    let buttons = &*buttons;
+    let gamepad_actions = gamepads.iter().flat_map(|gamepad| {
+        GAMEPAD_ACTIONS
+            .iter()
+            .filter(move |(button, _)| buttons.just_pressed(GamepadButton::new(gamepad, *button)))
+            .map(|&(_, action)| action)
+    });
+    let actions: Vec<CursorAction> = key_actions.chain(gamepad_actions).collect();
+    if actions.is_empty() {
+        return;
+    }
+
+    let Some((x, y)) = cursor.square.filter(|_| cursor.visible) else {
+        let side = board.position.side_to_move;
+        cursor.square = cursor
+            .square
+            .or_else(|| board.position.king_square(side))
+            .or(Some((0, 0)));
+        cursor.visible = true;
+        return;
+    };
+
+    for action in actions {
+        match action {
+            CursorAction::Move { up, right } => {
+                // The arrows follow the board as drawn
+                let (up, right) = if layout.flipped {
+                    (-up, -right)
+                } else {
+                    (up, right)
+                };
+                let x = (x as i32 + up).clamp(0, Board::ROWS as i32 - 1) as usize;
+                let y = (y as i32 + right).clamp(0, Board::COLS as i32 - 1) as usize;
+                cursor.square = Some((x, y));
+            }
+            CursorAction::Select => selections.send(SelectSquare {
+                x,
+                y,
+                promotion: None,
+            }),
+            CursorAction::Cancel => {
+                selected_tile.tile = None;
+                selected_piece.piece = None;
+                highlights.clear(HighlightLayer::Selection);
+                highlights.clear(HighlightLayer::QuietMove);
+                highlights.clear(HighlightLayer::Capture);
+            }
+        }
+    }
+}
+
+fn hide_cursor_on_click(mouse: Res<Input<MouseButton>>, mut cursor: ResMut<BoardCursor>) {
+    if mouse.just_pressed(MouseButton::Left) && cursor.visible {
+        cursor.visible = false;
+    }
+}
+
+fn show_cursor(cursor: Res<BoardCursor>, mut highlights: ResMut<Highlights>) {
+    if !cursor.is_changed() {
+        return;
+    }
+
+    match cursor.square.filter(|_| cursor.visible) {
+        Some(square) => highlights.set(HighlightLayer::Cursor, [square]),
+        None => highlights.clear(HighlightLayer::Cursor),
+    }
+}
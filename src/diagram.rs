@@ -0,0 +1,489 @@
+//! Diagrams of a position to paste in documents and chats: an SVG image with
+//! the last move, highlighted squares and arrows, or lines of text with
+//! ASCII letters or Unicode pieces. `D` saves the position shown in the
+//! game, and `bevy-chess diagram` draws any FEN without opening a window.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{log, prelude::*};
+
+use crate::{
+    annotations::Annotations,
+    history::{self, GameHistory, HistoryView},
+    is_dark_square, notation,
+    rules::{Piece, PieceColor, PieceType, Position, COLS, ROWS},
+    AppState, BoardLayout,
+};
+
+type Square = (usize, usize);
+
+/// Width and height of a square in SVG diagrams.
+const SQUARE_SIZE: f32 = 45.;
+
+/// Room left of and below the board for the coordinates.
+const COORDINATES_MARGIN: f32 = 20.;
+
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const LAST_MOVE: &str = "#cdd26a";
+const HIGHLIGHT: &str = "#d9423d";
+const ARROW: &str = "#1a9926";
+
+pub const USAGE: &str = "\
+Usage: bevy-chess diagram [options]
+
+Options:
+  --fen FEN              position to draw (default: the initial position)
+  --format FORMAT        svg, ascii or unicode (default: svg)
+  --flip                 draw the board with black at the bottom
+  --no-coordinates       leave out the files and ranks
+  --last-move MOVE       mark the squares of a move, like e2e4
+  --highlight SQUARES    mark squares, like e4,d5
+  --arrow MOVES          draw arrows, like g1f3,f1c4
+  --output FILE          write the diagram to FILE instead of printing it";
+
+/// What is drawn along with the pieces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagramOptions {
+    /// Draw the board with black at the bottom.
+    pub flipped: bool,
+    /// Show the files and ranks next to the board.
+    pub coordinates: bool,
+    /// Squares the last move was played from and to, SVG only.
+    pub last_move: Option<(Square, Square)>,
+    /// Squares to mark, SVG only.
+    pub highlights: Vec<Square>,
+    /// Arrows from one square to another, SVG only.
+    pub arrows: Vec<(Square, Square)>,
+}
+
+impl Default for DiagramOptions {
+    fn default() -> Self {
+        Self {
+            flipped: false,
+            coordinates: true,
+            last_move: None,
+            highlights: Vec::new(),
+            arrows: Vec::new(),
+        }
+    }
+}
+
+impl DiagramOptions {
+    /// Column and row from the top left corner the square is drawn at.
+    fn cell(&self, (x, y): Square) -> (usize, usize) {
+        if self.flipped {
+            (COLS - 1 - y, x)
+        } else {
+            (y, ROWS - 1 - x)
+        }
+    }
+}
+
+/// Characters the pieces are written with in text diagrams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbols {
+    /// FEN letters, upper case for white, and `.` for empty squares.
+    Ascii,
+    /// Chess symbols, and `·` for empty squares.
+    Unicode,
+}
+
+fn unicode_symbol(piece: Piece) -> char {
+    match (piece.piece_color, piece.piece_type) {
+        (PieceColor::White, PieceType::King) => '♔',
+        (PieceColor::White, PieceType::Queen) => '♕',
+        (PieceColor::White, PieceType::Rook) => '♖',
+        (PieceColor::White, PieceType::Bishop) => '♗',
+        (PieceColor::White, PieceType::Knight) => '♘',
+        (PieceColor::White, PieceType::Pawn) => '♙',
+        (PieceColor::Black, PieceType::King) => '♚',
+        (PieceColor::Black, PieceType::Queen) => '♛',
+        (PieceColor::Black, PieceType::Rook) => '♜',
+        (PieceColor::Black, PieceType::Bishop) => '♝',
+        (PieceColor::Black, PieceType::Knight) => '♞',
+        (PieceColor::Black, PieceType::Pawn) => '♟',
+    }
+}
+
+/// The position as lines of text, one per rank.
+pub fn text(position: &Position, options: &DiagramOptions, symbols: Symbols) -> String {
+    let mut text = String::new();
+
+    for row in 0..ROWS {
+        if options.coordinates {
+            let rank = if options.flipped { row + 1 } else { ROWS - row };
+            write!(text, "{} ", rank).unwrap();
+        }
+
+        let squares: Vec<String> = (0..COLS)
+            .map(|col| {
+                let square = if options.flipped {
+                    (row, COLS - 1 - col)
+                } else {
+                    (ROWS - 1 - row, col)
+                };
+                let piece = position.piece_at(square.0, square.1);
+                let symbol = match (symbols, piece) {
+                    (Symbols::Ascii, Some(piece)) => {
+                        let letter = notation::piece_letter(piece.piece_type);
+                        match piece.piece_color {
+                            PieceColor::White => letter,
+                            PieceColor::Black => letter.to_ascii_lowercase(),
+                        }
+                    }
+                    (Symbols::Ascii, None) => '.',
+                    (Symbols::Unicode, Some(piece)) => unicode_symbol(piece),
+                    (Symbols::Unicode, None) => '·',
+                };
+                symbol.to_string()
+            })
+            .collect();
+        text += &squares.join(" ");
+        text.push('\n');
+    }
+
+    if options.coordinates {
+        let files: Vec<String> = (0..COLS)
+            .map(|col| if options.flipped { COLS - 1 - col } else { col })
+            .map(|col| notation::square_name(0, col)[..1].to_string())
+            .collect();
+        writeln!(text, "  {}", files.join(" ")).unwrap();
+    }
+
+    text
+}
+
+/// The position as an SVG image.
+pub fn svg(position: &Position, options: &DiagramOptions) -> String {
+    let margin = if options.coordinates {
+        COORDINATES_MARGIN
+    } else {
+        0.
+    };
+    let board_size = SQUARE_SIZE * COLS as f32;
+    let (width, height) = (margin + board_size, board_size + margin);
+    let corner = |square| {
+        let (col, row) = options.cell(square);
+        (margin + col as f32 * SQUARE_SIZE, row as f32 * SQUARE_SIZE)
+    };
+    let center = |square| {
+        let (x, y) = corner(square);
+        (x + SQUARE_SIZE / 2., y + SQUARE_SIZE / 2.)
+    };
+    let square_rect = |svg: &mut String, square, fill: &str, opacity: f32| {
+        let (x, y) = corner(square);
+        writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="{}"/>"#,
+            x, y, SQUARE_SIZE, SQUARE_SIZE, fill, opacity
+        )
+        .unwrap();
+    };
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}" width="{w}" height="{h}" font-family="sans-serif">"#,
+        w = width,
+        h = height
+    )
+    .unwrap();
+
+    for x in 0..ROWS {
+        for y in 0..COLS {
+            let fill = if is_dark_square(x, y) {
+                DARK_SQUARE
+            } else {
+                LIGHT_SQUARE
+            };
+            square_rect(&mut svg, (x, y), fill, 1.);
+        }
+    }
+
+    if let Some((from, to)) = options.last_move {
+        square_rect(&mut svg, from, LAST_MOVE, 0.8);
+        square_rect(&mut svg, to, LAST_MOVE, 0.8);
+    }
+    for &square in &options.highlights {
+        square_rect(&mut svg, square, HIGHLIGHT, 0.6);
+    }
+
+    if options.coordinates {
+        for i in 0..COLS {
+            let (x, _) = center((0, i));
+            let file = &notation::square_name(0, i)[..1];
+            writeln!(
+                svg,
+                r##"<text x="{}" y="{}" font-size="12" text-anchor="middle" fill="#555">{}</text>"##,
+                x,
+                board_size + 15.,
+                file
+            )
+            .unwrap();
+        }
+        for i in 0..ROWS {
+            let (_, y) = center((i, 0));
+            writeln!(
+                svg,
+                r##"<text x="{}" y="{}" font-size="12" text-anchor="middle" dominant-baseline="central" fill="#555">{}</text>"##,
+                COORDINATES_MARGIN / 2.,
+                y,
+                i + 1
+            )
+            .unwrap();
+        }
+    }
+
+    for piece in position.pieces() {
+        let (x, y) = center((piece.x, piece.y));
+        // The filled symbols for both sides, told apart by their fill
+        let symbol = unicode_symbol(Piece {
+            piece_color: PieceColor::Black,
+            ..piece
+        });
+        let fill = match piece.piece_color {
+            PieceColor::White => "#fff",
+            PieceColor::Black => "#000",
+        };
+        writeln!(
+            svg,
+            r##"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central" fill="{}" stroke="#000" stroke-width="1">{}</text>"##,
+            x,
+            y,
+            SQUARE_SIZE * 0.8,
+            fill,
+            symbol
+        )
+        .unwrap();
+    }
+
+    let stroke = SQUARE_SIZE * 0.2;
+    for &(from, to) in &options.arrows {
+        let (start, end) = (Vec2::from(center(from)), Vec2::from(center(to)));
+        // Stop the line where the head starts, so that its tip is on the
+        // center of the square
+        let head = 2. * stroke;
+        let end = end - (end - start).normalize_or_zero() * head;
+        writeln!(
+            svg,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-opacity="0.8" marker-end="url(#arrowhead)"/>"#,
+            start.x, start.y, end.x, end.y, ARROW, stroke
+        )
+        .unwrap();
+    }
+    if !options.arrows.is_empty() {
+        writeln!(
+            svg,
+            r#"<defs><marker id="arrowhead" viewBox="0 0 10 10" refX="0" refY="5" markerWidth="2" markerHeight="2" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="{}" fill-opacity="0.8"/></marker></defs>"#,
+            ARROW
+        )
+        .unwrap();
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+/// Format of a diagram written by the `diagram` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Svg,
+    Text(Symbols),
+}
+
+#[derive(Debug)]
+struct Command {
+    position: Position,
+    options: DiagramOptions,
+    format: Format,
+    output: Option<PathBuf>,
+}
+
+fn parse_move_squares(text: &str) -> Result<(Square, Square), String> {
+    let squares = text
+        .get(..2)
+        .zip(text.get(2..))
+        .and_then(|(from, to)| notation::parse_square(from).zip(notation::parse_square(to)));
+    squares.ok_or(format!("{} is not a move like e2e4", text))
+}
+
+fn parse_command(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut command = Command {
+        position: Position::default(),
+        options: DiagramOptions::default(),
+        format: Format::Svg,
+        output: None,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--fen" => {
+                command.position = notation::parse_fen(&value()?).map_err(|err| err.to_string())?;
+            }
+            "--format" => {
+                command.format = match value()?.as_str() {
+                    "svg" => Format::Svg,
+                    "ascii" => Format::Text(Symbols::Ascii),
+                    "unicode" => Format::Text(Symbols::Unicode),
+                    other => return Err(format!("no format called {}", other)),
+                };
+            }
+            "--flip" => command.options.flipped = true,
+            "--no-coordinates" => command.options.coordinates = false,
+            "--last-move" => command.options.last_move = Some(parse_move_squares(&value()?)?),
+            "--highlight" => {
+                for name in value()?.split(',') {
+                    let square = notation::parse_square(name)
+                        .ok_or(format!("{} is not a square like e4", name))?;
+                    command.options.highlights.push(square);
+                }
+            }
+            "--arrow" => {
+                for text in value()?.split(',') {
+                    command.options.arrows.push(parse_move_squares(text)?);
+                }
+            }
+            "--output" => command.output = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+
+    Ok(command)
+}
+
+/// Run the `diagram` command with the arguments that follow it.
+pub fn run_command(args: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let command = parse_command(args)?;
+    let diagram = match command.format {
+        Format::Svg => svg(&command.position, &command.options),
+        Format::Text(symbols) => text(&command.position, &command.options, symbols),
+    };
+
+    match command.output {
+        Some(path) => fs::write(&path, diagram)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err)),
+        None => {
+            print!("{}", diagram);
+            Ok(())
+        }
+    }
+}
+
+pub struct DiagramPlugin;
+
+impl Plugin for DiagramPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(export_diagram.in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+/// Save the position shown on the board, with its last move and drawings,
+/// as an SVG image and a Unicode text diagram.
+fn export_diagram(
+    keys: Res<Input<KeyCode>>,
+    history: Res<GameHistory>,
+    view: Res<HistoryView>,
+    annotations: Res<Annotations>,
+    layout: Res<BoardLayout>,
+) {
+    if !keys.just_pressed(KeyCode::D) {
+        return;
+    }
+
+    let ply = view.shown_ply(&history);
+    let position = history.position_at(ply);
+    let drawing = annotations.drawing(ply);
+    let options = DiagramOptions {
+        flipped: layout.flipped,
+        coordinates: true,
+        last_move: history.move_at(ply).map(|m| (m.from(), m.to())),
+        highlights: drawing.map_or(Vec::new(), |drawing| drawing.circled().collect()),
+        arrows: drawing.map_or(Vec::new(), |drawing| drawing.arrows().collect()),
+    };
+
+    let Some(dir) = history::export_dir() else {
+        log::error!("No folder to save the diagram in");
+        return;
+    };
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+
+    let diagrams = [
+        ("svg", svg(position, &options)),
+        ("txt", text(position, &options, Symbols::Unicode)),
+    ];
+    for (extension, diagram) in diagrams {
+        let path = dir.join(format!("position-{}.{}", seconds, extension));
+        match fs::create_dir_all(&dir).and_then(|()| fs::write(&path, diagram)) {
+            Ok(()) => log::info!("Diagram saved to {}", path.display()),
+            Err(err) => log::error!("Failed to save the diagram to {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn text_diagrams_follow_the_orientation() {
+        let position = notation::parse_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let options = DiagramOptions::default();
+
+        let ascii = text(&position, &options, Symbols::Ascii);
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines[0], "8 . . . . k . . .");
+        assert_eq!(lines[6], "2 . . . . P . . .");
+        assert_eq!(lines[8], "  a b c d e f g h");
+
+        let flipped = DiagramOptions {
+            flipped: true,
+            coordinates: false,
+            ..default()
+        };
+        let unicode = text(&position, &flipped, Symbols::Unicode);
+        assert_eq!(unicode.lines().next(), Some("· · · ♔ · · · ·"));
+        assert_eq!(unicode.lines().count(), 8);
+    }
+
+    #[test]
+    fn svg_diagrams_draw_the_pieces_and_marks() {
+        let command = parse_command(args(
+            "--last-move e2e4 --highlight d5,e5 --arrow g1f3 --no-coordinates",
+        ))
+        .unwrap();
+        let image = svg(&command.position, &command.options);
+
+        assert!(image.starts_with("<svg"));
+        assert!(image.trim_end().ends_with("</svg>"));
+        assert_eq!(image.matches("<rect").count(), 64 + 2 + 2);
+        assert_eq!(image.matches("<line").count(), 1);
+        assert_eq!(image.matches('♟').count(), 16);
+        assert!(image.contains(r#"viewBox="0 0 360 360""#));
+    }
+
+    #[test]
+    fn command_options_are_checked() {
+        let command = parse_command(args("--format unicode --flip")).unwrap();
+        assert_eq!(command.format, Format::Text(Symbols::Unicode));
+        assert!(command.options.flipped);
+
+        assert!(parse_command(args("--format png")).is_err());
+        assert!(parse_command(args("--arrow e2")).is_err());
+        assert!(parse_command(args("--highlight z9")).is_err());
+        assert!(parse_command(args("--fen")).is_err());
+    }
+}
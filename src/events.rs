@@ -0,0 +1,168 @@
+//! Events about what happens on the board, for other systems to react to,
+//! and [`RequestMove`] for them to play moves, for example moves received
+//! over the network or sent by a script.
+
+use bevy::prelude::*;
+
+use crate::{
+    animation::AnimationSpeed,
+    clock::ChessClock,
+    find_move,
+    highlight::{HighlightLayer, Highlights},
+    history::{GameHistory, HistoryView},
+    move_piece, notation,
+    rules::{EndReason, GameResult, Piece, PieceColor, PieceType},
+    AppState, Board, GameOutcome, SelectedPiece, SelectedTile,
+};
+
+type Square = (usize, usize);
+
+/// A move played on the board, by the player, the computer, a puzzle or a
+/// [`RequestMove`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveMade {
+    pub from: Square,
+    pub to: Square,
+    /// The piece that moved, as it stood before the move.
+    pub piece: Piece,
+    pub captured: Option<Piece>,
+    /// The move in standard algebraic notation, like `Nxe5+`.
+    pub san: String,
+}
+
+/// The player selected a piece of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceSelected {
+    pub square: Square,
+    pub piece: Piece,
+}
+
+/// A move the player tried to make which was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IllegalMoveAttempted {
+    /// Why the move was refused, like `the game is over`.
+    pub reason: String,
+}
+
+/// A move put the king of `color` in check, or checkmated it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckGiven {
+    pub color: PieceColor,
+}
+
+/// The game ended, on the board, on time or by agreement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameEnded {
+    pub result: GameResult,
+    pub reason: EndReason,
+}
+
+/// Play the move from `from` to `to` for the side to move, whoever controls
+/// it. Pawns reaching the last row are promoted to `promotion`, or to a
+/// queen if it is `None`. Moves which are not legal are refused with an
+/// [`IllegalMoveAttempted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestMove {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceType>,
+}
+
+pub struct EventsPlugin;
+
+impl Plugin for EventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MoveMade>()
+            .add_event::<PieceSelected>()
+            .add_event::<IllegalMoveAttempted>()
+            .add_event::<CheckGiven>()
+            .add_event::<GameEnded>()
+            .add_event::<RequestMove>()
+            .add_system(play_requested_moves.in_set(OnUpdate(AppState::InGame)))
+            .add_system(send_game_ended);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_requested_moves(
+    mut commands: Commands,
+    mut requests: EventReader<RequestMove>,
+    animation_speed: Res<AnimationSpeed>,
+    clock: Res<ChessClock>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<GameHistory>,
+    mut view: ResMut<HistoryView>,
+    mut outcome: ResMut<GameOutcome>,
+    mut selected_tile: ResMut<SelectedTile>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut highlights: ResMut<Highlights>,
+    mut illegal_moves: EventWriter<IllegalMoveAttempted>,
+) {
+    for &RequestMove {
+        from,
+        to,
+        promotion,
+    } in requests.iter()
+    {
+        let legal_moves = board.position.legal_moves_from(from.0, from.1);
+        let found = find_move(
+            &legal_moves,
+            to.0,
+            to.1,
+            promotion.unwrap_or(PieceType::Queen),
+        );
+        let reason = match found {
+            _ if outcome.0.is_some() => "the game is over".to_string(),
+            _ if clock.paused => "the game is paused".to_string(),
+            None => format!(
+                "{}{} is not a legal move",
+                notation::square_name(from.0, from.1),
+                notation::square_name(to.0, to.1)
+            ),
+            Some(m) => {
+                // A piece the player had selected may no longer be there
+                selected_tile.tile = None;
+                selected_piece.piece = None;
+                for layer in [
+                    HighlightLayer::Selection,
+                    HighlightLayer::QuietMove,
+                    HighlightLayer::Capture,
+                ] {
+                    highlights.clear(layer);
+                }
+                view.ply = None;
+
+                move_piece(
+                    &mut commands,
+                    *animation_speed,
+                    m,
+                    &mut board,
+                    &mut history,
+                    &mut outcome,
+                );
+                continue;
+            }
+        };
+
+        illegal_moves.send(IllegalMoveAttempted { reason });
+    }
+}
+
+/// Send [`GameEnded`] once when the game gets an outcome, however it ended.
+fn send_game_ended(
+    outcome: Res<GameOutcome>,
+    mut sent: Local<bool>,
+    mut ended: EventWriter<GameEnded>,
+) {
+    match &outcome.0 {
+        Some(end) if !*sent => {
+            ended.send(GameEnded {
+                result: end.result,
+                reason: end.reason,
+            });
+            *sent = true;
+        }
+        Some(_) => {}
+        None => *sent = false,
+    }
+}
@@ -0,0 +1,462 @@
+//! Animated GIFs of a game, one frame per position, drawn without the GPU
+//! from the piece images in `assets`. `G` saves the game being played, and
+//! `bevy-chess gif` turns the main line of a PGN file into a GIF.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{log, prelude::*, tasks::AsyncComputeTaskPool};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{self, FilterType},
+    Delay, Frame, ImageResult, Rgba, RgbaImage,
+};
+
+use crate::{
+    history::{self, GameHistory},
+    is_dark_square, pgn,
+    rules::{Move, PieceColor, PieceType, Position, COLS, ROWS},
+    theme::{BoardTheme, Theme},
+    AppState, BoardLayout,
+};
+
+/// The last position stays on screen this many times longer than the others.
+const LAST_FRAME_FACTOR: u32 = 3;
+
+/// Squares of the last move are tinted with this color.
+const LAST_MOVE: Rgba<u8> = Rgba([205, 210, 106, 255]);
+
+/// Tiles never get smaller than this, to keep the pieces recognizable.
+const MIN_SQUARE_SIZE: u32 = 8;
+
+/// Quality of the color palettes, from 1 (best, slowest) to 30.
+const ENCODER_SPEED: i32 = 10;
+
+pub const USAGE: &str = "\
+Usage: bevy-chess gif FILE.pgn [options]
+
+Options:
+  --game N         which game of the file to draw, from 1 (default 1)
+  --size PIXELS    width and height of the board (default 400)
+  --delay MS       time each position is shown (default 800)
+  --flip           draw the board with black at the bottom
+  --output FILE    where to save the GIF (default: FILE.gif)";
+
+/// How the frames are drawn and shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GifOptions {
+    /// Width and height of the board in pixels.
+    pub size: u32,
+    /// Time each position is shown.
+    pub delay: Duration,
+    /// Draw the board with black at the bottom.
+    pub flipped: bool,
+    /// RGB color of the light squares.
+    pub light_square: [f32; 3],
+    /// RGB color of the dark squares.
+    pub dark_square: [f32; 3],
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        let theme = Theme::default();
+        Self {
+            size: 400,
+            delay: Duration::from_millis(800),
+            flipped: false,
+            light_square: theme.light_square,
+            dark_square: theme.dark_square,
+        }
+    }
+}
+
+fn rgba([r, g, b]: [f32; 3]) -> Rgba<u8> {
+    let channel = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    Rgba([channel(r), channel(g), channel(b), 255])
+}
+
+/// Mix the color `tint` into `color`, half and half.
+fn blend(color: Rgba<u8>, tint: Rgba<u8>) -> Rgba<u8> {
+    let mix = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    Rgba([
+        mix(color[0], tint[0]),
+        mix(color[1], tint[1]),
+        mix(color[2], tint[2]),
+        255,
+    ])
+}
+
+/// Encoded image of each piece, the same ones the default theme uses.
+fn piece_png(color: PieceColor, piece_type: PieceType) -> &'static [u8] {
+    match (color, piece_type) {
+        (PieceColor::White, PieceType::King) => include_bytes!("../assets/W_King.png"),
+        (PieceColor::White, PieceType::Queen) => include_bytes!("../assets/W_Queen.png"),
+        (PieceColor::White, PieceType::Rook) => include_bytes!("../assets/W_Rook.png"),
+        (PieceColor::White, PieceType::Bishop) => include_bytes!("../assets/W_Bishop.png"),
+        (PieceColor::White, PieceType::Knight) => include_bytes!("../assets/W_Knight.png"),
+        (PieceColor::White, PieceType::Pawn) => include_bytes!("../assets/W_Pawn.png"),
+        (PieceColor::Black, PieceType::King) => include_bytes!("../assets/B_King.png"),
+        (PieceColor::Black, PieceType::Queen) => include_bytes!("../assets/B_Queen.png"),
+        (PieceColor::Black, PieceType::Rook) => include_bytes!("../assets/B_Rook.png"),
+        (PieceColor::Black, PieceType::Bishop) => include_bytes!("../assets/B_Bishop.png"),
+        (PieceColor::Black, PieceType::Knight) => include_bytes!("../assets/B_Knight.png"),
+        (PieceColor::Black, PieceType::Pawn) => include_bytes!("../assets/B_Pawn.png"),
+    }
+}
+
+/// Draws positions on images of a given size.
+struct Renderer {
+    options: GifOptions,
+    square_size: u32,
+    /// Image of each piece scaled to the squares, by color and piece type.
+    pieces: Vec<((PieceColor, PieceType), RgbaImage)>,
+}
+
+impl Renderer {
+    fn new(options: &GifOptions) -> ImageResult<Self> {
+        let square_size = (options.size / COLS as u32).max(MIN_SQUARE_SIZE);
+
+        let mut pieces = Vec::new();
+        for color in [PieceColor::White, PieceColor::Black] {
+            for piece_type in [
+                PieceType::King,
+                PieceType::Queen,
+                PieceType::Rook,
+                PieceType::Bishop,
+                PieceType::Knight,
+                PieceType::Pawn,
+            ] {
+                let image = image::load_from_memory(piece_png(color, piece_type))?.to_rgba8();
+                // As tall as the square and keeping the aspect of the image,
+                // with the sharp edges of pixel art
+                let width = (image.width() * square_size / image.height()).min(square_size);
+                let scaled = imageops::resize(&image, width, square_size, FilterType::Nearest);
+                pieces.push(((color, piece_type), scaled));
+            }
+        }
+
+        Ok(Self {
+            options: options.clone(),
+            square_size,
+            pieces,
+        })
+    }
+
+    /// Top left corner of the square at row `x` and column `y`.
+    fn corner(&self, x: usize, y: usize) -> (u32, u32) {
+        let (col, row) = if self.options.flipped {
+            (COLS - 1 - y, x)
+        } else {
+            (y, ROWS - 1 - x)
+        };
+        (col as u32 * self.square_size, row as u32 * self.square_size)
+    }
+
+    fn draw(&self, position: &Position, last_move: Option<Move>) -> RgbaImage {
+        let size = self.square_size * COLS as u32;
+        let mut image = RgbaImage::new(size, size);
+        let (light, dark) = (
+            rgba(self.options.light_square),
+            rgba(self.options.dark_square),
+        );
+
+        for x in 0..ROWS {
+            for y in 0..COLS {
+                let mut color = if is_dark_square(x, y) { dark } else { light };
+                if last_move.is_some_and(|m| m.from() == (x, y) || m.to() == (x, y)) {
+                    color = blend(color, LAST_MOVE);
+                }
+
+                let (left, top) = self.corner(x, y);
+                for i in 0..self.square_size {
+                    for j in 0..self.square_size {
+                        image.put_pixel(left + i, top + j, color);
+                    }
+                }
+            }
+        }
+
+        for piece in position.pieces() {
+            let Some((_, sprite)) = self
+                .pieces
+                .iter()
+                .find(|(key, _)| *key == (piece.piece_color, piece.piece_type))
+            else {
+                continue;
+            };
+            let (left, top) = self.corner(piece.x, piece.y);
+            let offset = (self.square_size - sprite.width()) / 2;
+            imageops::overlay(&mut image, sprite, (left + offset).into(), top.into());
+        }
+
+        image
+    }
+}
+
+/// Draw the position `start` and each position reached by `moves`, and
+/// write them to `out` as an animated GIF playing in a loop.
+pub fn write_gif(
+    start: &Position,
+    moves: &[Move],
+    options: &GifOptions,
+    out: impl Write,
+) -> ImageResult<()> {
+    let renderer = Renderer::new(options)?;
+    let delay = |factor: u32| {
+        let millis = options.delay.as_millis() as u32 * factor;
+        Delay::from_numer_denom_ms(millis, 1)
+    };
+
+    let mut position = start.clone();
+    let mut frames = vec![(renderer.draw(&position, None), delay(1))];
+    for &m in moves {
+        position.make_move(m);
+        frames.push((renderer.draw(&position, Some(m)), delay(1)));
+    }
+    if let Some((_, last_delay)) = frames.last_mut() {
+        *last_delay = delay(LAST_FRAME_FACTOR);
+    }
+
+    let mut encoder = GifEncoder::new_with_speed(out, ENCODER_SPEED);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(
+        frames
+            .into_iter()
+            .map(|(image, delay)| Frame::from_parts(image, 0, 0, delay)),
+    )
+}
+
+#[derive(Debug)]
+struct Command {
+    pgn_file: PathBuf,
+    game: usize,
+    options: GifOptions,
+    output: Option<PathBuf>,
+}
+
+fn parse_number(text: &str, min: u64) -> Result<u64, String> {
+    text.parse()
+        .ok()
+        .filter(|&number| number >= min)
+        .ok_or(format!("{} is not a number of at least {}", text, min))
+}
+
+fn parse_command(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut pgn_file = None;
+    let mut command = Command {
+        pgn_file: PathBuf::new(),
+        game: 1,
+        options: GifOptions::default(),
+        output: None,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--game" => command.game = parse_number(&value()?, 1)? as usize,
+            "--size" => {
+                let min = MIN_SQUARE_SIZE as u64 * COLS as u64;
+                command.options.size = parse_number(&value()?, min)? as u32;
+            }
+            "--delay" => {
+                command.options.delay = Duration::from_millis(parse_number(&value()?, 10)?)
+            }
+            "--flip" => command.options.flipped = true,
+            "--output" => command.output = Some(PathBuf::from(value()?)),
+            other if other.starts_with("--") => return Err(format!("unknown option {}", other)),
+            _ if pgn_file.is_none() => pgn_file = Some(PathBuf::from(arg)),
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+    }
+
+    command.pgn_file = pgn_file.ok_or("no PGN file given")?;
+    Ok(command)
+}
+
+/// Run the `gif` command with the arguments that follow it.
+pub fn run_command(args: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let command = parse_command(args)?;
+    let path = &command.pgn_file;
+
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let games = pgn::parse_pgn(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let game = games.get(command.game - 1).ok_or(format!(
+        "{} has {} games",
+        path.display(),
+        games.len()
+    ))?;
+    let moves: Vec<Move> = game
+        .line(game.line_end(None))
+        .into_iter()
+        .map(|node| game.nodes[node].m)
+        .collect();
+
+    let output = command.output.unwrap_or_else(|| path.with_extension("gif"));
+    let file = File::create(&output)
+        .map_err(|err| format!("failed to create {}: {}", output.display(), err))?;
+    write_gif(&game.start, &moves, &command.options, BufWriter::new(file))
+        .map_err(|err| format!("failed to write {}: {}", output.display(), err))?;
+
+    println!(
+        "{} positions saved to {}",
+        moves.len() + 1,
+        output.display()
+    );
+    Ok(())
+}
+
+pub struct GifPlugin;
+
+impl Plugin for GifPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(export_gif.in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+/// Save the game as a GIF, drawn with the colors of the board and from the
+/// side it is seen from. The frames are drawn and encoded in the background.
+fn export_gif(
+    keys: Res<Input<KeyCode>>,
+    history: Res<GameHistory>,
+    layout: Res<BoardLayout>,
+    theme: Res<BoardTheme>,
+) {
+    if !keys.just_pressed(KeyCode::G) {
+        return;
+    }
+
+    let Some(dir) = history::export_dir() else {
+        log::error!("No folder to save the GIF in");
+        return;
+    };
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = dir.join(format!("game-{}.gif", seconds));
+
+    let start = history.start.clone();
+    let moves: Vec<Move> = history.moves.iter().map(|entry| entry.m).collect();
+    let options = GifOptions {
+        flipped: layout.flipped,
+        light_square: theme.light_square,
+        dark_square: theme.dark_square,
+        ..default()
+    };
+
+    log::info!("Saving the game as a GIF");
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            let result = fs::create_dir_all(&dir)
+                .and_then(|()| File::create(&path))
+                .map_err(image::ImageError::from)
+                .and_then(|file| write_gif(&start, &moves, &options, BufWriter::new(file)));
+            match result {
+                Ok(()) => log::info!("GIF saved to {}", path.display()),
+                Err(err) => log::error!("Failed to save the GIF to {}: {}", path.display(), err),
+            }
+        })
+        .detach();
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    use super::*;
+    use crate::notation;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn pieces_are_drawn_on_their_squares() {
+        let options = GifOptions {
+            size: 160,
+            ..default()
+        };
+        let renderer = Renderer::new(&options).unwrap();
+        let position = notation::parse_fen("4k3/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let image = renderer.draw(&position, None);
+
+        assert_eq!(image.dimensions(), (160, 160));
+        let square_color = rgba(options.dark_square);
+        // The king on a1 covers part of the bottom left square only
+        let a1: Vec<_> = (0..20)
+            .flat_map(|i| (140..160).map(move |j| (i, j)))
+            .map(|(i, j)| *image.get_pixel(i, j))
+            .collect();
+        assert!(a1.iter().any(|&pixel| pixel != square_color));
+        assert!(a1.contains(&square_color));
+        assert_eq!(*image.get_pixel(30, 150), rgba(options.light_square));
+
+        // Seen from black, a1 is in the top right corner
+        let flipped = Renderer::new(&GifOptions {
+            flipped: true,
+            ..options.clone()
+        })
+        .unwrap()
+        .draw(&position, None);
+        for i in 0..20 {
+            for j in 0..20 {
+                assert_eq!(flipped.get_pixel(140 + i, j), image.get_pixel(i, 140 + j));
+                assert_eq!(*flipped.get_pixel(i, 140 + j), square_color);
+            }
+        }
+    }
+
+    #[test]
+    fn games_are_encoded_one_frame_per_position() {
+        let start = Position::STARTING;
+        let mut position = start.clone();
+        let moves: Vec<Move> = ["e4", "e5", "Nf3"]
+            .iter()
+            .map(|san| {
+                let m = notation::parse_move(&position, san).unwrap();
+                position.make_move(m);
+                m
+            })
+            .collect();
+        let options = GifOptions {
+            size: 64,
+            delay: Duration::from_millis(100),
+            ..default()
+        };
+
+        let mut gif = Vec::new();
+        write_gif(&start, &moves, &options, &mut gif).unwrap();
+
+        let frames = GifDecoder::new(gif.as_slice())
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 4);
+        let delays: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.delay().numer_denom_ms())
+            .collect();
+        assert_eq!(delays[0], (100, 1));
+        assert_eq!(delays[3], (300, 1));
+    }
+
+    #[test]
+    fn command_options_are_checked() {
+        let command = parse_command(args("game.pgn --size 200 --delay 500 --flip")).unwrap();
+        assert_eq!(command.pgn_file, PathBuf::from("game.pgn"));
+        assert_eq!(command.options.size, 200);
+        assert_eq!(command.options.delay, Duration::from_millis(500));
+        assert!(command.options.flipped);
+
+        assert!(parse_command(args("--flip")).is_err());
+        assert!(parse_command(args("game.pgn --size 10")).is_err());
+        assert!(parse_command(args("game.pgn --game 0")).is_err());
+        assert!(parse_command(args("a.pgn b.pgn")).is_err());
+    }
+}
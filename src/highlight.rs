@@ -0,0 +1,249 @@
+//! Square highlights drawn as overlay sprites between the tiles and the
+//! pieces. Each [`HighlightLayer`] owns its own sprites, so clearing one
+//! layer leaves the others in place.
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    ui::FocusPolicy,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{layout::BoardLayout, GameEntity};
+
+/// Size in pixels of the generated marker textures.
+const MARKER_TEXTURE_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HighlightLayer {
+    /// Squares the last move was made from and to.
+    LastMove,
+    /// Squares of the moves queued to be played on the next turns.
+    Premove,
+    /// Glow under a king in check.
+    Check,
+    /// Glow under the squares of the move a puzzle hint points at.
+    Hint,
+    /// The tile the player clicked on.
+    Selection,
+    /// Frame around the square under the keyboard or gamepad cursor.
+    Cursor,
+    /// Dots on the empty squares the selected piece can move to.
+    QuietMove,
+    /// Rings around the pieces the selected piece can capture.
+    Capture,
+}
+
+impl HighlightLayer {
+    const ALL: [Self; 8] = [
+        Self::LastMove,
+        Self::Premove,
+        Self::Check,
+        Self::Hint,
+        Self::Selection,
+        Self::Cursor,
+        Self::QuietMove,
+        Self::Capture,
+    ];
+
+    fn z(self) -> f32 {
+        match self {
+            Self::LastMove => 0.1,
+            Self::Premove => 0.15,
+            Self::Check => 0.2,
+            Self::Hint => 0.25,
+            Self::Selection => 0.3,
+            Self::Cursor => 0.35,
+            Self::QuietMove => 0.4,
+            Self::Capture => 0.5,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::LastMove => Color::rgba(0.9, 0.8, 0.2, 0.45),
+            Self::Premove => Color::rgba(0.7, 0.3, 0.9, 0.5),
+            Self::Check => Color::rgba(1., 0.1, 0.1, 0.9),
+            Self::Hint => Color::rgba(0.2, 0.9, 0.4, 0.9),
+            Self::Selection => Color::rgba(0.2, 0.6, 0.9, 0.5),
+            Self::Cursor => Color::rgba(1., 1., 1., 0.9),
+            Self::QuietMove => Color::rgba(0.2, 0.7, 0.3, 0.8),
+            Self::Capture => Color::rgba(0.9, 0.2, 0.1, 0.85),
+        }
+    }
+}
+
+/// Squares marked on each highlight layer. The overlay sprites are updated to
+/// match whenever this resource changes.
+#[derive(Resource, Default)]
+pub struct Highlights {
+    layers: HashMap<HighlightLayer, Vec<(usize, usize)>>,
+    dirty: HashSet<HighlightLayer>,
+}
+
+impl Highlights {
+    /// Replace the squares marked on the layer.
+    pub fn set(
+        &mut self,
+        layer: HighlightLayer,
+        squares: impl IntoIterator<Item = (usize, usize)>,
+    ) {
+        self.layers.insert(layer, squares.into_iter().collect());
+        self.dirty.insert(layer);
+    }
+
+    pub fn clear(&mut self, layer: HighlightLayer) {
+        if self.layers.remove(&layer).is_some() {
+            self.dirty.insert(layer);
+        }
+    }
+}
+
+/// Overlay sprite marking the square at row `x` and column `y`.
+#[derive(Component, Debug)]
+pub struct Highlight {
+    pub layer: HighlightLayer,
+    pub x: usize,
+    pub y: usize,
+}
+
+/// Textures the markers are drawn with, generated at startup.
+#[derive(Resource)]
+struct MarkerTextures {
+    dot: Handle<Image>,
+    ring: Handle<Image>,
+    glow: Handle<Image>,
+    frame: Handle<Image>,
+}
+
+pub struct HighlightPlugin;
+
+impl Plugin for HighlightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Highlights>()
+            .add_startup_system(create_marker_textures)
+            .add_system(sync_highlights)
+            .add_system(place_highlights.after(sync_highlights));
+    }
+}
+
+fn create_marker_textures(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    // Alpha of the marker for a pixel at offset `p` from the center, where
+    // 1.0 is the edge of the texture
+    let dot = marker_image(|p| if p.length() < 0.3 { 1. } else { 0. });
+    let ring = marker_image(|p| {
+        if (0.82..0.98).contains(&p.length()) {
+            1.
+        } else {
+            0.
+        }
+    });
+    let glow = marker_image(|p| (1. - p.length()).clamp(0., 1.).powf(1.5));
+    let frame = marker_image(|p| if p.abs().max_element() > 0.88 { 1. } else { 0. });
+
+    commands.insert_resource(MarkerTextures {
+        dot: images.add(dot),
+        ring: images.add(ring),
+        glow: images.add(glow),
+        frame: images.add(frame),
+    });
+}
+
+/// White square texture with the alpha channel given by `alpha`.
+fn marker_image(alpha: impl Fn(Vec2) -> f32) -> Image {
+    let size = MARKER_TEXTURE_SIZE;
+    let center = size as f32 / 2.;
+
+    let data = (0..size * size)
+        .flat_map(|i| {
+            let (px, py) = ((i % size) as f32 + 0.5, (i / size) as f32 + 0.5);
+            let offset = Vec2::new(px - center, py - center) / center;
+            let a = (alpha(offset) * 255.) as u8;
+            [255, 255, 255, a]
+        })
+        .collect();
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Respawn the overlay sprites of every layer changed since the last frame.
+fn sync_highlights(
+    mut commands: Commands,
+    mut highlights: ResMut<Highlights>,
+    textures: Res<MarkerTextures>,
+    layout: Res<BoardLayout>,
+    overlays: Query<(Entity, &Highlight)>,
+) {
+    if highlights.dirty.is_empty() {
+        return;
+    }
+    let dirty = std::mem::take(&mut highlights.dirty);
+
+    for (entity, highlight) in overlays.iter() {
+        if dirty.contains(&highlight.layer) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for layer in HighlightLayer::ALL {
+        if !dirty.contains(&layer) {
+            continue;
+        }
+
+        let texture = match layer {
+            HighlightLayer::LastMove | HighlightLayer::Premove | HighlightLayer::Selection => None,
+            HighlightLayer::Check | HighlightLayer::Hint => Some(textures.glow.clone()),
+            HighlightLayer::QuietMove => Some(textures.dot.clone()),
+            HighlightLayer::Capture => Some(textures.ring.clone()),
+            HighlightLayer::Cursor => Some(textures.frame.clone()),
+        };
+
+        for &(x, y) in highlights.layers.get(&layer).into_iter().flatten() {
+            let position = layout.square_translation(x, y).extend(layer.z());
+
+            let mut overlay = commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: layer.color(),
+                        custom_size: Some(Vec2::splat(layout.tile_size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position),
+                    ..default()
+                },
+                // Let clicks through to the tile under the overlay
+                FocusPolicy::Pass,
+                Highlight { layer, x, y },
+                GameEntity,
+            ));
+            if let Some(texture) = texture.clone() {
+                overlay.insert(texture);
+            }
+        }
+    }
+}
+
+/// Keep the overlays on their squares when the board layout changes.
+fn place_highlights(
+    layout: Res<BoardLayout>,
+    mut overlays: Query<(&Highlight, &mut Transform, &mut Sprite)>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+
+    for (highlight, mut transform, mut sprite) in overlays.iter_mut() {
+        let position = layout.square_translation(highlight.x, highlight.y);
+        transform.translation = position.extend(highlight.layer.z());
+        sprite.custom_size = Some(Vec2::splat(layout.tile_size));
+    }
+}
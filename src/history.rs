@@ -0,0 +1,655 @@
+//! Record of the moves played, listed in a side panel. Clicking a move or
+//! stepping with Page Up and Page Down shows the position after it without
+//! leaving the game. `E` saves the game as a PGN file.
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{log, prelude::*};
+
+use crate::{
+    animation::PIECE_Z,
+    annotations::Annotations,
+    cursor::BoardCursor,
+    highlight::{HighlightLayer, Highlights},
+    layout::{BoardLayout, ACTIONS_PANEL_HEIGHT, MOVE_INPUT_HEIGHT, SIDE_PANEL_WIDTH},
+    menu::GameSetup,
+    notation,
+    rules::{EndReason, Move, PieceColor, Position},
+    spawn_piece,
+    theme::BoardTheme,
+    AppState, GameEntity, GameOutcome, Piece, FONT,
+};
+
+/// Number of move rows shown in the panel. The rows are scrolled to keep the
+/// move being looked at in view.
+const VISIBLE_ROWS: usize = 20;
+
+const PANEL_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const MOVE_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const MOVE_HOVERED_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const CURRENT_MOVE_COLOR: Color = Color::rgb(0.3, 0.4, 0.55);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const MOVE_NUMBER_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+
+/// A move of the game with its notation, the piece it captured and the
+/// position it led to.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub m: Move,
+    pub san: String,
+    pub captured: Option<Piece>,
+    pub position: Position,
+}
+
+/// Every move played since the start position.
+#[derive(Resource, Debug, Default)]
+pub struct GameHistory {
+    pub start: Position,
+    pub moves: Vec<HistoryEntry>,
+}
+
+impl GameHistory {
+    /// Record the move `m` played in `position`, before it is made.
+    pub fn record(&mut self, position: &Position, m: Move) {
+        let san = notation::san(position, m);
+        let mut after = position.clone();
+        let captured = after.make_move(m);
+
+        self.moves.push(HistoryEntry {
+            m,
+            san,
+            captured,
+            position: after,
+        });
+    }
+
+    /// Position after the first `ply` moves.
+    pub fn position_at(&self, ply: usize) -> &Position {
+        match ply {
+            0 => &self.start,
+            ply => &self.moves[ply - 1].position,
+        }
+    }
+
+    /// The move which led to the position after `ply` moves.
+    pub fn move_at(&self, ply: usize) -> Option<Move> {
+        ply.checked_sub(1).map(|i| self.moves[i].m)
+    }
+
+    /// Number of times the position after `ply` moves has occurred so far,
+    /// counting itself.
+    pub fn repetitions(&self, ply: usize) -> usize {
+        let position = self.position_at(ply);
+        (0..=ply)
+            .filter(|&earlier| self.position_at(earlier).repeats(position))
+            .count()
+    }
+
+    /// Why a draw can be claimed in the current position, if it can.
+    pub fn draw_claim(&self) -> Option<EndReason> {
+        let ply = self.moves.len();
+
+        if self.repetitions(ply) >= 3 {
+            Some(EndReason::ThreefoldRepetition)
+        } else if self.position_at(ply).fifty_move_rule_applies() {
+            Some(EndReason::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+    /// The game in PGN, with the tag pairs `tags` in front and the text
+    /// `comment` gives for a ply after the move which led to it.
+    pub fn pgn(
+        &self,
+        tags: &[(&str, String)],
+        result: &str,
+        comment: impl Fn(usize) -> Option<String>,
+    ) -> String {
+        let mut text = String::new();
+        for (name, value) in tags {
+            text += &format!(
+                "[{} \"{}\"]\n",
+                name,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            );
+        }
+        text += &format!("[Result \"{}\"]\n", result);
+        if self.start != Position::default() {
+            text += "[SetUp \"1\"]\n";
+            text += &format!("[FEN \"{}\"]\n", notation::fen(&self.start));
+        }
+        text.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut commented = false;
+        if let Some(comment) = comment(0) {
+            tokens.push(format!("{{{}}}", comment));
+        }
+
+        for (i, entry) in self.moves.iter().enumerate() {
+            let before = self.position_at(i);
+            let number = before.fullmove_number;
+            match before.side_to_move {
+                PieceColor::White => tokens.push(format!("{}.", number)),
+                PieceColor::Black if i == 0 || commented => tokens.push(format!("{}...", number)),
+                PieceColor::Black => {}
+            }
+            tokens.push(entry.san.clone());
+
+            commented = false;
+            if let Some(comment) = comment(i + 1) {
+                tokens.push(format!("{{{}}}", comment));
+                commented = true;
+            }
+        }
+        tokens.push(result.to_string());
+
+        // Lines of movetext are kept under 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 79 {
+                text += &line;
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        text += &line;
+        text.push('\n');
+
+        text
+    }
+}
+
+/// Position of the game shown on the board, as the number of moves played
+/// to reach it. `None` shows the game as it is being played.
+#[derive(Resource, Debug, Default)]
+pub struct HistoryView {
+    pub ply: Option<usize>,
+}
+
+impl HistoryView {
+    /// Show the position after `ply` moves, or the live game if that is the
+    /// last position.
+    fn show(&mut self, ply: usize, history: &GameHistory) {
+        self.ply = (ply < history.moves.len()).then_some(ply);
+    }
+
+    /// Number of moves played to reach the position shown.
+    pub fn shown_ply(&self, history: &GameHistory) -> usize {
+        self.ply.unwrap_or(history.moves.len())
+    }
+}
+
+/// Sprite of a piece of a past position, shown in place of the pieces of the
+/// game while looking at it.
+#[derive(Component)]
+pub struct HistoryPiece;
+
+#[derive(Component)]
+struct MoveList;
+
+/// Move in the panel which shows the position after `ply` moves when
+/// clicked.
+#[derive(Component)]
+struct MoveButton {
+    ply: usize,
+}
+
+#[derive(Component)]
+struct LiveButton;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameHistory>()
+            .init_resource::<HistoryView>()
+            .add_system(spawn_move_panel.in_schedule(OnEnter(AppState::InGame)))
+            .add_systems(
+                (
+                    step_through_history,
+                    move_button_interaction,
+                    live_button_interaction,
+                    export_game,
+                )
+                    .in_set(OnUpdate(AppState::InGame)),
+            )
+            .add_system(
+                refresh_move_list
+                    .after(step_through_history)
+                    .after(move_button_interaction)
+                    .in_set(OnUpdate(AppState::InGame)),
+            )
+            .add_system(
+                show_position
+                    .after(step_through_history)
+                    .after(move_button_interaction)
+                    .after(live_button_interaction)
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+/// Folder exported games are saved in.
+pub fn export_dir() -> Option<PathBuf> {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Save the game with its drawings as a PGN file.
+fn export_game(
+    keys: Res<Input<KeyCode>>,
+    history: Res<GameHistory>,
+    annotations: Res<Annotations>,
+    setup: Res<GameSetup>,
+    outcome: Res<GameOutcome>,
+) {
+    if !keys.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    let player = |color| match setup.computer() {
+        Some((computer, strength)) if computer == color => format!("Computer (level {})", strength),
+        _ => "Human".to_string(),
+    };
+    let tags = [
+        ("Event", "Casual game".to_string()),
+        ("Site", env!("CARGO_PKG_NAME").to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", "-".to_string()),
+        ("White", player(PieceColor::White)),
+        ("Black", player(PieceColor::Black)),
+    ];
+    let result = outcome
+        .0
+        .map_or("*".to_string(), |end| end.result.to_string());
+    let pgn = history.pgn(&tags, &result, |ply| annotations.comment(ply));
+
+    let Some(dir) = export_dir() else {
+        log::error!("No folder to save the game in");
+        return;
+    };
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = dir.join(format!("game-{}.pgn", seconds));
+
+    match fs::create_dir_all(&dir).and_then(|()| fs::write(&path, pgn)) {
+        Ok(()) => log::info!("Game saved to {}", path.display()),
+        Err(err) => log::error!("Failed to save the game to {}: {}", path.display(), err),
+    }
+}
+
+fn spawn_move_panel(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(SIDE_PANEL_WIDTH - 20.), Val::Auto),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        top: Val::Px(56. + MOVE_INPUT_HEIGHT),
+                        bottom: Val::Px(ACTIONS_PANEL_HEIGHT + 20.),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        flex_grow: 1.,
+                        overflow: Overflow::Hidden,
+                        ..default()
+                    },
+                    ..default()
+                },
+                MoveList,
+            ));
+
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.), Val::Px(32.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            display: Display::None,
+                            ..default()
+                        },
+                        background_color: CURRENT_MOVE_COLOR.into(),
+                        ..default()
+                    },
+                    LiveButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Back to live",
+                        TextStyle {
+                            font,
+                            font_size: 18.,
+                            color: TEXT_COLOR,
+                        },
+                    ));
+                });
+        });
+}
+
+/// Page Up and Page Down step through the moves, Home shows the start
+/// position and End the live game. Left and Right step through the moves
+/// too, unless they move the board cursor.
+fn step_through_history(
+    keys: Res<Input<KeyCode>>,
+    cursor: Option<Res<BoardCursor>>,
+    history: Res<GameHistory>,
+    mut view: ResMut<HistoryView>,
+) {
+    let ply = view.shown_ply(&history);
+    let arrows = cursor.is_none_or(|cursor| !cursor.is_shown());
+
+    if keys.just_pressed(KeyCode::PageUp) || arrows && keys.just_pressed(KeyCode::Left) {
+        view.show(ply.saturating_sub(1), &history);
+    } else if keys.just_pressed(KeyCode::PageDown) || arrows && keys.just_pressed(KeyCode::Right) {
+        view.show(ply + 1, &history);
+    } else if keys.just_pressed(KeyCode::Home) {
+        view.show(0, &history);
+    } else if keys.just_pressed(KeyCode::End) {
+        view.ply = None;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn move_button_interaction(
+    mut buttons: Query<(&Interaction, &MoveButton, &mut BackgroundColor), Changed<Interaction>>,
+    history: Res<GameHistory>,
+    mut view: ResMut<HistoryView>,
+) {
+    let shown_ply = view.shown_ply(&history);
+
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => view.show(button.ply, &history),
+            Interaction::Hovered => *color = MOVE_HOVERED_COLOR.into(),
+            Interaction::None if button.ply == shown_ply => *color = CURRENT_MOVE_COLOR.into(),
+            Interaction::None => *color = MOVE_COLOR.into(),
+        }
+    }
+}
+
+fn live_button_interaction(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<LiveButton>)>,
+    mut view: ResMut<HistoryView>,
+) {
+    if buttons.iter().any(|i| *i == Interaction::Clicked) {
+        view.ply = None;
+    }
+}
+
+/// Rebuild the rows of the move list, one per move number with the moves of
+/// white and black side by side.
+fn refresh_move_list(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    history: Res<GameHistory>,
+    view: Res<HistoryView>,
+    list: Query<Entity, With<MoveList>>,
+    mut live_button: Query<&mut Style, With<LiveButton>>,
+) {
+    if !history.is_changed() && !view.is_changed() {
+        return;
+    }
+
+    for mut style in live_button.iter_mut() {
+        style.display = if view.ply.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    let Ok(list) = list.get_single() else {
+        return;
+    };
+    commands.entity(list).despawn_descendants();
+
+    let font = asset_server.load(FONT);
+    let text_style = |color| TextStyle {
+        font: font.clone(),
+        font_size: 18.,
+        color,
+    };
+
+    let shown_ply = view.shown_ply(&history);
+    // A game starting with black to move leaves white's slot of the first
+    // row empty
+    let skipped = usize::from(history.start.side_to_move == PieceColor::Black);
+    let rows = (history.moves.len() + skipped).div_ceil(2);
+    // Scroll so that the row of the move shown is the last visible one at
+    // most
+    let last_row = (shown_ply + skipped)
+        .div_ceil(2)
+        .max(VISIBLE_ROWS)
+        .min(rows);
+    let first_row = last_row.saturating_sub(VISIBLE_ROWS);
+
+    commands.entity(list).with_children(|parent| {
+        for row in first_row..last_row {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    let move_number = history.start.fullmove_number as usize + row;
+                    parent.spawn(
+                        TextBundle::from_section(
+                            format!("{}.", move_number),
+                            text_style(MOVE_NUMBER_COLOR),
+                        )
+                        .with_style(Style {
+                            size: Size::new(Val::Px(40.), Val::Auto),
+                            ..default()
+                        }),
+                    );
+
+                    for slot in [row * 2, row * 2 + 1] {
+                        let Some(ply) = (slot + 1).checked_sub(skipped).filter(|&ply| ply > 0)
+                        else {
+                            parent.spawn(
+                                TextBundle::from_section("...", text_style(MOVE_NUMBER_COLOR))
+                                    .with_style(Style {
+                                        size: Size::new(Val::Px(90.), Val::Auto),
+                                        padding: UiRect::horizontal(Val::Px(4.)),
+                                        ..default()
+                                    }),
+                            );
+                            continue;
+                        };
+                        let Some(entry) = history.moves.get(ply - 1) else {
+                            continue;
+                        };
+
+                        let color = if ply == shown_ply {
+                            CURRENT_MOVE_COLOR
+                        } else {
+                            MOVE_COLOR
+                        };
+
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(90.), Val::Px(24.)),
+                                        padding: UiRect::horizontal(Val::Px(4.)),
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    background_color: color.into(),
+                                    ..default()
+                                },
+                                MoveButton { ply },
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    entry.san.clone(),
+                                    text_style(TEXT_COLOR),
+                                ));
+                            });
+                    }
+                });
+        }
+    });
+}
+
+/// Show the position being looked at on the board. Past positions are drawn
+/// with their own sprites while the pieces of the game are hidden, so the
+/// game is left untouched. The last move and check highlights follow the
+/// position shown.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn show_position(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<BoardTheme>,
+    layout: Res<BoardLayout>,
+    history: Res<GameHistory>,
+    view: Res<HistoryView>,
+    mut highlights: ResMut<Highlights>,
+    history_pieces: Query<Entity, With<HistoryPiece>>,
+    mut pieces: Query<&mut Visibility, (With<Piece>, Without<HistoryPiece>)>,
+) {
+    if !history.is_changed() && !view.is_changed() {
+        return;
+    }
+
+    for entity in history_pieces.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let shown_ply = view.shown_ply(&history);
+    let position = history.position_at(shown_ply);
+
+    let visibility = if view.ply.is_some() {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut piece_visibility in pieces.iter_mut() {
+        *piece_visibility = visibility;
+    }
+
+    if view.ply.is_some() {
+        log::info!("Looking at the position after {} moves", shown_ply);
+
+        for piece in position.pieces() {
+            let translation = layout.square_translation(piece.x, piece.y).extend(PIECE_Z);
+            let entity = spawn_piece(&mut commands, &asset_server, &theme, piece, translation);
+            commands.entity(entity).insert(HistoryPiece);
+        }
+    }
+
+    match history.move_at(shown_ply) {
+        Some(m) => highlights.set(HighlightLayer::LastMove, [m.from(), m.to()]),
+        None => highlights.clear(HighlightLayer::LastMove),
+    }
+
+    let side = position.side_to_move;
+    match position.king_square(side) {
+        Some(king) if position.is_in_check(side) => {
+            highlights.set(HighlightLayer::Check, [king]);
+        }
+        _ => highlights.clear(HighlightLayer::Check),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(history: &mut GameHistory, moves: &[&str]) {
+        for name in moves {
+            let position = history.position_at(history.moves.len()).clone();
+            let (from, to) = name.split_at(2);
+            let from = notation::parse_square(from).unwrap();
+            let to = notation::parse_square(to).unwrap();
+            let m = position
+                .legal_moves_from(from.0, from.1)
+                .into_iter()
+                .find(|m| m.to() == to)
+                .unwrap_or_else(|| panic!("{} is not legal", name));
+            history.record(&position, m);
+        }
+    }
+
+    #[test]
+    fn third_repetition_can_be_claimed() {
+        let mut history = GameHistory::default();
+        let knights_out_and_back = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        play(&mut history, &knights_out_and_back);
+        assert_eq!(history.repetitions(4), 2);
+        assert_eq!(history.draw_claim(), None);
+
+        play(&mut history, &knights_out_and_back);
+        assert_eq!(history.repetitions(8), 3);
+        assert_eq!(history.draw_claim(), Some(EndReason::ThreefoldRepetition));
+    }
+
+    #[test]
+    fn fifty_moves_without_progress_can_be_claimed() {
+        let mut history = GameHistory {
+            start: notation::parse_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap(),
+            moves: Vec::new(),
+        };
+        assert_eq!(history.draw_claim(), None);
+
+        play(&mut history, &["a1a2"]);
+        assert_eq!(history.draw_claim(), Some(EndReason::FiftyMoveRule));
+    }
+
+    #[test]
+    fn pgn_lists_the_moves_with_their_comments() {
+        let mut history = GameHistory {
+            start: notation::parse_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 40").unwrap(),
+            moves: Vec::new(),
+        };
+        play(&mut history, &["e8d7", "a1a7", "d7c6"]);
+
+        let pgn = history.pgn(&[("White", "Human".to_string())], "*", |ply| {
+            (ply == 2).then(|| "[%cal Ga7a8]".to_string())
+        });
+        assert_eq!(
+            pgn,
+            "[White \"Human\"]\n\
+             [Result \"*\"]\n\
+             [SetUp \"1\"]\n\
+             [FEN \"4k3/8/8/8/8/8/8/R3K3 b - - 0 40\"]\n\
+             \n\
+             40... Kd7 41. Ra7+ {[%cal Ga7a8]} 41... Kc6 *\n"
+        );
+    }
+}
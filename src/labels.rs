@@ -0,0 +1,169 @@
+//! Rank and file coordinate labels drawn around the board.
+
+use bevy::{log, prelude::*, sprite::Anchor};
+
+use crate::{
+    is_dark_square, layout::BoardLayout, theme::BoardTheme, AppState, Board, GameEntity, FONT,
+};
+
+const FILES: [&str; Board::COLS] = ["a", "b", "c", "d", "e", "f", "g", "h"];
+const RANKS: [&str; Board::ROWS] = ["1", "2", "3", "4", "5", "6", "7", "8"];
+
+const LABEL_Z: f32 = 3.0;
+const BORDER_LABEL_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+
+/// Where the coordinate labels are drawn.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LabelPlacement {
+    /// Outside of the board, along the bottom and left edges.
+    #[default]
+    Border,
+    /// In the corners of the bottom row and left column tiles.
+    Inside,
+    Hidden,
+}
+
+impl LabelPlacement {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Border => Self::Inside,
+            Self::Inside => Self::Hidden,
+            Self::Hidden => Self::Border,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy)]
+enum CoordinateLabel {
+    File(usize),
+    Rank(usize),
+}
+
+pub struct LabelsPlugin;
+
+impl Plugin for LabelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LabelPlacement>()
+            .add_system(spawn_labels.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(spawn_labels.in_schedule(OnEnter(AppState::Replay)))
+            .add_system(cycle_label_placement)
+            .add_system(place_labels.after(cycle_label_placement));
+    }
+}
+
+fn spawn_labels(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    let labels = (0..Board::COLS)
+        .map(|col| (CoordinateLabel::File(col), FILES[col]))
+        .chain((0..Board::ROWS).map(|row| (CoordinateLabel::Rank(row), RANKS[row])));
+
+    for (label, value) in labels {
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    value,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 16.,
+                        color: BORDER_LABEL_COLOR,
+                    },
+                ),
+                ..default()
+            },
+            label,
+            GameEntity,
+        ));
+    }
+}
+
+fn cycle_label_placement(keys: Res<Input<KeyCode>>, mut placement: ResMut<LabelPlacement>) {
+    if keys.just_pressed(KeyCode::L) {
+        *placement = placement.next();
+        log::info!("Coordinate labels: {:?}", *placement);
+    }
+}
+
+/// Move the labels next to their rank or file when they are spawned and
+/// whenever the board layout, the label placement or the theme changes.
+#[allow(clippy::type_complexity)]
+fn place_labels(
+    layout: Res<BoardLayout>,
+    placement: Res<LabelPlacement>,
+    theme: Res<BoardTheme>,
+    mut labels: Query<(
+        &CoordinateLabel,
+        &mut Text,
+        &mut Anchor,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+    added: Query<(), Added<CoordinateLabel>>,
+) {
+    if !layout.is_changed() && !placement.is_changed() && !theme.is_changed() && added.is_empty() {
+        return;
+    }
+
+    let tile = layout.tile_size;
+    let font_size = tile / 4.;
+
+    // The row and column drawn along the bottom and left edges of the board
+    let (bottom_row, left_col) = if layout.flipped {
+        (Board::ROWS - 1, Board::COLS - 1)
+    } else {
+        (0, 0)
+    };
+
+    for (label, mut text, mut anchor, mut transform, mut visibility) in labels.iter_mut() {
+        *visibility = if *placement == LabelPlacement::Hidden {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+
+        let (row, col) = match *label {
+            CoordinateLabel::File(col) => (bottom_row, col),
+            CoordinateLabel::Rank(row) => (row, left_col),
+        };
+        let square = layout.square_translation(row, col);
+
+        let (position, label_anchor, color) = match (*placement, label) {
+            (LabelPlacement::Inside, CoordinateLabel::File(_)) => (
+                square + Vec2::new(tile / 2. - 2., -tile / 2. + 2.),
+                Anchor::BottomRight,
+                contrast_color(&theme, row, col),
+            ),
+            (LabelPlacement::Inside, CoordinateLabel::Rank(_)) => (
+                square + Vec2::new(-tile / 2. + 2., tile / 2. - 2.),
+                Anchor::TopLeft,
+                contrast_color(&theme, row, col),
+            ),
+            (_, CoordinateLabel::File(_)) => (
+                Vec2::new(square.x, square.y - tile / 2. - 4.),
+                Anchor::TopCenter,
+                BORDER_LABEL_COLOR,
+            ),
+            (_, CoordinateLabel::Rank(_)) => (
+                Vec2::new(square.x - tile / 2. - 8., square.y),
+                Anchor::CenterRight,
+                BORDER_LABEL_COLOR,
+            ),
+        };
+
+        transform.translation = position.extend(LABEL_Z);
+        *anchor = label_anchor;
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+            section.style.font_size = font_size;
+        }
+    }
+}
+
+/// Color that stands out on the tile at `row` and `col`.
+fn contrast_color(theme: &BoardTheme, row: usize, col: usize) -> Color {
+    if is_dark_square(row, col) {
+        theme.light_color()
+    } else {
+        theme.dark_color()
+    }
+}
@@ -0,0 +1,371 @@
+//! Mapping between board squares and world space, and the board orientation.
+
+use bevy::{log, prelude::*, window::PrimaryWindow, window::WindowResized};
+
+use crate::{
+    animation::{FadeOut, PieceTween, PIECE_Z},
+    AppState, Board, GameEntity, Piece, Tile, FONT,
+};
+
+/// Space between neighbouring tiles.
+const TILE_GAP: f32 = 0.0;
+
+/// Room left around the board for the coordinate labels.
+const BOARD_MARGIN: f32 = 40.;
+
+/// Height kept free above and below the board for the captured pieces and
+/// the clocks.
+pub const TRAY_HEIGHT: f32 = 28.;
+
+/// Height of the file labels below the board, above the bottom tray.
+const LABELS_HEIGHT: f32 = 30.;
+
+/// Width kept free on the right of the board for the side panels.
+pub const SIDE_PANEL_WIDTH: f32 = 260.;
+
+/// Height of the box moves are typed in above the move list, with its error
+/// message.
+pub const MOVE_INPUT_HEIGHT: f32 = 60.;
+
+/// Height of the panel of game actions below the move list.
+pub const ACTIONS_PANEL_HEIGHT: f32 = 150.;
+
+/// Tiles never get smaller than this, however small the window is.
+const MIN_TILE_SIZE: f32 = 16.;
+
+const BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+
+/// Where the squares of the board are drawn and how big they are. Every
+/// sprite that sits on a square should be positioned through
+/// [`BoardLayout::square_translation`] and sized from
+/// [`BoardLayout::tile_size`], so that it follows the board when it is
+/// flipped or the window is resized.
+#[derive(Resource, Debug)]
+pub struct BoardLayout {
+    /// Draw the board with black at the bottom.
+    pub flipped: bool,
+    /// Width and height of a tile.
+    pub tile_size: f32,
+    /// Center of the board in world space.
+    pub center: Vec2,
+    /// Size the board to the window whenever it is resized.
+    pub fit_to_window: bool,
+    /// Turn the board toward the player at the start of games against the
+    /// computer and of puzzles.
+    pub auto_orient: bool,
+}
+
+impl Default for BoardLayout {
+    fn default() -> Self {
+        Self {
+            flipped: false,
+            tile_size: 500. / Board::ROWS as f32,
+            center: Vec2::ZERO,
+            fit_to_window: true,
+            auto_orient: true,
+        }
+    }
+}
+
+impl BoardLayout {
+    /// Width and height of the whole board.
+    pub fn board_size(&self) -> f32 {
+        self.tile_size * Board::ROWS as f32 + TILE_GAP * (Board::ROWS - 1) as f32
+    }
+
+    /// Size the board to fill a window of `width` by `height` pixels, keeping
+    /// the board square and leaving room for the labels, the captured pieces
+    /// and the side panels.
+    pub fn fit(&mut self, width: f32, height: f32) {
+        let available = (width - SIDE_PANEL_WIDTH - 2. * BOARD_MARGIN)
+            .min(height - 2. * (BOARD_MARGIN + TRAY_HEIGHT));
+        let tile_size = (available - TILE_GAP * (Board::ROWS - 1) as f32) / Board::ROWS as f32;
+
+        self.tile_size = tile_size.max(MIN_TILE_SIZE);
+        self.center = Vec2::new(-SIDE_PANEL_WIDTH / 2., 0.);
+    }
+
+    /// Height of the center of the row of captured pieces and clock above
+    /// the board, or below it if `top` is false.
+    pub fn tray_y(&self, top: bool) -> f32 {
+        let half_board = self.board_size() / 2.;
+
+        if top {
+            self.center.y + half_board + 6. + TRAY_HEIGHT / 2.
+        } else {
+            self.center.y - half_board - LABELS_HEIGHT - TRAY_HEIGHT / 2.
+        }
+    }
+
+    /// Position of the center of the square at `row` and `col` in world
+    /// space.
+    pub fn square_translation(&self, row: usize, col: usize) -> Vec2 {
+        let (row, col) = if self.flipped {
+            (Board::ROWS - 1 - row, Board::COLS - 1 - col)
+        } else {
+            (row, col)
+        };
+
+        let step = self.tile_size + TILE_GAP;
+        let corner = self.center - Vec2::splat(self.board_size() / 2.);
+
+        corner + Vec2::new(col as f32 * step, row as f32 * step) + Vec2::splat(self.tile_size / 2.)
+    }
+
+    /// Row and column of the square at `position` in world space, if it is
+    /// on the board.
+    pub fn square_at(&self, position: Vec2) -> Option<(usize, usize)> {
+        let step = self.tile_size + TILE_GAP;
+        let corner = self.center - Vec2::splat(self.board_size() / 2.);
+        let offset = (position - corner) / step;
+        if offset.min_element() < 0. || offset.max_element() >= Board::ROWS as f32 {
+            return None;
+        }
+
+        let (row, col) = (offset.y as usize, offset.x as usize);
+        if self.flipped {
+            Some((Board::ROWS - 1 - row, Board::COLS - 1 - col))
+        } else {
+            Some((row, col))
+        }
+    }
+
+    /// Size of a piece drawn with a texture of `texture_size`, as tall as the
+    /// tile and keeping the aspect ratio of the texture, shrunk to fit if the
+    /// texture is wider than it is tall.
+    pub fn piece_size(&self, texture_size: Vec2) -> Vec2 {
+        let size = texture_size * (self.tile_size / texture_size.y);
+        if size.x > self.tile_size {
+            size * (self.tile_size / size.x)
+        } else {
+            size
+        }
+    }
+}
+
+#[derive(Component)]
+struct FlipButton;
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoardLayout>()
+            .add_startup_system(fit_board_to_window.in_base_set(StartupSet::PreStartup))
+            .add_system(spawn_flip_button.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(spawn_flip_button.in_schedule(OnEnter(AppState::Replay)))
+            .add_system(
+                flip_board_hotkey
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Replay))),
+            )
+            .add_system(
+                flip_button_interaction
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Replay))),
+            )
+            .add_system(toggle_auto_orient)
+            .add_system(resize_board)
+            .add_system(
+                apply_board_layout
+                    .after(flip_board_hotkey)
+                    .after(resize_board),
+            )
+            .add_system(fit_piece_sprites.after(apply_board_layout));
+    }
+}
+
+fn spawn_flip_button(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(90.), Val::Px(36.)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        top: Val::Px(10.),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            FlipButton,
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Flip",
+                TextStyle {
+                    font: asset_server.load(FONT),
+                    font_size: 20.,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
+
+fn fit_board_to_window(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut layout: ResMut<BoardLayout>,
+) {
+    if !layout.fit_to_window {
+        return;
+    }
+    if let Ok(window) = windows.get_single() {
+        layout.fit(window.width(), window.height());
+    }
+}
+
+fn resize_board(mut events: EventReader<WindowResized>, mut layout: ResMut<BoardLayout>) {
+    let Some(event) = events.iter().last() else {
+        return;
+    };
+    if layout.fit_to_window {
+        layout.fit(event.width, event.height);
+    }
+}
+
+fn flip_board_hotkey(keys: Res<Input<KeyCode>>, mut layout: ResMut<BoardLayout>) {
+    if keys.just_pressed(KeyCode::F) {
+        layout.flipped = !layout.flipped;
+        log::info!("Board flipped: {}", layout.flipped);
+    }
+}
+
+fn toggle_auto_orient(keys: Res<Input<KeyCode>>, mut layout: ResMut<BoardLayout>) {
+    if keys.just_pressed(KeyCode::O) {
+        layout.auto_orient = !layout.auto_orient;
+        log::info!("Turn the board toward the player: {}", layout.auto_orient);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn flip_button_interaction(
+    mut buttons: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<FlipButton>),
+    >,
+    mut layout: ResMut<BoardLayout>,
+) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                layout.flipped = !layout.flipped;
+                *color = BUTTON_HOVERED_COLOR.into();
+            }
+            Interaction::Hovered => *color = BUTTON_HOVERED_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
+
+/// Move and resize tiles and move pieces to their squares whenever the
+/// layout changes, along with captured pieces still fading out.
+#[allow(clippy::type_complexity)]
+fn apply_board_layout(
+    mut commands: Commands,
+    layout: Res<BoardLayout>,
+    mut tiles: Query<(&Tile, &mut Transform, &mut Sprite), (Without<Piece>, Without<FadeOut>)>,
+    mut pieces: Query<(Entity, &Piece, &mut Transform), Without<Tile>>,
+    mut fading: Query<(&FadeOut, &mut Transform), (Without<Tile>, Without<Piece>)>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+
+    for (tile, mut transform, mut sprite) in tiles.iter_mut() {
+        let position = layout.square_translation(tile.x, tile.y);
+        transform.translation = position.extend(transform.translation.z);
+        sprite.custom_size = Some(Vec2::splat(layout.tile_size));
+    }
+
+    for (entity, piece, mut transform) in pieces.iter_mut() {
+        commands.entity(entity).remove::<PieceTween>();
+        transform.translation = layout.square_translation(piece.x, piece.y).extend(PIECE_Z);
+    }
+
+    for (fade, mut transform) in fading.iter_mut() {
+        let (x, y) = fade.square();
+        transform.translation = layout
+            .square_translation(x, y)
+            .extend(transform.translation.z);
+    }
+}
+
+/// Size piece sprites, captured ones still fading out included, from the
+/// aspect ratio of their texture, or of the region of a sprite sheet they
+/// are drawn with. Pieces are resized as soon as their texture is loaded,
+/// so this runs every frame.
+#[allow(clippy::type_complexity)]
+fn fit_piece_sprites(
+    layout: Res<BoardLayout>,
+    images: Res<Assets<Image>>,
+    mut pieces: Query<(&Handle<Image>, &mut Sprite), Or<(With<Piece>, With<FadeOut>)>>,
+) {
+    for (texture, mut sprite) in pieces.iter_mut() {
+        let texture_size = match (sprite.rect, images.get(texture)) {
+            (Some(rect), _) => rect.size(),
+            (None, Some(image)) => image.size(),
+            (None, None) => continue,
+        };
+
+        let size = Some(layout.piece_size(texture_size));
+        if sprite.custom_size != size {
+            sprite.custom_size = size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_fits_next_to_the_side_panel() {
+        let mut layout = BoardLayout::default();
+
+        layout.fit(1280., 720.);
+        assert_eq!(
+            layout.board_size(),
+            720. - 2. * (BOARD_MARGIN + TRAY_HEIGHT)
+        );
+        assert!(layout.center.x + layout.board_size() / 2. <= 640. - SIDE_PANEL_WIDTH);
+
+        layout.fit(700., 900.);
+        assert_eq!(
+            layout.board_size(),
+            700. - SIDE_PANEL_WIDTH - 2. * BOARD_MARGIN
+        );
+    }
+
+    #[test]
+    fn pieces_keep_the_aspect_of_their_texture() {
+        let layout = BoardLayout {
+            tile_size: 64.,
+            ..default()
+        };
+
+        assert_eq!(layout.piece_size(Vec2::new(16., 32.)), Vec2::new(32., 64.));
+        assert_eq!(layout.piece_size(Vec2::new(40., 20.)), Vec2::new(64., 32.));
+    }
+
+    #[test]
+    fn squares_are_found_under_their_translation() {
+        let mut layout = BoardLayout::default();
+
+        for flipped in [false, true] {
+            layout.flipped = flipped;
+            let center = layout.square_translation(1, 6);
+            assert_eq!(layout.square_at(center), Some((1, 6)));
+            assert_eq!(
+                layout.square_at(center + Vec2::splat(layout.tile_size * 0.45)),
+                Some((1, 6))
+            );
+        }
+        assert_eq!(layout.square_at(Vec2::splat(layout.board_size())), None);
+    }
+}
@@ -0,0 +1,803 @@
+//! A 2d chess game made with bevy
+//!
+//! [`run`] opens the game in its own window. Bevy apps can embed the board
+//! with [`ChessPlugin`] instead, configured by [`ChessConfig`].
+
+mod actions;
+pub mod ai;
+mod animation;
+mod annotations;
+pub mod clock;
+mod cursor;
+pub mod diagram;
+mod events;
+pub mod gif;
+mod highlight;
+pub mod history;
+mod labels;
+mod layout;
+mod material;
+mod menu;
+mod move_input;
+pub mod notation;
+mod pgn;
+mod premove;
+mod puzzle;
+mod replay;
+pub mod rules;
+mod settings;
+mod sound;
+mod theme;
+
+use std::path::PathBuf;
+
+use actions::ActionsPlugin;
+use ai::AiPlugin;
+use animation::{AnimationPlugin, AnimationSpeed, PIECE_Z};
+use annotations::AnnotationsPlugin;
+use bevy::{log, prelude::*, ui::FocusPolicy};
+use bevy_mod_picking::prelude::*;
+use clock::{ChessClock, Clock, ClockPlugin};
+use cursor::CursorPlugin;
+use diagram::DiagramPlugin;
+use events::EventsPlugin;
+use gif::GifPlugin;
+use highlight::{HighlightLayer, HighlightPlugin, Highlights};
+use history::{GameHistory, HistoryPlugin, HistoryView};
+use labels::LabelsPlugin;
+use layout::LayoutPlugin;
+use material::MaterialPlugin;
+use menu::MenuPlugin;
+use move_input::MoveInputPlugin;
+use premove::PremovePlugin;
+use puzzle::PuzzlePlugin;
+use replay::{ReplayFile, ReplayPlugin};
+use rules::{MoveType, Outcome};
+use settings::{Settings, SettingsPlugin};
+use sound::SoundPlugin;
+use theme::{BoardTheme, ThemePlugin};
+
+pub use events::{
+    CheckGiven, GameEnded, IllegalMoveAttempted, MoveMade, PieceSelected, RequestMove,
+};
+pub use layout::BoardLayout;
+pub use menu::{GameMode, GameSetup};
+pub use rules::{Move, Piece, PieceColor, PieceType, Position};
+
+/// Whether the tile at `row` and `col` is dark. The corner square of each
+/// player, a1 and h8, is dark.
+pub fn is_dark_square(row: usize, col: usize) -> bool {
+    (row + col).is_multiple_of(2)
+}
+
+const FONT: &str = "fonts/FiraMono-Medium.ttf";
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    NewGame,
+    InGame,
+    /// Stepping through a game read from a PGN file.
+    Replay,
+}
+
+/// Whether the game is interrupted by the pause menu. The clocks are stopped
+/// and no moves can be made while paused.
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+/// Entity of the board or of the game interface around it, despawned when
+/// the game or the replay is left.
+#[derive(Component)]
+pub struct GameEntity;
+
+/// The position of the game on the board, and the entity of the piece on
+/// each square.
+#[derive(Resource, Default)]
+pub struct Board {
+    pub state: [[Option<Entity>; Self::COLS]; Self::ROWS],
+    pub position: Position,
+}
+
+impl Board {
+    pub const COLS: usize = rules::COLS;
+    pub const ROWS: usize = rules::ROWS;
+
+    /// Play the move on the board, moving the piece entities along with the
+    /// pieces. Returns the entity of the captured piece.
+    fn make_move(&mut self, m: Move) -> Option<Entity> {
+        let captured = m
+            .captured_square()
+            .and_then(|(x, y)| self.state[x][y].take());
+
+        let piece = self.state[m.from_x][m.from_y].take();
+        self.state[m.x][m.y] = piece;
+
+        if let Some(((from_x, from_y), (x, y))) = m.castling_rook() {
+            self.state[x][y] = self.state[from_x][from_y].take();
+        }
+
+        self.position.make_move(m);
+
+        captured
+    }
+}
+
+/// Square of the board drawn by the entity.
+#[derive(Component)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Resource, Default)]
+struct SelectedTile {
+    pub tile: Option<Entity>,
+}
+
+#[derive(Resource, Default)]
+struct SelectedPiece {
+    pub piece: Option<(Vec<Move>, Entity)>,
+}
+
+/// The piece pawns reaching the last row are promoted to.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct PromotionPiece(pub PieceType);
+
+impl Default for PromotionPiece {
+    fn default() -> Self {
+        Self(PieceType::Queen)
+    }
+}
+
+/// How the game ended, `None` while it is being played.
+#[derive(Resource, Debug, Default)]
+pub struct GameOutcome(pub Option<Outcome>);
+
+/// Where the board is drawn, in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardArea {
+    /// Bottom left corner of the board, the corner of a1 unless the board is
+    /// flipped.
+    pub origin: Vec2,
+    /// Width and height of the board.
+    pub size: f32,
+}
+
+/// Ways the player can choose moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputModes {
+    /// Clicking the tiles, and drawing on the board with the right button.
+    pub mouse: bool,
+    /// Moving a cursor over the board with the arrow keys or a gamepad.
+    pub cursor: bool,
+    /// Typing moves in a box above the move list.
+    pub typed_moves: bool,
+}
+
+impl Default for InputModes {
+    fn default() -> Self {
+        Self {
+            mouse: true,
+            cursor: true,
+            typed_moves: true,
+        }
+    }
+}
+
+/// How [`ChessPlugin`] sets the game up.
+#[derive(Resource, Debug, Clone)]
+pub struct ChessConfig {
+    /// Where the board is drawn, or `None` to fill the window next to the
+    /// side panels.
+    pub area: Option<BoardArea>,
+    /// Position games start from, instead of the one chosen in the new game
+    /// dialog.
+    pub start_position: Option<Position>,
+    pub input: InputModes,
+    /// Spawn a 2d camera, for apps which do not have their own.
+    pub spawn_camera: bool,
+    /// Open on the main menu. Otherwise a game starts right away.
+    pub main_menu: bool,
+}
+
+impl Default for ChessConfig {
+    fn default() -> Self {
+        Self {
+            area: None,
+            start_position: None,
+            input: InputModes::default(),
+            spawn_camera: true,
+            main_menu: true,
+        }
+    }
+}
+
+/// The whole game: board, menus and side panels. Needs Bevy's
+/// `DefaultPlugins` and `bevy_mod_picking`'s `DefaultPickingPlugins`.
+///
+/// An app can only host one board for now. The game is kept in global
+/// resources such as [`Board`], [`GameHistory`] and
+/// [`BoardLayout`] rather than on a board entity, and Bevy refuses to add
+/// the plugin a second time.
+#[derive(Default)]
+pub struct ChessPlugin {
+    pub config: ChessConfig,
+}
+
+impl Plugin for ChessPlugin {
+    fn build(&self, app: &mut App) {
+        let config = &self.config;
+        let layout = match config.area {
+            Some(area) => BoardLayout {
+                tile_size: area.size / Board::ROWS as f32,
+                center: area.origin + Vec2::splat(area.size / 2.),
+                fit_to_window: false,
+                ..default()
+            },
+            None => BoardLayout::default(),
+        };
+
+        app.insert_resource(config.clone())
+            .insert_resource(layout)
+            .insert_resource(GameSetup {
+                position: config.start_position.clone(),
+                ..default()
+            })
+            .add_plugin(BoardPlugin)
+            .add_plugin(MenuPlugin)
+            .add_plugin(SettingsPlugin)
+            .add_plugin(AnimationPlugin)
+            .add_plugin(LayoutPlugin)
+            .add_plugin(LabelsPlugin)
+            .add_plugin(HighlightPlugin)
+            .add_plugin(ThemePlugin)
+            .add_plugin(HistoryPlugin)
+            .add_plugin(MaterialPlugin)
+            .add_plugin(ClockPlugin)
+            .add_plugin(AiPlugin)
+            .add_plugin(ActionsPlugin)
+            .add_plugin(PremovePlugin)
+            .add_plugin(AnnotationsPlugin)
+            .add_plugin(SoundPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_plugin(PuzzlePlugin)
+            .add_plugin(DiagramPlugin)
+            .add_plugin(GifPlugin)
+            .add_system(
+                spawn_board
+                    .after(start_game)
+                    .in_schedule(OnEnter(AppState::InGame)),
+            )
+            .add_system(add_piece_sprites)
+            .add_system(cycle_promotion_piece)
+            .add_system(toggle_auto_queen);
+
+        if config.input.typed_moves {
+            app.add_plugin(MoveInputPlugin);
+        }
+        if config.input.cursor {
+            app.add_plugin(CursorPlugin);
+        }
+        if config.spawn_camera {
+            app.add_startup_system(spawn_camera);
+        }
+        if !config.main_menu {
+            app.add_startup_system(skip_main_menu);
+        }
+    }
+}
+
+/// The game without anything drawn and without input devices: the board,
+/// the moves chosen with [`SelectSquare`] or [`RequestMove`], and the events
+/// about them. [`ChessPlugin`] adds it, apps without a window can add it
+/// next to Bevy's `MinimalPlugins` instead.
+pub struct BoardPlugin;
+
+impl Plugin for BoardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>()
+            .add_state::<PauseState>()
+            .init_resource::<Settings>()
+            .init_resource::<GameSetup>()
+            .init_resource::<BoardLayout>()
+            .init_resource::<AnimationSpeed>()
+            .init_resource::<Highlights>()
+            .init_resource::<GameHistory>()
+            .init_resource::<HistoryView>()
+            .init_resource::<ChessClock>()
+            .init_resource::<Board>()
+            .init_resource::<SelectedTile>()
+            .init_resource::<SelectedPiece>()
+            .init_resource::<PromotionPiece>()
+            .init_resource::<GameOutcome>()
+            .add_event::<SelectSquare>()
+            .add_plugin(EventsPlugin)
+            .add_system(start_game.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(leave_game.in_schedule(OnExit(AppState::InGame)))
+            .add_system(leave_game.in_schedule(OnExit(AppState::Replay)))
+            .add_system(select_tile.in_set(OnUpdate(AppState::InGame)))
+            .add_system(sync_pieces);
+    }
+}
+
+/// Commands run from the command line instead of opening the game, with
+/// their usage and the function running them.
+type CommandFn = fn(Vec<String>) -> Result<(), String>;
+const COMMANDS: [(&str, &str, CommandFn); 2] = [
+    ("diagram", diagram::USAGE, |args| diagram::run_command(args)),
+    ("gif", gif::USAGE, |args| gif::run_command(args)),
+];
+
+/// Open the game window and run the game until it is closed, or run the
+/// command given on the command line.
+pub fn run() {
+    let first_arg = std::env::args_os().nth(1);
+    let command = COMMANDS
+        .iter()
+        .find(|(name, _, _)| first_arg.as_ref().is_some_and(|arg| arg == name));
+    if let Some(&(name, usage, run_command)) = command {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            println!("{}", usage);
+            return;
+        }
+        if let Err(err) = run_command(args) {
+            eprintln!("bevy-chess {}: {}, see --help", name, err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let (settings, settings_file) = Settings::load();
+    let (width, height) = settings.window_size;
+    // A PGN file given on the command line is replayed right away
+    let replay_file = ReplayFile(std::env::args_os().nth(1).map(PathBuf::from));
+
+    App::new()
+        .insert_resource(settings)
+        .insert_resource(settings_file)
+        .insert_resource(replay_file)
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        resolution: (width, height).into(),
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .set(
+                    // This sets image filtering to nearest
+                    // This is done to prevent textures with low resolution (e.g. pixel art) from being blurred
+                    // by linear filtering.
+                    ImagePlugin::default_nearest(),
+                )
+                .set(AssetPlugin {
+                    // Reload themes when their files are edited
+                    watch_for_changes: true,
+                    ..default()
+                }),
+        )
+        .add_plugins(DefaultPickingPlugins)
+        .add_plugin(ChessPlugin::default())
+        .run();
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+}
+
+fn skip_main_menu(mut app_state: ResMut<NextState<AppState>>) {
+    app_state.set(AppState::InGame);
+}
+
+/// Set up a new game as chosen in [`GameSetup`] and create its pieces, which
+/// get their sprites from [`add_piece_sprites`]. Unless auto-orient is off,
+/// the board is turned so that a player facing the computer or solving a
+/// puzzle plays from the bottom, and as set in the settings otherwise.
+#[allow(clippy::too_many_arguments)]
+fn start_game(
+    mut commands: Commands,
+    setup: Res<GameSetup>,
+    settings: Res<Settings>,
+    mut layout: ResMut<BoardLayout>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<GameHistory>,
+    mut view: ResMut<HistoryView>,
+    mut highlights: ResMut<Highlights>,
+    mut clock: ResMut<ChessClock>,
+    mut outcome: ResMut<GameOutcome>,
+    mut selected_tile: ResMut<SelectedTile>,
+    mut selected_piece: ResMut<SelectedPiece>,
+) {
+    let start = setup.start_position();
+    log::info!("New game from {}", notation::fen(&start));
+
+    *board = Board {
+        position: start.clone(),
+        ..default()
+    };
+    *history = GameHistory {
+        start: start.clone(),
+        moves: Vec::new(),
+    };
+    *view = HistoryView::default();
+    *highlights = Highlights::default();
+    *clock = ChessClock::new(
+        setup
+            .time_control()
+            .map(|control| Clock::new(control, start.side_to_move)),
+    );
+    *outcome = GameOutcome::default();
+    *selected_tile = SelectedTile::default();
+    *selected_piece = SelectedPiece::default();
+
+    layout.flipped = match setup.opponent() {
+        Some(opponent) if layout.auto_orient => opponent == PieceColor::White,
+        _ => settings.flipped,
+    };
+
+    for piece in start.pieces() {
+        let entity = commands.spawn((piece, GameEntity)).id();
+        board.state[piece.x][piece.y] = Some(entity);
+    }
+}
+
+fn spawn_board(mut commands: Commands, layout: Res<BoardLayout>, theme: Res<BoardTheme>) {
+    spawn_tiles(&mut commands, &layout, &theme);
+}
+
+/// Draw the tiles of the board.
+fn spawn_tiles(commands: &mut Commands, layout: &BoardLayout, theme: &BoardTheme) {
+    for row in 0..Board::ROWS {
+        for col in 0..Board::COLS {
+            let position = layout.square_translation(row, col);
+
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: theme.square_color(row, col),
+                        custom_size: Some(Vec2::splat(layout.tile_size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position.extend(0.0)),
+                    ..default()
+                },
+                PickableBundle::default(),
+                OnPointer::<Click>::run_callback(click_tile),
+                Tile { x: row, y: col },
+                GameEntity,
+            ));
+        }
+    }
+}
+
+/// Remove the board and everything shown around it.
+fn leave_game(
+    mut commands: Commands,
+    entities: Query<Entity, With<GameEntity>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    pause_state.set(PauseState::Running);
+}
+
+fn spawn_piece(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    theme: &BoardTheme,
+    piece: Piece,
+    position: Vec3,
+) -> Entity {
+    let sprite = piece_sprite(asset_server, theme, piece, position);
+    commands.spawn((sprite, piece, GameEntity)).id()
+}
+
+/// Draw the pieces of the game, which [`start_game`] creates without
+/// sprites.
+fn add_piece_sprites(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<BoardTheme>,
+    layout: Res<BoardLayout>,
+    pieces: Query<(Entity, &Piece), Without<Sprite>>,
+) {
+    for (entity, &piece) in pieces.iter() {
+        let translation = layout.square_translation(piece.x, piece.y).extend(PIECE_Z);
+        let sprite = piece_sprite(&asset_server, &theme, piece, translation);
+        commands.entity(entity).insert(sprite);
+    }
+}
+
+fn piece_sprite(
+    asset_server: &AssetServer,
+    theme: &BoardTheme,
+    piece: Piece,
+    position: Vec3,
+) -> impl Bundle {
+    let (texture, rect) = theme.piece_sprite(asset_server, piece.piece_color, piece.piece_type);
+
+    (
+        SpriteBundle {
+            texture,
+            sprite: Sprite {
+                // Sized by the layout once the texture is loaded
+                custom_size: Some(Vec2::ZERO),
+                rect,
+                ..default()
+            },
+            transform: Transform::from_translation(position),
+            ..default()
+        },
+        PickableBundle::default(),
+        // Let clicks through to the tile under the piece
+        FocusPolicy::Pass,
+    )
+}
+
+/// A square chosen by the player, by clicking its tile or from the keyboard.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectSquare {
+    pub x: usize,
+    pub y: usize,
+    /// Piece a pawn moving to the square is promoted to, instead of the
+    /// [`PromotionPiece`].
+    pub promotion: Option<PieceType>,
+}
+
+/// Choose the square of the clicked tile.
+fn click_tile(
+    In(event): In<ListenedEvent<Click>>,
+    config: Res<ChessConfig>,
+    tiles: Query<&Tile>,
+    mut selections: EventWriter<SelectSquare>,
+) -> Bubble {
+    if !config.input.mouse {
+        return Bubble::Up;
+    }
+    let Ok(&Tile { x, y }) = tiles.get(event.target) else {
+        return Bubble::Burst;
+    };
+
+    selections.send(SelectSquare {
+        x,
+        y,
+        promotion: None,
+    });
+
+    Bubble::Up
+}
+
+/// Mark the chosen tile as selected, and add that one to [`SelectedTile`]
+/// resource. If there is already a piece selected and the tile is one of its
+/// moves, move the piece there and update the board state. Otherwise, if
+/// there is a piece of the side to move on the tile, select it and add it to
+/// [`SelectedPiece`], also show its possible moves.
+#[allow(clippy::too_many_arguments)]
+fn select_tile(
+    mut commands: Commands,
+    mut selections: EventReader<SelectSquare>,
+    tiles: Query<(Entity, &Tile)>,
+    mut selected_tile: ResMut<SelectedTile>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut board: ResMut<Board>,
+    promotion: Res<PromotionPiece>,
+    settings: Res<Settings>,
+    animation_speed: Res<AnimationSpeed>,
+    mut highlights: ResMut<Highlights>,
+    mut history: ResMut<GameHistory>,
+    mut view: ResMut<HistoryView>,
+    clock: Res<ChessClock>,
+    setup: Res<GameSetup>,
+    mut outcome: ResMut<GameOutcome>,
+    // Bevy systems take at most 16 parameters
+    (mut illegal_moves, mut selected_pieces): (
+        EventWriter<IllegalMoveAttempted>,
+        EventWriter<PieceSelected>,
+    ),
+) {
+    for &SelectSquare {
+        x,
+        y,
+        promotion: chosen_promotion,
+    } in selections.iter()
+    {
+        // Nothing can be played once the game is over, while it is paused or
+        // while the computer or the puzzle is about to answer
+        let opponent = setup.opponent();
+        if outcome.0.is_some() || clock.paused || opponent == Some(board.position.side_to_move) {
+            continue;
+        }
+
+        // Go back to the game when looking at an earlier position
+        if view.ply.is_some() {
+            view.ply = None;
+            continue;
+        }
+
+        // If there is a piece selected, move it to the selected tile
+        if let Some((moves, _)) = selected_piece.piece.take() {
+            highlights.clear(HighlightLayer::QuietMove);
+            highlights.clear(HighlightLayer::Capture);
+
+            let promotion = match chosen_promotion {
+                Some(piece_type) => piece_type,
+                None if settings.auto_queen => PieceType::Queen,
+                None => promotion.0,
+            };
+
+            if let Some(m) = find_move(&moves, x, y, promotion) {
+                selected_tile.tile = None;
+                highlights.clear(HighlightLayer::Selection);
+
+                move_piece(
+                    &mut commands,
+                    *animation_speed,
+                    m,
+                    &mut board,
+                    &mut history,
+                    &mut outcome,
+                );
+
+                continue;
+            }
+
+            // Anywhere but on another piece of its side, which selects that
+            // piece instead, the piece cannot go there
+            let side = board.position.side_to_move;
+            if board
+                .position
+                .piece_at(x, y)
+                .is_none_or(|piece| piece.piece_color != side)
+            {
+                illegal_moves.send(IllegalMoveAttempted {
+                    reason: format!(
+                        "the selected piece cannot move to {}",
+                        notation::square_name(x, y)
+                    ),
+                });
+            }
+        }
+
+        // Select new tile
+        selected_tile.tile = tiles
+            .iter()
+            .find(|(_, tile)| tile.x == x && tile.y == y)
+            .map(|(entity, _)| entity);
+        highlights.set(HighlightLayer::Selection, [(x, y)]);
+
+        // If there is a piece on the tile, select it
+        if let (Some(piece_entity), Some(piece)) =
+            (board.state[x][y], board.position.piece_at(x, y))
+        {
+            let moves = board.position.legal_moves_from(x, y);
+
+            highlight_possible_moves(&moves, &mut highlights);
+            if piece.piece_color == board.position.side_to_move {
+                selected_pieces.send(PieceSelected {
+                    square: (x, y),
+                    piece,
+                });
+            }
+
+            selected_piece.piece = Some((moves, piece_entity));
+        }
+    }
+}
+
+/// Find the move to the square at row `x` and column `y`, promoting pawns
+/// that reach the last row to `promotion`.
+fn find_move(moves: &[Move], x: usize, y: usize, promotion: PieceType) -> Option<Move> {
+    moves
+        .iter()
+        .filter(|m| m.promotion.is_none() || m.promotion == Some(promotion))
+        .find(|m| m.x == x && m.y == y)
+        .copied()
+}
+
+/// Play the move `m` on the board, and send [`MoveMade`] and [`CheckGiven`]
+/// about it.
+fn move_piece(
+    commands: &mut Commands,
+    animation_speed: AnimationSpeed,
+    m: Move,
+    board: &mut Board,
+    history: &mut GameHistory,
+    outcome: &mut GameOutcome,
+) {
+    let made = MoveMade {
+        from: m.from(),
+        to: m.to(),
+        piece: board
+            .position
+            .piece_at(m.from_x, m.from_y)
+            .expect("moves start from a piece"),
+        captured: m
+            .captured_square()
+            .and_then(|(x, y)| board.position.piece_at(x, y)),
+        san: notation::san(&board.position, m),
+    };
+    commands.add(|world: &mut World| world.send_event(made));
+
+    history.record(&board.position, m);
+
+    if let Some(captured_piece) = board.make_move(m) {
+        let square = m.captured_square().expect("only captures take pieces");
+        animation::capture_piece(commands, animation_speed, captured_piece, square);
+    }
+
+    let side = board.position.side_to_move;
+    if board.position.is_in_check(side) {
+        commands.add(move |world: &mut World| world.send_event(CheckGiven { color: side }));
+    }
+    if let Some(end) = board.position.outcome() {
+        log::info!("Game over: {}", end);
+        outcome.0 = Some(end);
+    } else if board.position.is_in_check(side) {
+        log::info!("{:?} is in check", side);
+    }
+}
+
+/// Put dots on the empty squares the piece can move to, including en passant
+/// destinations, and rings around the pieces it can capture.
+fn highlight_possible_moves(moves: &[Move], highlights: &mut Highlights) {
+    highlights.set(
+        HighlightLayer::QuietMove,
+        moves
+            .iter()
+            .filter(|m| m.move_type != MoveType::Capture)
+            .map(Move::to),
+    );
+    highlights.set(
+        HighlightLayer::Capture,
+        moves.iter().filter_map(Move::captured_square),
+    );
+}
+
+fn cycle_promotion_piece(keys: Res<Input<KeyCode>>, mut promotion: ResMut<PromotionPiece>) {
+    if keys.just_pressed(KeyCode::P) {
+        promotion.0 = match promotion.0 {
+            PieceType::Queen => PieceType::Rook,
+            PieceType::Rook => PieceType::Bishop,
+            PieceType::Bishop => PieceType::Knight,
+            _ => PieceType::Queen,
+        };
+        log::info!("Pawns are promoted to: {:?}", promotion.0);
+    }
+}
+
+fn toggle_auto_queen(keys: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keys.just_pressed(KeyCode::Q) {
+        settings.auto_queen = !settings.auto_queen;
+        log::info!("Always promote to a queen: {}", settings.auto_queen);
+    }
+}
+
+/// Copy the pieces of the position to the components of their entities.
+fn sync_pieces(board: Res<Board>, mut pieces: Query<&mut Piece>) {
+    if !board.is_changed() {
+        return;
+    }
+
+    for (row, entities) in board.state.iter().enumerate() {
+        for (col, entity) in entities.iter().enumerate() {
+            let (Some(entity), Some(piece)) = (entity, board.position.piece_at(row, col)) else {
+                continue;
+            };
+
+            if let Ok(mut component) = pieces.get_mut(*entity) {
+                if *component != piece {
+                    *component = piece;
+                }
+            }
+        }
+    }
+}
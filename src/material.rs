@@ -0,0 +1,158 @@
+//! Trays of captured pieces above and below the board, with the material
+//! balance next to the side which is ahead.
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+    history::{GameHistory, HistoryView},
+    layout::{BoardLayout, TRAY_HEIGHT},
+    rules::{PieceColor, PieceType},
+    theme::BoardTheme,
+    AppState, GameEntity, FONT,
+};
+
+/// Order the captured pieces are grouped in.
+const TRAY_ORDER: [PieceType; 5] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+const TRAY_Z: f32 = 1.0;
+
+/// Horizontal distance between pieces of the same type, which overlap.
+const PIECE_SPACING: f32 = 0.45;
+
+/// Extra distance between two groups of pieces.
+const GROUP_SPACING: f32 = 0.35;
+
+const BALANCE_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+
+/// Sprite of a captured piece in a tray.
+#[derive(Component)]
+struct TrayPiece;
+
+/// Material advantage of the side whose tray it follows.
+#[derive(Component)]
+struct MaterialBalance;
+
+pub struct MaterialPlugin;
+
+impl Plugin for MaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(refresh_trays.in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+/// Pieces of `color` taken in the first `ply` moves, grouped by type.
+fn captured_pieces(history: &GameHistory, ply: usize, color: PieceColor) -> Vec<PieceType> {
+    let captured: Vec<PieceType> = history.moves[..ply]
+        .iter()
+        .filter_map(|entry| entry.captured)
+        .filter(|piece| piece.piece_color == color)
+        .map(|piece| piece.piece_type)
+        .collect();
+
+    TRAY_ORDER
+        .iter()
+        .flat_map(|&piece_type| captured.iter().filter(move |&&t| t == piece_type))
+        .copied()
+        .collect()
+}
+
+/// Redraw both trays whenever a move is made, an earlier position is shown
+/// or the board changes size, orientation or theme.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn refresh_trays(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    images: Res<Assets<Image>>,
+    history: Res<GameHistory>,
+    view: Res<HistoryView>,
+    layout: Res<BoardLayout>,
+    theme: Res<BoardTheme>,
+    trays: Query<Entity, Or<(With<TrayPiece>, With<MaterialBalance>)>>,
+) {
+    if !history.is_changed() && !view.is_changed() && !layout.is_changed() && !theme.is_changed() {
+        return;
+    }
+
+    for entity in trays.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let ply = view.shown_ply(&history);
+    let position = history.position_at(ply);
+    let font = asset_server.load(FONT);
+
+    // The side playing from the top of the board
+    let top = if layout.flipped {
+        PieceColor::White
+    } else {
+        PieceColor::Black
+    };
+
+    let half_board = layout.board_size() / 2.;
+    let piece_height = TRAY_HEIGHT - 4.;
+
+    for side in [PieceColor::White, PieceColor::Black] {
+        let y = layout.tray_y(side == top);
+        let mut x = layout.center.x - half_board;
+
+        // Each side's tray holds the pieces it took from the other side
+        let captured = captured_pieces(&history, ply, side.opposite());
+        for (i, &piece_type) in captured.iter().enumerate() {
+            if i > 0 && captured[i - 1] != piece_type {
+                x += piece_height * GROUP_SPACING;
+            }
+
+            let (texture, rect) = theme.piece_sprite(&asset_server, side.opposite(), piece_type);
+            let texture_size = rect
+                .map(|rect| rect.size())
+                .or_else(|| images.get(&texture).map(|image| image.size()))
+                .unwrap_or(Vec2::new(1., 2.));
+            let size = texture_size * (piece_height / texture_size.y);
+
+            commands.spawn((
+                SpriteBundle {
+                    texture,
+                    sprite: Sprite {
+                        custom_size: Some(size),
+                        rect,
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(x + size.x / 2., y, TRAY_Z + i as f32 * 0.01),
+                    ..default()
+                },
+                TrayPiece,
+                GameEntity,
+            ));
+
+            x += piece_height * PIECE_SPACING;
+        }
+
+        let balance = position.material(side) as i32 - position.material(side.opposite()) as i32;
+        if balance > 0 {
+            commands.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        format!("+{}", balance),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: piece_height * 0.8,
+                            color: BALANCE_COLOR,
+                        },
+                    ),
+                    text_anchor: Anchor::CenterLeft,
+                    transform: Transform::from_xyz(x + piece_height * 0.6, y, TRAY_Z),
+                    ..default()
+                },
+                MaterialBalance,
+                GameEntity,
+            ));
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! Main menu, the new game dialog where the opponent, time control and start
+//! position are chosen, and the pause menu shown with `Esc` during a game.
+//! `Esc` goes back to the main menu from the dialog and from replays. Puzzles
+//! are started straight from the main menu.
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    ai::{MAX_STRENGTH, MIN_STRENGTH},
+    clock::TimeControl,
+    notation,
+    rules::{PieceColor, Position},
+    AppState, ChessConfig, PauseState, FONT,
+};
+
+const BACKDROP_COLOR: Color = Color::rgba(0.05, 0.05, 0.05, 0.85);
+const BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+const BUTTON_PRESSED_COLOR: Color = Color::rgb(0.3, 0.4, 0.55);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+/// Position a game can be started from.
+pub struct StartPosition {
+    pub name: &'static str,
+    pub fen: &'static str,
+}
+
+pub const START_POSITIONS: [StartPosition; 4] = [
+    StartPosition {
+        name: "Standard",
+        fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    },
+    StartPosition {
+        name: "Italian Game",
+        fen: "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    },
+    StartPosition {
+        name: "King and pawn",
+        fen: "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1",
+    },
+    StartPosition {
+        name: "Queen vs king",
+        fen: "8/8/8/4k3/8/8/8/3QK3 w - - 0 1",
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    HumanVsHuman,
+    HumanVsComputer {
+        /// Side played by the human.
+        human: PieceColor,
+        strength: u8,
+    },
+    /// Solving a puzzle, whose opponent moves are played from the solution.
+    Puzzle {
+        /// Side played by the human.
+        player: PieceColor,
+    },
+}
+
+/// How the next game is played, as chosen in the new game dialog.
+#[derive(Resource, Debug, Clone)]
+pub struct GameSetup {
+    pub mode: GameMode,
+    /// Index in [`TimeControl::presets`].
+    pub time_control: usize,
+    /// Index in [`START_POSITIONS`].
+    pub start_position: usize,
+    /// Position to start from instead of one of [`START_POSITIONS`], e.g.
+    /// the one of a puzzle.
+    pub position: Option<Position>,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::HumanVsHuman,
+            time_control: 0,
+            start_position: 0,
+            position: None,
+        }
+    }
+}
+
+impl GameSetup {
+    /// Side and strength of the computer, if it plays.
+    pub fn computer(&self) -> Option<(PieceColor, u8)> {
+        match self.mode {
+            GameMode::HumanVsHuman | GameMode::Puzzle { .. } => None,
+            GameMode::HumanVsComputer { human, strength } => Some((human.opposite(), strength)),
+        }
+    }
+
+    /// Side the player cannot move: the computer's, or the side whose moves
+    /// come from the solution of a puzzle.
+    pub fn opponent(&self) -> Option<PieceColor> {
+        match self.mode {
+            GameMode::HumanVsHuman => None,
+            GameMode::HumanVsComputer { human, .. } => Some(human.opposite()),
+            GameMode::Puzzle { player } => Some(player.opposite()),
+        }
+    }
+
+    /// Time control of the game. Puzzles are untimed.
+    pub fn time_control(&self) -> Option<TimeControl> {
+        match self.mode {
+            GameMode::Puzzle { .. } => None,
+            _ => TimeControl::presets()[self.time_control].clone(),
+        }
+    }
+
+    pub fn start_position(&self) -> Position {
+        if let Some(position) = &self.position {
+            return position.clone();
+        }
+        let start = &START_POSITIONS[self.start_position];
+        notation::parse_fen(start.fen).expect("start positions are valid FEN")
+    }
+}
+
+/// Root node of the main menu.
+#[derive(Component)]
+struct MainMenuScreen;
+
+/// Root node of the new game dialog.
+#[derive(Component)]
+struct NewGameScreen;
+
+/// Root node of the pause menu.
+#[derive(Component)]
+struct PauseScreen;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuButton {
+    NewGame,
+    Puzzles,
+    Replay,
+    Quit,
+    Opponent,
+    Side,
+    Strength,
+    TimeControl,
+    StartPosition,
+    Start,
+    Back,
+    Resume,
+    MainMenu,
+}
+
+impl MenuButton {
+    /// Text of the button, or `None` if it does not apply to the setup.
+    fn label(self, setup: &GameSetup) -> Option<String> {
+        Some(match self {
+            Self::NewGame => "New game".to_string(),
+            Self::Puzzles => "Puzzles".to_string(),
+            Self::Replay => "Replay a game".to_string(),
+            Self::Quit => "Quit".to_string(),
+            Self::Opponent => match setup.mode {
+                GameMode::HumanVsHuman | GameMode::Puzzle { .. } => "Opponent: Human".to_string(),
+                GameMode::HumanVsComputer { .. } => "Opponent: Computer".to_string(),
+            },
+            Self::Side => match setup.mode {
+                GameMode::HumanVsHuman | GameMode::Puzzle { .. } => return None,
+                GameMode::HumanVsComputer { human, .. } => format!("Play as: {:?}", human),
+            },
+            Self::Strength => match setup.mode {
+                GameMode::HumanVsHuman | GameMode::Puzzle { .. } => return None,
+                GameMode::HumanVsComputer { strength, .. } => {
+                    format!("Strength: {}/{}", strength, MAX_STRENGTH)
+                }
+            },
+            Self::TimeControl => match setup.time_control() {
+                Some(control) => format!("Time: {}", control),
+                None => "Time: untimed".to_string(),
+            },
+            Self::StartPosition => {
+                format!("Position: {}", START_POSITIONS[setup.start_position].name)
+            }
+            Self::Start => "Start".to_string(),
+            Self::Back => "Back".to_string(),
+            Self::Resume => "Resume".to_string(),
+            Self::MainMenu => "Main menu".to_string(),
+        })
+    }
+}
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameSetup>()
+            .add_system(spawn_main_menu.in_schedule(OnEnter(AppState::MainMenu)))
+            .add_system(despawn_screen::<MainMenuScreen>.in_schedule(OnExit(AppState::MainMenu)))
+            .add_system(spawn_new_game_menu.in_schedule(OnEnter(AppState::NewGame)))
+            .add_system(despawn_screen::<NewGameScreen>.in_schedule(OnExit(AppState::NewGame)))
+            .add_system(spawn_pause_menu.in_schedule(OnEnter(PauseState::Paused)))
+            .add_system(despawn_screen::<PauseScreen>.in_schedule(OnExit(PauseState::Paused)))
+            .add_system(toggle_pause.in_set(OnUpdate(AppState::InGame)))
+            .add_system(
+                back_to_main_menu
+                    .run_if(in_state(AppState::NewGame).or_else(in_state(AppState::Replay))),
+            )
+            .add_system(menu_buttons)
+            .add_system(update_button_labels.after(menu_buttons));
+    }
+}
+
+/// Full window node which the buttons of a menu are stacked in.
+fn screen(backdrop: Color) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            gap: Size::all(Val::Px(10.)),
+            ..default()
+        },
+        background_color: backdrop.into(),
+        // Draw the menu above the rest of the UI
+        z_index: ZIndex::Global(10),
+        ..default()
+    }
+}
+
+fn spawn_title(parent: &mut ChildBuilder, font: &Handle<Font>, title: &str) {
+    parent.spawn(
+        TextBundle::from_section(
+            title,
+            TextStyle {
+                font: font.clone(),
+                font_size: 48.,
+                color: TEXT_COLOR,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::bottom(Val::Px(20.)),
+            ..default()
+        }),
+    );
+}
+
+fn spawn_button(parent: &mut ChildBuilder, font: &Handle<Font>, button: MenuButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(320.), Val::Px(44.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 22.,
+                    color: TEXT_COLOR,
+                },
+            ));
+        });
+}
+
+fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    commands
+        .spawn((screen(Color::NONE), MainMenuScreen))
+        .with_children(|parent| {
+            spawn_title(parent, &font, "Chess");
+            spawn_button(parent, &font, MenuButton::NewGame);
+            spawn_button(parent, &font, MenuButton::Puzzles);
+            spawn_button(parent, &font, MenuButton::Replay);
+            spawn_button(parent, &font, MenuButton::Quit);
+        });
+}
+
+fn spawn_new_game_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    commands
+        .spawn((screen(Color::NONE), NewGameScreen))
+        .with_children(|parent| {
+            spawn_title(parent, &font, "New game");
+            for button in [
+                MenuButton::Opponent,
+                MenuButton::Side,
+                MenuButton::Strength,
+                MenuButton::TimeControl,
+                MenuButton::StartPosition,
+                MenuButton::Start,
+                MenuButton::Back,
+            ] {
+                spawn_button(parent, &font, button);
+            }
+        });
+}
+
+fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    commands
+        .spawn((screen(BACKDROP_COLOR), PauseScreen))
+        .with_children(|parent| {
+            spawn_title(parent, &font, "Paused");
+            for button in [
+                MenuButton::Resume,
+                MenuButton::NewGame,
+                MenuButton::MainMenu,
+                MenuButton::Quit,
+            ] {
+                spawn_button(parent, &font, button);
+            }
+        });
+}
+
+fn despawn_screen<T: Component>(mut commands: Commands, screens: Query<Entity, With<T>>) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Open or close the pause menu with `Esc`.
+fn toggle_pause(
+    keys: Res<Input<KeyCode>>,
+    state: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(match state.0 {
+            PauseState::Running => PauseState::Paused,
+            PauseState::Paused => PauseState::Running,
+        });
+    }
+}
+
+fn back_to_main_menu(keys: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn menu_buttons(
+    mut buttons: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut setup: ResMut<GameSetup>,
+    config: Res<ChessConfig>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => *color = BUTTON_PRESSED_COLOR.into(),
+            Interaction::Hovered => {
+                *color = BUTTON_HOVERED_COLOR.into();
+                continue;
+            }
+            Interaction::None => {
+                *color = BUTTON_COLOR.into();
+                continue;
+            }
+        }
+
+        match button {
+            MenuButton::NewGame => {
+                // Puzzles are not chosen in the dialog
+                if let GameMode::Puzzle { .. } = setup.mode {
+                    setup.mode = GameMode::HumanVsHuman;
+                    setup.position = config.start_position.clone();
+                }
+                pause_state.set(PauseState::Running);
+                app_state.set(AppState::NewGame);
+            }
+            MenuButton::Puzzles => {
+                // The puzzle and the side played are picked as the game starts
+                setup.mode = GameMode::Puzzle {
+                    player: PieceColor::White,
+                };
+                app_state.set(AppState::InGame);
+            }
+            MenuButton::Replay => app_state.set(AppState::Replay),
+            MenuButton::Quit => exit.send(AppExit),
+            MenuButton::Opponent => {
+                setup.mode = match setup.mode {
+                    GameMode::HumanVsHuman => GameMode::HumanVsComputer {
+                        human: PieceColor::White,
+                        strength: (MIN_STRENGTH + MAX_STRENGTH) / 2,
+                    },
+                    GameMode::HumanVsComputer { .. } | GameMode::Puzzle { .. } => {
+                        GameMode::HumanVsHuman
+                    }
+                }
+            }
+            MenuButton::Side => {
+                if let GameMode::HumanVsComputer { human, .. } = &mut setup.mode {
+                    *human = human.opposite();
+                }
+            }
+            MenuButton::Strength => {
+                if let GameMode::HumanVsComputer { strength, .. } = &mut setup.mode {
+                    *strength = if *strength >= MAX_STRENGTH {
+                        MIN_STRENGTH
+                    } else {
+                        *strength + 1
+                    };
+                }
+            }
+            MenuButton::TimeControl => {
+                setup.time_control = (setup.time_control + 1) % TimeControl::presets().len();
+            }
+            MenuButton::StartPosition => {
+                setup.start_position = (setup.start_position + 1) % START_POSITIONS.len();
+            }
+            MenuButton::Start => app_state.set(AppState::InGame),
+            MenuButton::Back => app_state.set(AppState::MainMenu),
+            MenuButton::Resume => pause_state.set(PauseState::Running),
+            MenuButton::MainMenu => {
+                pause_state.set(PauseState::Running);
+                app_state.set(AppState::MainMenu);
+            }
+        }
+    }
+}
+
+/// Write the labels of new buttons and of the setup rows when the setup
+/// changes, hiding the rows which do not apply to it.
+fn update_button_labels(
+    setup: Res<GameSetup>,
+    mut buttons: Query<(Ref<MenuButton>, &mut Style, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (button, mut style, children) in buttons.iter_mut() {
+        if !setup.is_changed() && !button.is_added() {
+            continue;
+        }
+
+        let label = button.label(&setup);
+        style.display = if label.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = label.clone().unwrap_or_default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_positions_are_valid() {
+        for start in &START_POSITIONS {
+            let position = notation::parse_fen(start.fen)
+                .unwrap_or_else(|err| panic!("{}: {}", start.name, err));
+            assert_eq!(notation::fen(&position), start.fen);
+        }
+        assert_eq!(GameSetup::default().start_position(), Position::STARTING);
+    }
+}
@@ -0,0 +1,276 @@
+//! Moves typed into a text box above the move list, in SAN (`Nf3`, `exd5`,
+//! `O-O`, `e8=Q`) or coordinate notation (`g1f3`). `Enter` focuses the box
+//! and plays the move, `Esc` leaves it. Typed moves are played by choosing
+//! their squares like clicks on the board would.
+
+use bevy::{input::InputSystem, prelude::*};
+
+use crate::{
+    clock::ChessClock,
+    history::HistoryView,
+    layout::{MOVE_INPUT_HEIGHT, SIDE_PANEL_WIDTH},
+    menu::GameSetup,
+    notation, AppState, Board, GameEntity, GameOutcome, IllegalMoveAttempted, SelectSquare, FONT,
+};
+
+/// Longest text the box takes, enough for `exd8=Q+` and the like.
+const MAX_LENGTH: usize = 10;
+
+/// Characters which can appear in a move.
+const MOVE_CHARACTERS: &str = "abcdefghKQRBNqrn12345678xO0-=+#";
+
+const PLACEHOLDER: &str = "Enter to type a move";
+
+const BOX_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const FOCUSED_BOX_COLOR: Color = Color::rgb(0.22, 0.22, 0.25);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const PLACEHOLDER_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+const ERROR_COLOR: Color = Color::rgb(0.95, 0.3, 0.2);
+
+/// Text typed so far and why the last move entered was not played.
+#[derive(Resource, Debug, Default)]
+struct MoveInput {
+    text: String,
+    focused: bool,
+    error: Option<String>,
+}
+
+#[derive(Component)]
+struct MoveInputBox;
+
+#[derive(Component)]
+struct MoveInputText;
+
+#[derive(Component)]
+struct MoveInputError;
+
+pub struct MoveInputPlugin;
+
+impl Plugin for MoveInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveInput>()
+            .add_system(spawn_move_input.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(clear_move_input.in_schedule(OnEnter(AppState::InGame)))
+            // Runs before the other systems read the keyboard, so that typing
+            // does not trigger their hotkeys
+            .add_system(
+                type_move
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                (focus_move_input, update_move_input)
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn spawn_move_input(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT);
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(SIDE_PANEL_WIDTH - 20.), Val::Px(MOVE_INPUT_HEIGHT)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        top: Val::Px(56.),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.), Val::Px(34.)),
+                            padding: UiRect::horizontal(Val::Px(8.)),
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: BOX_COLOR.into(),
+                        ..default()
+                    },
+                    MoveInputBox,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 20.,
+                                color: TEXT_COLOR,
+                            },
+                        ),
+                        MoveInputText,
+                    ));
+                });
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: 16.,
+                        color: ERROR_COLOR,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::top(Val::Px(4.)),
+                    ..default()
+                }),
+                MoveInputError,
+            ));
+        });
+}
+
+fn clear_move_input(mut input: ResMut<MoveInput>) {
+    *input = MoveInput::default();
+}
+
+/// Edit the text while the box is focused, and play the move on `Enter`.
+#[allow(clippy::too_many_arguments)]
+fn type_move(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut input: ResMut<MoveInput>,
+    board: Res<Board>,
+    setup: Res<GameSetup>,
+    clock: Res<ChessClock>,
+    outcome: Res<GameOutcome>,
+    mut view: ResMut<HistoryView>,
+    mut selections: EventWriter<SelectSquare>,
+    mut illegal_moves: EventWriter<IllegalMoveAttempted>,
+) {
+    if !input.focused {
+        characters.clear();
+        if keys.just_pressed(KeyCode::Return) {
+            input.focused = true;
+            keys.reset(KeyCode::Return);
+        }
+        return;
+    }
+
+    for event in characters.iter() {
+        if MOVE_CHARACTERS.contains(event.char) && input.text.len() < MAX_LENGTH {
+            input.text.push(event.char);
+            input.error = None;
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Back) {
+        input.text.pop();
+        input.error = None;
+    }
+
+    if keys.just_pressed(KeyCode::Escape) {
+        *input = MoveInput::default();
+    }
+
+    if keys.just_pressed(KeyCode::Return) && !input.text.is_empty() {
+        let result = if outcome.0.is_some() {
+            Err("the game is over".to_string())
+        } else if clock.paused {
+            Err("the game is paused".to_string())
+        } else if setup.opponent() == Some(board.position.side_to_move) {
+            Err("wait for the opponent's move".to_string())
+        } else {
+            notation::parse_move(&board.position, &input.text).map_err(|err| {
+                illegal_moves.send(IllegalMoveAttempted {
+                    reason: err.to_string(),
+                });
+                err.to_string()
+            })
+        };
+
+        match result {
+            Ok(m) => {
+                view.ply = None;
+                selections.send(SelectSquare {
+                    x: m.from_x,
+                    y: m.from_y,
+                    promotion: None,
+                });
+                selections.send(SelectSquare {
+                    x: m.x,
+                    y: m.y,
+                    promotion: m.promotion,
+                });
+                input.text.clear();
+                input.error = None;
+            }
+            Err(error) => input.error = Some(error),
+        }
+    }
+
+    // The keys typed are for the box only
+    keys.reset_all();
+}
+
+/// Focus the box when it is clicked and leave it when clicking elsewhere.
+fn focus_move_input(
+    mouse: Res<Input<MouseButton>>,
+    boxes: Query<&Interaction, With<MoveInputBox>>,
+    mut input: ResMut<MoveInput>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let clicked = boxes.iter().any(|i| *i == Interaction::Clicked);
+    if input.focused != clicked {
+        input.focused = clicked;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_move_input(
+    input: Res<MoveInput>,
+    mut boxes: Query<&mut BackgroundColor, With<MoveInputBox>>,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<MoveInputText>>,
+        Query<&mut Text, With<MoveInputError>>,
+    )>,
+) {
+    if !input.is_changed() {
+        return;
+    }
+
+    for mut color in boxes.iter_mut() {
+        *color = if input.focused {
+            FOCUSED_BOX_COLOR
+        } else {
+            BOX_COLOR
+        }
+        .into();
+    }
+
+    for mut text in texts.p0().iter_mut() {
+        let section = &mut text.sections[0];
+        if input.focused {
+            section.value = format!("{}_", input.text);
+            section.style.color = TEXT_COLOR;
+        } else if input.text.is_empty() {
+            section.value = PLACEHOLDER.to_string();
+            section.style.color = PLACEHOLDER_COLOR;
+        } else {
+            section.value = input.text.clone();
+            section.style.color = TEXT_COLOR;
+        }
+    }
+
+    for mut text in texts.p1().iter_mut() {
+        text.sections[0].value = input.error.clone().unwrap_or_default();
+    }
+}
@@ -0,0 +1,602 @@
+//! Squares and moves written as text in algebraic notation, and positions
+//! in Forsyth-Edwards Notation (FEN).
+
+use std::fmt;
+
+use crate::rules::{
+    CastlingRights, Move, MoveType, Piece, PieceColor, PieceType, Position, COLS, ROWS,
+};
+
+const FILES: &[u8; 8] = b"abcdefgh";
+const RANKS: &[u8; 8] = b"12345678";
+
+/// Row and column of a square.
+type Square = (usize, usize);
+
+/// Name of the square at row `x` and column `y`, e.g. `e4`.
+pub fn square_name(x: usize, y: usize) -> String {
+    format!("{}{}", FILES[y] as char, RANKS[x] as char)
+}
+
+/// Upper case letter of the piece type, `P` for pawns.
+pub fn piece_letter(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::Pawn => 'P',
+        PieceType::Rook => 'R',
+        PieceType::Knight => 'N',
+        PieceType::Bishop => 'B',
+        PieceType::Queen => 'Q',
+        PieceType::King => 'K',
+    }
+}
+
+/// Piece type of an upper or lower case letter, as used in FEN and SAN.
+pub fn piece_type_from_letter(letter: char) -> Option<PieceType> {
+    match letter.to_ascii_uppercase() {
+        'P' => Some(PieceType::Pawn),
+        'R' => Some(PieceType::Rook),
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+/// Row and column of a square given by its name, e.g. `e4`.
+pub fn parse_square(name: &str) -> Option<Square> {
+    let &[file, rank] = name.as_bytes() else {
+        return None;
+    };
+    let y = FILES.iter().position(|&f| f == file)?;
+    let x = RANKS.iter().position(|&r| r == rank)?;
+    Some((x, y))
+}
+
+/// The field of a FEN record which could not be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FenError {
+    pub field: &'static str,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} in FEN", self.field)
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// Read a position from a FEN record. The move counters may be left out.
+pub fn parse_fen(fen: &str) -> Result<Position, FenError> {
+    let error = |field| FenError { field };
+    let mut fields = fen.split_whitespace();
+
+    let mut squares = [[None; COLS]; ROWS];
+    let placement = fields.next().ok_or(error("piece placement"))?;
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != ROWS {
+        return Err(error("piece placement"));
+    }
+    for (i, rank) in ranks.iter().enumerate() {
+        let x = ROWS - 1 - i;
+        let mut y = 0;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                y += empty as usize;
+                continue;
+            }
+
+            let piece_type = piece_type_from_letter(c).ok_or(error("piece placement"))?;
+            let color = if c.is_ascii_uppercase() {
+                PieceColor::White
+            } else {
+                PieceColor::Black
+            };
+            if y >= COLS {
+                return Err(error("piece placement"));
+            }
+            squares[x][y] = Some(Piece::new(piece_type, color, x, y));
+            y += 1;
+        }
+        if y != COLS {
+            return Err(error("piece placement"));
+        }
+    }
+
+    let side_to_move = match fields.next() {
+        Some("w") => PieceColor::White,
+        Some("b") => PieceColor::Black,
+        _ => return Err(error("side to move")),
+    };
+
+    let castling_field = fields.next().ok_or(error("castling rights"))?;
+    if castling_field != "-" && !castling_field.chars().all(|c| "KQkq".contains(c)) {
+        return Err(error("castling rights"));
+    }
+    let castling = CastlingRights {
+        white_king_side: castling_field.contains('K'),
+        white_queen_side: castling_field.contains('Q'),
+        black_king_side: castling_field.contains('k'),
+        black_queen_side: castling_field.contains('q'),
+    };
+
+    let en_passant = match fields.next().ok_or(error("en passant square"))? {
+        "-" => None,
+        name => Some(parse_square(name).ok_or(error("en passant square"))?),
+    };
+
+    let halfmove_clock = match fields.next() {
+        Some(clock) => clock.parse().map_err(|_| error("halfmove clock"))?,
+        None => 0,
+    };
+    let fullmove_number = match fields.next() {
+        Some(number) => number.parse().map_err(|_| error("fullmove number"))?,
+        None => 1,
+    };
+
+    let position = Position {
+        squares,
+        side_to_move,
+        castling,
+        en_passant,
+        halfmove_clock,
+        fullmove_number,
+    };
+
+    for color in [PieceColor::White, PieceColor::Black] {
+        if position.king_square(color).is_none() {
+            return Err(error("piece placement"));
+        }
+    }
+
+    Ok(position)
+}
+
+/// The position written as a FEN record.
+pub fn fen(position: &Position) -> String {
+    let mut fen = String::new();
+
+    for x in (0..ROWS).rev() {
+        let mut empty = 0;
+        for y in 0..COLS {
+            match position.piece_at(x, y) {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    let letter = piece_letter(piece.piece_type);
+                    fen.push(match piece.piece_color {
+                        PieceColor::White => letter,
+                        PieceColor::Black => letter.to_ascii_lowercase(),
+                    });
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if x > 0 {
+            fen.push('/');
+        }
+    }
+
+    fen.push(' ');
+    fen.push(match position.side_to_move {
+        PieceColor::White => 'w',
+        PieceColor::Black => 'b',
+    });
+
+    fen.push(' ');
+    let castling = &position.castling;
+    let rights: String = [
+        (castling.white_king_side, 'K'),
+        (castling.white_queen_side, 'Q'),
+        (castling.black_king_side, 'k'),
+        (castling.black_queen_side, 'q'),
+    ]
+    .iter()
+    .filter(|(allowed, _)| *allowed)
+    .map(|(_, c)| c)
+    .collect();
+    fen.push_str(if rights.is_empty() { "-" } else { &rights });
+
+    fen.push(' ');
+    match position.en_passant {
+        Some((x, y)) => fen.push_str(&square_name(x, y)),
+        None => fen.push('-'),
+    }
+
+    fen.push_str(&format!(
+        " {} {}",
+        position.halfmove_clock, position.fullmove_number
+    ));
+
+    fen
+}
+
+/// The legal move `m` of `position` in standard algebraic notation, e.g.
+/// `Nbd2`, `exd5`, `e8=Q+` or `O-O`.
+pub fn san(position: &Position, m: Move) -> String {
+    let mut san = String::new();
+    let piece = position
+        .piece_at(m.from_x, m.from_y)
+        .expect("No piece to move");
+    let is_capture = m.captured_square().is_some();
+
+    if m.move_type == MoveType::Castle {
+        san.push_str(if m.y > m.from_y { "O-O" } else { "O-O-O" });
+    } else {
+        if piece.piece_type == PieceType::Pawn {
+            if is_capture {
+                san.push(FILES[m.from_y] as char);
+            }
+        } else {
+            san.push(piece_letter(piece.piece_type));
+            san.push_str(&disambiguation(position, m, piece.piece_type));
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(m.x, m.y));
+
+        if let Some(promotion) = m.promotion {
+            san.push('=');
+            san.push(piece_letter(promotion));
+        }
+    }
+
+    let mut after = position.clone();
+    after.make_move(m);
+    if after.is_checkmate() {
+        san.push('#');
+    } else if after.is_in_check(after.side_to_move) {
+        san.push('+');
+    }
+
+    san
+}
+
+/// Why text could not be read as a move of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// The text is not written in SAN or coordinate notation.
+    Invalid,
+    /// No legal move matches the text.
+    Illegal,
+    /// More than one legal move matches the text.
+    Ambiguous,
+    /// A pawn reaching the last row needs the piece it is promoted to.
+    MissingPromotion,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Invalid => "not a move",
+            Self::Illegal => "illegal move",
+            Self::Ambiguous => "ambiguous, add the file or rank",
+            Self::MissingPromotion => "add the promotion piece, e.g. =Q",
+        })
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Read a legal move of the position written in SAN, e.g. `Nf3`, `exd5`,
+/// `O-O` or `e8=Q`, or in coordinate notation, e.g. `g1f3` or `e7e8q`.
+/// Check and annotation marks at the end are ignored.
+pub fn parse_move(position: &Position, text: &str) -> Result<Move, MoveError> {
+    let text = text.trim().trim_end_matches(['+', '#', '!', '?']);
+    if text.is_empty() {
+        return Err(MoveError::Invalid);
+    }
+
+    let mut candidates = match parse_coordinates(text) {
+        Some((from, to, promotion)) => position
+            .legal_moves_from(from.0, from.1)
+            .into_iter()
+            .filter(|m| m.to() == to)
+            .filter(|m| promotion.is_none() || m.promotion == promotion)
+            .collect::<Vec<_>>(),
+        None => san_candidates(position, text)?,
+    };
+
+    // A promotion written without its piece matches all four promotions
+    if candidates.len() > 1 && candidates.iter().all(|m| m.promotion.is_some()) {
+        let first = candidates[0];
+        if candidates
+            .iter()
+            .all(|m| m.from() == first.from() && m.to() == first.to())
+        {
+            return Err(MoveError::MissingPromotion);
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(MoveError::Illegal),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(MoveError::Ambiguous),
+    }
+}
+
+/// Squares and promotion piece of a move like `e7e8q` or `e7e8=Q`.
+fn parse_coordinates(text: &str) -> Option<(Square, Square, Option<PieceType>)> {
+    if !text.is_ascii() || text.len() < 4 {
+        return None;
+    }
+
+    let from = parse_square(&text[0..2])?;
+    let to = parse_square(&text[2..4])?;
+    let promotion = match text[4..].trim_start_matches('=') {
+        "" => None,
+        letter => {
+            let mut chars = letter.chars();
+            let piece_type = chars.next().and_then(piece_type_from_letter)?;
+            if chars.next().is_some() {
+                return None;
+            }
+            Some(piece_type)
+        }
+    };
+
+    Some((from, to, promotion))
+}
+
+/// Legal moves matching a move written in SAN.
+fn san_candidates(position: &Position, text: &str) -> Result<Vec<Move>, MoveError> {
+    let castling = text.replace('0', "O");
+    if castling == "O-O" || castling == "O-O-O" {
+        let king_side = castling == "O-O";
+        return Ok(position
+            .legal_moves()
+            .into_iter()
+            .filter(|m| m.move_type == MoveType::Castle && (m.y > m.from_y) == king_side)
+            .collect());
+    }
+
+    if !text.is_ascii() {
+        return Err(MoveError::Invalid);
+    }
+    let mut rest = text;
+
+    // Pieces are written with an upper case letter, so `b` is a pawn on the
+    // b file and `B` a bishop
+    let piece_type = match rest.chars().next() {
+        Some(letter @ ('K' | 'Q' | 'R' | 'B' | 'N')) => {
+            rest = &rest[1..];
+            piece_type_from_letter(letter).ok_or(MoveError::Invalid)?
+        }
+        _ => PieceType::Pawn,
+    };
+
+    // A letter after the rank of the destination is the promotion piece, in
+    // either case
+    let mut promotion = None;
+    if let [.., before, letter] = rest.as_bytes() {
+        if piece_type == PieceType::Pawn
+            && letter.is_ascii_alphabetic()
+            && (before.is_ascii_digit() || *before == b'=')
+        {
+            promotion = Some(piece_type_from_letter(*letter as char).ok_or(MoveError::Invalid)?);
+            rest = rest[..rest.len() - 1].trim_end_matches('=');
+        }
+    }
+
+    if rest.len() < 2 {
+        return Err(MoveError::Invalid);
+    }
+    let to = parse_square(&rest[rest.len() - 2..]).ok_or(MoveError::Invalid)?;
+    let rest = &rest[..rest.len() - 2];
+
+    let is_capture = rest.ends_with('x') || rest.ends_with(':');
+    let rest = rest.trim_end_matches(['x', ':']);
+
+    // What is left tells which of the pieces moves: its file, rank or both
+    let mut from_file = None;
+    let mut from_rank = None;
+    for byte in rest.bytes() {
+        if let Some(y) = FILES.iter().position(|&f| f == byte) {
+            from_file = Some(y);
+        } else if let Some(x) = RANKS.iter().position(|&r| r == byte) {
+            from_rank = Some(x);
+        } else {
+            return Err(MoveError::Invalid);
+        }
+    }
+
+    Ok(position
+        .legal_moves()
+        .into_iter()
+        .filter(|m| m.move_type != MoveType::Castle)
+        .filter(|m| {
+            position
+                .piece_at(m.from_x, m.from_y)
+                .is_some_and(|piece| piece.piece_type == piece_type)
+        })
+        .filter(|m| m.to() == to)
+        .filter(|m| !is_capture || m.captured_square().is_some())
+        .filter(|m| from_file.is_none_or(|y| m.from_y == y))
+        .filter(|m| from_rank.is_none_or(|x| m.from_x == x))
+        .filter(|m| promotion.is_none() || m.promotion == promotion)
+        .collect())
+}
+
+/// File, rank or both of the square the piece moves from, when another piece
+/// of the same type can move to the same square.
+fn disambiguation(position: &Position, m: Move, piece_type: PieceType) -> String {
+    let others: Vec<Move> = position
+        .legal_moves()
+        .into_iter()
+        .filter(|other| other.to() == m.to() && other.from() != m.from())
+        .filter(|other| {
+            position
+                .piece_at(other.from_x, other.from_y)
+                .is_some_and(|p| p.piece_type == piece_type)
+        })
+        .collect();
+
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|other| other.from_y != m.from_y) {
+        (FILES[m.from_y] as char).to_string()
+    } else if others.iter().all(|other| other.from_x != m.from_x) {
+        (RANKS[m.from_x] as char).to_string()
+    } else {
+        square_name(m.from_x, m.from_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Play moves given in coordinate notation, e.g. `e2e4`, returning the
+    /// SAN of each.
+    fn play(position: &mut Position, moves: &[&str]) -> Vec<String> {
+        moves
+            .iter()
+            .map(|coordinates| {
+                let m = position
+                    .legal_moves()
+                    .into_iter()
+                    .find(|m| {
+                        square_name(m.from_x, m.from_y) + &square_name(m.x, m.y) == coordinates[..4]
+                            && m.promotion
+                                .is_none_or(|p| coordinates.ends_with(piece_letter(p)))
+                    })
+                    .unwrap_or_else(|| panic!("{coordinates} is not legal"));
+                let san = san(position, m);
+                position.make_move(m);
+                san
+            })
+            .collect()
+    }
+
+    /// Position with only the given pieces on the board, white to move.
+    fn board(pieces: &[Piece]) -> Position {
+        let mut position = Position {
+            squares: [[None; COLS]; ROWS],
+            ..Position::default()
+        };
+        for &piece in pieces {
+            position.squares[piece.x][piece.y] = Some(piece);
+        }
+        position
+    }
+
+    #[test]
+    fn pieces_pawns_and_captures() {
+        let mut position = Position::default();
+
+        let sans = play(
+            &mut position,
+            &["e2e4", "d7d5", "e4d5", "g8f6", "f1b5", "c7c6"],
+        );
+
+        assert_eq!(sans, ["e4", "d5", "exd5", "Nf6", "Bb5+", "c6"]);
+    }
+
+    #[test]
+    fn ambiguous_moves_name_the_file_or_rank() {
+        let mut position = Position::default();
+        let sans = play(&mut position, &["g1f3", "a7a6", "d2d3", "a6a5", "b1d2"]);
+        assert_eq!(sans[4], "Nbd2");
+
+        let mut position = board(&[
+            Piece::king(PieceColor::White, 0, 7),
+            Piece::rook(PieceColor::White, 0, 0),
+            Piece::rook(PieceColor::White, 4, 0),
+            Piece::king(PieceColor::Black, 7, 7),
+        ]);
+        assert_eq!(play(&mut position, &["a1a3"]), ["R1a3"]);
+    }
+
+    #[test]
+    fn castling_promotion_and_mate() {
+        let mut position = Position::default();
+        let sans = play(
+            &mut position,
+            &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1"],
+        );
+        assert_eq!(sans[6], "O-O");
+
+        let mut position = board(&[
+            Piece::king(PieceColor::White, 0, 4),
+            Piece::pawn(PieceColor::White, 6, 1),
+            Piece::rook(PieceColor::Black, 7, 0),
+            Piece::king(PieceColor::Black, 4, 7),
+        ]);
+        assert_eq!(play(&mut position, &["b7a8N"]), ["bxa8=N"]);
+
+        let mut position = Position::default();
+        let sans = play(&mut position, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(sans[3], "Qh4#");
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(parse_fen(start), Ok(Position::STARTING));
+        assert_eq!(fen(&Position::STARTING), start);
+
+        let mut position = Position::default();
+        play(&mut position, &["e2e4", "c7c5", "g1f3"]);
+        let after = "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        assert_eq!(fen(&position), after);
+        assert_eq!(parse_fen(after), Ok(position));
+    }
+
+    #[test]
+    fn invalid_fen_names_the_field() {
+        assert_eq!(
+            parse_fen("8/8/8/8/8/8/8/8 w - - 0 1").unwrap_err().field,
+            "piece placement"
+        );
+        assert_eq!(
+            parse_fen("4k3/8/8/8/8/8/8/4K3 x - -").unwrap_err().field,
+            "side to move"
+        );
+        assert!(parse_fen("4k3/8/8/8/8/8/8/4K3 w - -").is_ok());
+    }
+
+    #[test]
+    fn typed_moves_in_san_and_coordinates() {
+        let position = Position::default();
+        let parse = |text| parse_move(&position, text).map(|m| san(&position, m));
+
+        assert_eq!(parse("Nf3"), Ok("Nf3".to_string()));
+        assert_eq!(parse("g1f3"), Ok("Nf3".to_string()));
+        assert_eq!(parse("e4"), Ok("e4".to_string()));
+        assert_eq!(parse("e2-e4"), Err(MoveError::Invalid));
+        assert_eq!(parse("Nf4"), Err(MoveError::Illegal));
+        assert_eq!(parse("e5"), Err(MoveError::Illegal));
+        assert_eq!(parse("O-O"), Err(MoveError::Illegal));
+        assert_eq!(parse("hello"), Err(MoveError::Invalid));
+    }
+
+    #[test]
+    fn typed_moves_need_enough_to_tell_them_apart() {
+        let position = board(&[
+            Piece::king(PieceColor::White, 0, 4),
+            Piece::knight(PieceColor::White, 0, 1),
+            Piece::knight(PieceColor::White, 0, 5),
+            Piece::pawn(PieceColor::White, 6, 1),
+            Piece::rook(PieceColor::Black, 7, 0),
+            Piece::king(PieceColor::Black, 7, 7),
+        ]);
+        let parse = |text| parse_move(&position, text).map(|m| san(&position, m));
+
+        assert_eq!(parse("Nd2"), Err(MoveError::Ambiguous));
+        assert_eq!(parse("Nbd2"), Ok("Nbd2".to_string()));
+        assert_eq!(parse("Nfd2"), Ok("Nfd2".to_string()));
+        assert_eq!(parse("bxa8=N"), Ok("bxa8=N".to_string()));
+        assert_eq!(parse("bxa8q"), Ok("bxa8=Q+".to_string()));
+        assert_eq!(parse("b7a8q"), Ok("bxa8=Q+".to_string()));
+        assert_eq!(parse("b8Q+"), Ok("b8=Q+".to_string()));
+        assert_eq!(parse("b8"), Err(MoveError::MissingPromotion));
+    }
+}
@@ -0,0 +1,424 @@
+//! Reading games in Portable Game Notation: tag pairs, moves in SAN,
+//! comments and side variations. NAGs and escaped lines are skipped.
+
+use std::fmt;
+
+use crate::{
+    notation::{self, FenError, MoveError},
+    rules::{Move, PieceColor, Position},
+};
+
+/// A move of a game or of one of its variations.
+#[derive(Debug, Clone)]
+pub struct MoveNode {
+    pub m: Move,
+    pub san: String,
+    /// Position after the move.
+    pub position: Position,
+    /// The move played before, `None` for a first move.
+    pub parent: Option<usize>,
+    /// Moves played after this one, the main line first.
+    pub children: Vec<usize>,
+    pub comment: Option<String>,
+}
+
+/// A game read from PGN. Moves are kept in a tree in [`PgnGame::nodes`] and
+/// referred to by their index, `None` standing for the start position.
+#[derive(Debug, Clone, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: Position,
+    /// First move of the main line and of the variations which start at
+    /// the first move.
+    pub first_moves: Vec<usize>,
+    pub nodes: Vec<MoveNode>,
+    /// Comment before the first move.
+    pub comment: Option<String>,
+}
+
+impl PgnGame {
+    /// Value of the tag pair called `name`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn position(&self, node: Option<usize>) -> &Position {
+        match node {
+            Some(i) => &self.nodes[i].position,
+            None => &self.start,
+        }
+    }
+
+    /// Moves which can be played after `node`, the main line first.
+    pub fn children(&self, node: Option<usize>) -> &[usize] {
+        match node {
+            Some(i) => &self.nodes[i].children,
+            None => &self.first_moves,
+        }
+    }
+
+    pub fn parent(&self, node: Option<usize>) -> Option<usize> {
+        node.and_then(|i| self.nodes[i].parent)
+    }
+
+    pub fn comment(&self, node: Option<usize>) -> Option<&str> {
+        match node {
+            Some(i) => self.nodes[i].comment.as_deref(),
+            None => self.comment.as_deref(),
+        }
+    }
+
+    /// Moves played from the start to reach `node`, in order.
+    pub fn line(&self, node: Option<usize>) -> Vec<usize> {
+        let mut line: Vec<usize> = std::iter::successors(node, |&i| self.nodes[i].parent).collect();
+        line.reverse();
+        line
+    }
+
+    /// Last move reached from `node` by always following the main line.
+    pub fn line_end(&self, mut node: Option<usize>) -> Option<usize> {
+        while let Some(&next) = self.children(node).first() {
+            node = Some(next);
+        }
+        node
+    }
+
+    /// The move of `node` with its number, like `12. Nf3` or `12... Nf6`.
+    pub fn numbered_move(&self, node: usize) -> String {
+        let before = self.position(self.nodes[node].parent);
+        let dots = match before.side_to_move {
+            PieceColor::White => ".",
+            PieceColor::Black => "...",
+        };
+        format!(
+            "{}{} {}",
+            before.fullmove_number, dots, self.nodes[node].san
+        )
+    }
+
+    fn add_move(&mut self, parent: Option<usize>, san: &str) -> Result<usize, PgnError> {
+        let before = self.position(parent);
+        let m = notation::parse_move(before, san).map_err(|error| PgnError::Move {
+            san: san.to_string(),
+            error,
+        })?;
+
+        let san = notation::san(before, m);
+        let mut position = before.clone();
+        position.make_move(m);
+
+        let index = self.nodes.len();
+        self.nodes.push(MoveNode {
+            m,
+            san,
+            position,
+            parent,
+            children: Vec::new(),
+            comment: None,
+        });
+        match parent {
+            Some(i) => self.nodes[i].children.push(index),
+            None => self.first_moves.push(index),
+        }
+
+        Ok(index)
+    }
+
+    fn add_comment(&mut self, node: Option<usize>, text: &str) {
+        let comment = match node {
+            Some(i) => &mut self.nodes[i].comment,
+            None => &mut self.comment,
+        };
+        match comment {
+            Some(existing) => {
+                existing.push(' ');
+                existing.push_str(text);
+            }
+            None => *comment = Some(text.to_string()),
+        }
+    }
+}
+
+/// Why a PGN file could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    /// The `FEN` tag is not a valid position.
+    Fen(FenError),
+    /// A move which cannot be played where it appears.
+    Move { san: String, error: MoveError },
+    /// A tag, comment or variation which is not closed.
+    Unclosed(&'static str),
+    /// A variation closed without being opened, or opened before any move.
+    MisplacedVariation,
+    /// No game in the text.
+    Empty,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fen(err) => err.fmt(f),
+            Self::Move { san, error } => write!(f, "{}: {}", san, error),
+            Self::Unclosed(what) => write!(f, "unclosed {}", what),
+            Self::MisplacedVariation => f.write_str("misplaced variation"),
+            Self::Empty => f.write_str("no game found"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Tag(&'a str, String),
+    Comment(&'a str),
+    VariationStart,
+    VariationEnd,
+    Word(&'a str),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token<'_>>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let at_line_start =
+            text.len() == rest.len() || text[..text.len() - rest.len()].ends_with('\n');
+
+        match c {
+            _ if c.is_whitespace() => rest = &rest[c.len_utf8()..],
+            // Escaped lines and rest of line comments
+            '%' if at_line_start => rest = rest.find('\n').map_or("", |end| &rest[end..]),
+            ';' => rest = rest.find('\n').map_or("", |end| &rest[end..]),
+            '{' => {
+                let end = rest.find('}').ok_or(PgnError::Unclosed("comment"))?;
+                tokens.push(Token::Comment(rest[1..end].trim()));
+                rest = &rest[end + 1..];
+            }
+            '[' => {
+                let (tag, after) = read_tag(rest)?;
+                tokens.push(tag);
+                rest = after;
+            }
+            '(' => {
+                tokens.push(Token::VariationStart);
+                rest = &rest[1..];
+            }
+            ')' => {
+                tokens.push(Token::VariationEnd);
+                rest = &rest[1..];
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "{}[]();".contains(c))
+                    .unwrap_or(rest.len())
+                    .max(c.len_utf8());
+                // Numeric annotation glyphs carry nothing shown here
+                if !rest.starts_with('$') {
+                    tokens.push(Token::Word(&rest[..end]));
+                }
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Read the tag pair at the start of `text`, like `[Event "Casual game"]`,
+/// and return the text after it.
+fn read_tag(text: &str) -> Result<(Token<'_>, &str), PgnError> {
+    let unclosed = PgnError::Unclosed("tag");
+    let inside = &text[1..];
+    let open_quote = inside.find('"').ok_or(unclosed.clone())?;
+    let name = inside[..open_quote].trim();
+
+    let mut value = String::new();
+    let mut chars = inside[open_quote + 1..].char_indices();
+    let close_quote = loop {
+        match chars.next().ok_or(unclosed.clone())? {
+            (_, '\\') => value.extend(chars.next().map(|(_, c)| c)),
+            (i, '"') => break open_quote + 1 + i,
+            (_, c) => value.push(c),
+        }
+    };
+
+    let after = &inside[close_quote + 1..];
+    let end = after.find(']').ok_or(unclosed)?;
+    Ok((Token::Tag(name, value), &after[end + 1..]))
+}
+
+fn is_result(word: &str) -> bool {
+    matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+/// Read every game of a PGN file.
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut moves_started = false;
+    let mut current = None;
+    let mut variations = Vec::new();
+
+    let mut finish = |game: &mut PgnGame, variations: &mut Vec<Option<usize>>| {
+        if !variations.is_empty() {
+            return Err(PgnError::Unclosed("variation"));
+        }
+        games.push(std::mem::take(game));
+        Ok(())
+    };
+
+    for token in tokenize(text)? {
+        if !moves_started && !matches!(token, Token::Tag(..)) {
+            moves_started = true;
+            if let Some(fen) = game.tag("FEN") {
+                game.start = notation::parse_fen(fen).map_err(PgnError::Fen)?;
+            }
+        }
+
+        match token {
+            Token::Tag(name, value) => {
+                if moves_started {
+                    // A game without a result
+                    finish(&mut game, &mut variations)?;
+                    moves_started = false;
+                    current = None;
+                }
+                game.tags.push((name.to_string(), value));
+            }
+            Token::Comment(text) => game.add_comment(current, text),
+            Token::VariationStart => {
+                // The variation replaces the move just played
+                let Some(replaced) = current else {
+                    return Err(PgnError::MisplacedVariation);
+                };
+                variations.push(current);
+                current = game.nodes[replaced].parent;
+            }
+            Token::VariationEnd => {
+                current = variations.pop().ok_or(PgnError::MisplacedVariation)?;
+            }
+            Token::Word(word) if is_result(word) => {
+                if game.tag("Result").is_none() {
+                    game.tags.push(("Result".to_string(), word.to_string()));
+                }
+                finish(&mut game, &mut variations)?;
+                moves_started = false;
+                current = None;
+            }
+            Token::Word(word) => {
+                // Move numbers, alone or written against the move
+                let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if !san.is_empty() {
+                    current = Some(game.add_move(current, san)?);
+                }
+            }
+        }
+    }
+
+    if moves_started {
+        finish(&mut game, &mut variations)?;
+    }
+    if games.is_empty() {
+        return Err(PgnError::Empty);
+    }
+
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = r#"
+[Event "Casual game"]
+[White "Human"]
+[Black "Computer \"level 3\""]
+[Result "1-0"]
+
+% This line is skipped
+{Opening} 1. e4 e5 2. Nf3 $1 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 ; a comment
+3.Bb5 {The Spanish [%cal Gb5c6]} a6 1-0
+
+[Event "Second"]
+
+1. d4 *
+"#;
+
+    #[test]
+    fn games_are_read_with_tags_comments_and_variations() {
+        let games = parse_pgn(GAME).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tag("Black"), Some("Computer \"level 3\""));
+        assert_eq!(game.comment(None), Some("Opening"));
+
+        let end = game.line_end(None).unwrap();
+        let main_line: Vec<&str> = game
+            .line(Some(end))
+            .into_iter()
+            .map(|i| game.nodes[i].san.as_str())
+            .collect();
+        assert_eq!(main_line, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(game.numbered_move(end), "3... a6");
+
+        let bishop = game.line(Some(end))[4];
+        assert_eq!(game.comment(Some(bishop)), Some("The Spanish [%cal Gb5c6]"));
+
+        // 2. f4 is a variation of 2. Nf3, 2... d5 one of 2... exf4
+        let e5 = game.line(Some(end))[1];
+        let [knight, f4] = game.children(Some(e5)) else {
+            panic!("expected two moves after 1... e5");
+        };
+        assert_eq!(game.nodes[*knight].san, "Nf3");
+        assert_eq!(game.nodes[*f4].san, "f4");
+        let replies: Vec<&str> = game
+            .children(Some(*f4))
+            .iter()
+            .map(|&i| game.nodes[i].san.as_str())
+            .collect();
+        assert_eq!(replies, ["exf4", "d5"]);
+
+        assert_eq!(games[1].tag("Result"), Some("*"));
+        assert_eq!(games[1].first_moves.len(), 1);
+    }
+
+    #[test]
+    fn games_start_from_their_fen() {
+        let games =
+            parse_pgn("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b - - 0 40\"]\n\n40... Kd7 *")
+                .unwrap();
+        assert_eq!(games[0].numbered_move(0), "40... Kd7");
+    }
+
+    #[test]
+    fn broken_games_say_what_is_wrong() {
+        assert_eq!(
+            parse_pgn("1. e4 e5 2. Ke3 *").unwrap_err(),
+            PgnError::Move {
+                san: "Ke3".to_string(),
+                error: MoveError::Illegal
+            }
+        );
+        assert_eq!(
+            parse_pgn("1. e4 (1. d4 *").unwrap_err(),
+            PgnError::Unclosed("variation")
+        );
+        assert_eq!(
+            parse_pgn("1. e4 {unfinished").unwrap_err(),
+            PgnError::Unclosed("comment")
+        );
+        assert_eq!(parse_pgn("  \n").unwrap_err(), PgnError::Empty);
+        assert_eq!(
+            parse_pgn("1. e4 } *").unwrap_err(),
+            PgnError::Move {
+                san: "}".to_string(),
+                error: MoveError::Invalid
+            }
+        );
+    }
+}
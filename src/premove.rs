@@ -0,0 +1,312 @@
+//! Premoves: moves chosen while the computer is thinking, played as soon as
+//! it is the player's turn again if they are still legal. A right click
+//! cancels them. With multiple premoves enabled (`M`), several can be
+//! queued, each from the position the previous ones lead to.
+
+use bevy::{log, prelude::*};
+
+use crate::{
+    animation::AnimationSpeed,
+    clock::ChessClock,
+    find_move,
+    highlight::{HighlightLayer, Highlights},
+    highlight_possible_moves,
+    history::GameHistory,
+    menu::GameSetup,
+    move_piece,
+    rules::{Move, PieceColor, PieceType, Position},
+    select_tile,
+    settings::Settings,
+    AppState, Board, GameOutcome, PromotionPiece, SelectSquare,
+};
+
+/// Moves queued to be played on the next turns, and the piece picked for
+/// the next one.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct Premoves {
+    queue: Vec<Move>,
+    /// Where the piece picked could go.
+    selected: Option<Vec<Move>>,
+}
+
+pub struct PremovePlugin;
+
+impl Plugin for PremovePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Premoves>()
+            .add_system(clear_premoves.in_schedule(OnEnter(AppState::InGame)))
+            .add_systems(
+                (
+                    toggle_multiple_premoves,
+                    // Before the moves of the player's turn, so that a
+                    // click is not taken for both
+                    select_premove.before(select_tile),
+                    cancel_premoves,
+                    play_premove,
+                    show_premoves,
+                )
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn clear_premoves(mut premoves: ResMut<Premoves>) {
+    *premoves = Premoves::default();
+}
+
+/// The position as it would be if the computer passed and the queued
+/// premoves were played, which the next premove is chosen from.
+fn premove_position(position: &Position, human: PieceColor, queue: &[Move]) -> Position {
+    let mut position = position.clone();
+    position.en_passant = None;
+
+    for &m in queue {
+        position.side_to_move = human;
+        position.make_move(m);
+    }
+    position.side_to_move = human;
+
+    position
+}
+
+fn toggle_multiple_premoves(keys: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keys.just_pressed(KeyCode::M) {
+        settings.multiple_premoves = !settings.multiple_premoves;
+        log::info!("Multiple premoves: {}", settings.multiple_premoves);
+    }
+}
+
+/// Pick a piece and the square it goes to while the computer is to move,
+/// the same way moves are chosen on the player's turn.
+#[allow(clippy::too_many_arguments)]
+fn select_premove(
+    mut selections: EventReader<SelectSquare>,
+    setup: Res<GameSetup>,
+    settings: Res<Settings>,
+    promotion: Res<PromotionPiece>,
+    board: Res<Board>,
+    clock: Res<ChessClock>,
+    outcome: Res<GameOutcome>,
+    mut premoves: ResMut<Premoves>,
+    mut highlights: ResMut<Highlights>,
+) {
+    let Some((computer, _)) = setup.computer() else {
+        selections.clear();
+        return;
+    };
+    let human = computer.opposite();
+
+    for &SelectSquare {
+        x,
+        y,
+        promotion: chosen_promotion,
+    } in selections.iter()
+    {
+        if outcome.0.is_some() || clock.paused || board.position.side_to_move != computer {
+            continue;
+        }
+
+        if let Some(moves) = premoves.selected.take() {
+            highlights.clear(HighlightLayer::Selection);
+            highlights.clear(HighlightLayer::QuietMove);
+            highlights.clear(HighlightLayer::Capture);
+
+            let promotion = match chosen_promotion {
+                Some(piece_type) => piece_type,
+                None if settings.auto_queen => PieceType::Queen,
+                None => promotion.0,
+            };
+
+            if let Some(m) = find_move(&moves, x, y, promotion) {
+                if !settings.multiple_premoves {
+                    premoves.queue.clear();
+                }
+                log::info!("Premove {:?} to {:?}", m.from(), m.to());
+                premoves.queue.push(m);
+                continue;
+            }
+        }
+
+        let position = premove_position(&board.position, human, &premoves.queue);
+        let Some(piece) = position.piece_at(x, y).filter(|p| p.piece_color == human) else {
+            continue;
+        };
+
+        let moves = piece.premove_targets(&position);
+        highlights.set(HighlightLayer::Selection, [(x, y)]);
+        highlight_possible_moves(&moves, &mut highlights);
+        premoves.selected = Some(moves);
+    }
+}
+
+/// Cancel the premoves on a right click, which is then not taken for
+/// drawing on the board.
+pub(crate) fn cancel_premoves(
+    mut mouse: ResMut<Input<MouseButton>>,
+    mut premoves: ResMut<Premoves>,
+    mut highlights: ResMut<Highlights>,
+) {
+    if !mouse.just_pressed(MouseButton::Right) {
+        return;
+    }
+    if premoves.queue.is_empty() && premoves.selected.is_none() {
+        return;
+    }
+
+    if premoves.selected.take().is_some() {
+        highlights.clear(HighlightLayer::Selection);
+        highlights.clear(HighlightLayer::QuietMove);
+        highlights.clear(HighlightLayer::Capture);
+    }
+    premoves.queue.clear();
+    mouse.clear_just_pressed(MouseButton::Right);
+    log::info!("Premoves cancelled");
+}
+
+/// Play the first premove once it is the player's turn, or drop the queue
+/// if it is not legal anymore. A piece still picked for a premove is let go.
+#[allow(clippy::too_many_arguments)]
+fn play_premove(
+    mut commands: Commands,
+    animation_speed: Res<AnimationSpeed>,
+    setup: Res<GameSetup>,
+    clock: Res<ChessClock>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<GameHistory>,
+    mut outcome: ResMut<GameOutcome>,
+    mut premoves: ResMut<Premoves>,
+    mut highlights: ResMut<Highlights>,
+) {
+    let Some((computer, _)) = setup.computer() else {
+        return;
+    };
+    if board.position.side_to_move == computer || clock.paused {
+        return;
+    }
+
+    if premoves.selected.is_some() {
+        premoves.selected = None;
+        highlights.clear(HighlightLayer::Selection);
+        highlights.clear(HighlightLayer::QuietMove);
+        highlights.clear(HighlightLayer::Capture);
+    }
+    if premoves.queue.is_empty() {
+        return;
+    }
+    if outcome.0.is_some() {
+        premoves.queue.clear();
+        return;
+    }
+
+    let premove = premoves.queue.remove(0);
+    let legal = board
+        .position
+        .legal_moves_from(premove.from_x, premove.from_y)
+        .into_iter()
+        .find(|m| m.to() == premove.to() && m.promotion == premove.promotion);
+
+    match legal {
+        Some(m) => move_piece(
+            &mut commands,
+            *animation_speed,
+            m,
+            &mut board,
+            &mut history,
+            &mut outcome,
+        ),
+        None => {
+            log::info!(
+                "Premove {:?} to {:?} is not legal",
+                premove.from(),
+                premove.to()
+            );
+            premoves.queue.clear();
+        }
+    }
+}
+
+fn show_premoves(premoves: Res<Premoves>, mut highlights: ResMut<Highlights>) {
+    if !premoves.is_changed() {
+        return;
+    }
+
+    if premoves.queue.is_empty() {
+        highlights.clear(HighlightLayer::Premove);
+    } else {
+        highlights.set(
+            HighlightLayer::Premove,
+            premoves.queue.iter().flat_map(|m| [m.from(), m.to()]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        annotations::{AnnotationColor, Annotations},
+        notation::{parse_fen, parse_square},
+        rules::MoveType,
+    };
+
+    #[test]
+    fn premoves_are_chosen_from_the_position_they_lead_to() {
+        let position = parse_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        let rook_up = Move::new((0, 0), 4, 0, MoveType::Move);
+
+        let after = premove_position(&position, PieceColor::White, &[rook_up]);
+        assert_eq!(after.side_to_move, PieceColor::White);
+
+        let (x, y) = parse_square("a5").unwrap();
+        let rook = after.piece_at(x, y).unwrap();
+        assert!(rook
+            .possible_moves(&after)
+            .iter()
+            .any(|m| m.to() == parse_square("h5").unwrap()));
+    }
+
+    /// Right click on e4 with `queue` as the premoves, and return the
+    /// premoves and drawings left.
+    fn right_click(queue: Vec<Move>) -> (Premoves, Annotations) {
+        let e4 = parse_square("e4").unwrap();
+        let follow = move |mouse: Res<Input<MouseButton>>, mut annotations: ResMut<Annotations>| {
+            annotations.follow_right_button(&mouse, Some(e4), 0, AnnotationColor::Green);
+        };
+
+        let mut app = App::new();
+        app.init_resource::<Input<MouseButton>>()
+            .init_resource::<Highlights>()
+            .init_resource::<Annotations>()
+            .insert_resource(Premoves {
+                queue,
+                selected: None,
+            })
+            .add_systems((cancel_premoves, follow).chain());
+
+        let mut mouse = app.world.resource_mut::<Input<MouseButton>>();
+        mouse.press(MouseButton::Right);
+        app.update();
+        let mut mouse = app.world.resource_mut::<Input<MouseButton>>();
+        mouse.clear();
+        mouse.release(MouseButton::Right);
+        app.update();
+
+        let premoves = app.world.remove_resource::<Premoves>().unwrap();
+        let annotations = app.world.remove_resource::<Annotations>().unwrap();
+        (premoves, annotations)
+    }
+
+    #[test]
+    fn a_right_click_cancelling_premoves_draws_nothing() {
+        let rook_up = Move::new((0, 0), 4, 0, MoveType::Move);
+        let (premoves, annotations) = right_click(vec![rook_up]);
+        assert!(premoves.queue.is_empty());
+        assert!(annotations.drawing(0).is_none());
+
+        // Without premoves the click circles the square
+        let (_, annotations) = right_click(Vec::new());
+        let circled: Vec<_> = annotations.drawing(0).unwrap().circled().collect();
+        assert_eq!(circled, [parse_square("e4").unwrap()]);
+    }
+}
@@ -0,0 +1,785 @@
+//! Tactics puzzles in the Lichess puzzle CSV format, read from the file set
+//! in the settings or from the few that come with the game. The opponent's
+//! first move sets the puzzle up, then each move of the player must be the
+//! one of the solution, or any checkmate, and the opponent answers it. `H`
+//! points at the piece to move, then at its square, and `N` skips to the
+//! next puzzle. Puzzles solved without a hint raise the local puzzle rating
+//! and the streak, which are kept in `puzzles.toml` next to the settings.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use bevy::{log, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::AnimationSpeed,
+    clock::ChessClock,
+    highlight::{HighlightLayer, Highlights},
+    history::{GameHistory, HistoryEntry},
+    layout::{ACTIONS_PANEL_HEIGHT, SIDE_PANEL_WIDTH},
+    menu::{GameMode, GameSetup},
+    move_piece,
+    notation::{self, FenError, MoveError},
+    rules::{Move, PieceColor, Position},
+    settings::Settings,
+    start_game, AppState, Board, GameEntity, GameOutcome, FONT,
+};
+
+/// Puzzles played when no puzzle file is set.
+const BUNDLED_PUZZLES: &str = include_str!("../assets/puzzles.csv");
+
+const STATS_FILE_NAME: &str = "puzzles.toml";
+
+/// Puzzles read from a file at most. The Lichess database is sorted by
+/// puzzle id, so the first ones are a random sample of all ratings.
+const MAX_PUZZLES: usize = 10_000;
+
+/// Time before the opponent moves, so that the player sees the move coming.
+const MOVE_DELAY: f32 = 0.6;
+
+const START_RATING: f32 = 1500.;
+
+/// Largest change of the rating for one puzzle, as with Elo ratings.
+const RATING_K: f32 = 32.;
+
+const PANEL_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const THEMES_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+const SOLVED_COLOR: Color = Color::rgb(0.4, 0.85, 0.4);
+const FAILED_COLOR: Color = Color::rgb(0.95, 0.3, 0.2);
+
+#[derive(Debug, Clone)]
+struct Puzzle {
+    id: String,
+    /// Position before the setup move.
+    position: Position,
+    /// The setup move, then the moves of the player and the answers of the
+    /// opponent in turn.
+    moves: Vec<Move>,
+    rating: u32,
+    themes: Vec<String>,
+}
+
+impl Puzzle {
+    /// Side the puzzle is solved for, the one not making the setup move.
+    fn player(&self) -> PieceColor {
+        self.position.side_to_move.opposite()
+    }
+
+    /// Whether the move recorded in `entry` as the move at `ply` is the one
+    /// of the solution. Any checkmate solves the puzzle too.
+    fn is_answer(&self, ply: usize, entry: &HistoryEntry) -> bool {
+        self.moves.get(ply) == Some(&entry.m) || entry.position.is_checkmate()
+    }
+}
+
+/// Why a line of a puzzle file is not a puzzle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PuzzleError {
+    MissingField(&'static str),
+    Fen(FenError),
+    /// A move of the solution which cannot be played where it appears.
+    Move {
+        uci: String,
+        error: MoveError,
+    },
+    Rating(String),
+    /// Nothing to find after the setup move.
+    TooShort,
+}
+
+impl fmt::Display for PuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(name) => write!(f, "missing {}", name),
+            Self::Fen(err) => err.fmt(f),
+            Self::Move { uci, error } => write!(f, "{}: {}", uci, error),
+            Self::Rating(text) => write!(f, "invalid rating {}", text),
+            Self::TooShort => f.write_str("no move to find"),
+        }
+    }
+}
+
+impl std::error::Error for PuzzleError {}
+
+/// Read a line of the Lichess puzzle database: id, FEN, moves in UCI,
+/// rating, rating deviation, popularity, number of plays and themes,
+/// followed by fields which are not used.
+fn parse_puzzle(line: &str) -> Result<Puzzle, PuzzleError> {
+    let mut fields = line.split(',').map(str::trim);
+    let mut field = |name| {
+        fields
+            .next()
+            .filter(|text| !text.is_empty())
+            .ok_or(PuzzleError::MissingField(name))
+    };
+
+    let id = field("puzzle id")?.to_string();
+    let position = notation::parse_fen(field("FEN")?).map_err(PuzzleError::Fen)?;
+    let solution = field("moves")?;
+    let rating_text = field("rating")?;
+    let rating = rating_text
+        .parse()
+        .map_err(|_| PuzzleError::Rating(rating_text.to_string()))?;
+    let themes = fields
+        .nth(3)
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+
+    let mut current = position.clone();
+    let mut moves = Vec::new();
+    for uci in solution.split_whitespace() {
+        let m = notation::parse_move(&current, uci).map_err(|error| PuzzleError::Move {
+            uci: uci.to_string(),
+            error,
+        })?;
+        current.make_move(m);
+        moves.push(m);
+    }
+    if moves.len() < 2 {
+        return Err(PuzzleError::TooShort);
+    }
+
+    Ok(Puzzle {
+        id,
+        position,
+        moves,
+        rating,
+        themes,
+    })
+}
+
+/// Puzzles of a CSV file, skipping the header and the lines which are not
+/// valid puzzles.
+fn read_puzzles(reader: impl BufRead) -> Vec<Puzzle> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with("PuzzleId"))
+        .filter_map(|(index, line)| match parse_puzzle(&line) {
+            Ok(puzzle) => Some(puzzle),
+            Err(err) => {
+                log::warn!("Skipping the puzzle on line {}: {}", index + 1, err);
+                None
+            }
+        })
+        .take(MAX_PUZZLES)
+        .collect()
+}
+
+/// Puzzles to choose from, read at startup.
+#[derive(Resource, Debug, Default)]
+struct Puzzles(Vec<Puzzle>);
+
+/// Rating and streak of the player, kept between runs.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct PuzzleStats {
+    rating: f32,
+    /// Puzzles solved in a row without a hint.
+    streak: u32,
+    best_streak: u32,
+    /// Ids of the puzzles played, which are not given again until all of
+    /// them have been.
+    played: BTreeSet<String>,
+}
+
+impl Default for PuzzleStats {
+    fn default() -> Self {
+        Self {
+            rating: START_RATING,
+            streak: 0,
+            best_streak: 0,
+            played: BTreeSet::new(),
+        }
+    }
+}
+
+impl PuzzleStats {
+    fn load_from(path: &Path) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                return Self::default();
+            }
+        };
+
+        toml::from_str(&text).unwrap_or_else(|err| {
+            log::warn!("Invalid puzzle stats in {}: {}", path.display(), err);
+            Self::default()
+        })
+    }
+
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self).map_err(std::io::Error::other)?;
+        fs::write(path, text)
+    }
+
+    fn save(&self) {
+        let Some(path) = stats_path() else {
+            return;
+        };
+        if let Err(err) = self.save_to(&path) {
+            log::error!("Failed to save puzzle stats to {}: {}", path.display(), err);
+        }
+    }
+
+    /// Unplayed puzzle rated closest to the player.
+    fn choose<'a>(&self, puzzles: &'a [Puzzle]) -> Option<&'a Puzzle> {
+        puzzles
+            .iter()
+            .filter(|puzzle| !self.played.contains(&puzzle.id))
+            .min_by_key(|puzzle| (puzzle.rating as f32 - self.rating).abs() as u32)
+    }
+
+    /// Count the result of the puzzle and return the change of the rating.
+    /// A hint leaves the rating alone but ends the streak.
+    fn record(&mut self, puzzle: &Puzzle, solved: bool, hinted: bool) -> f32 {
+        self.played.insert(puzzle.id.clone());
+
+        if solved && !hinted {
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.streak = 0;
+        }
+
+        if hinted {
+            return 0.;
+        }
+        let expected = 1. / (1. + 10_f32.powf((puzzle.rating as f32 - self.rating) / 400.));
+        let score = if solved { 1. } else { 0. };
+        let change = RATING_K * (score - expected);
+        self.rating += change;
+        change
+    }
+}
+
+fn stats_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(STATS_FILE_NAME))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PuzzleStatus {
+    #[default]
+    Solving,
+    Solved,
+    /// A wrong move was played or the puzzle was skipped.
+    Failed,
+}
+
+/// The puzzle on the board, if the game is a puzzle.
+#[derive(Resource, Debug, Default)]
+struct ActivePuzzle {
+    puzzle: Option<Puzzle>,
+    status: PuzzleStatus,
+    /// Moves of the game compared with the solution so far.
+    checked: usize,
+    /// Hints given for the move to find: the piece, then its square too.
+    hints: usize,
+    /// Whether any hint was given for the puzzle.
+    hinted: bool,
+    /// The move which should have been played, once a wrong one was.
+    answer: Option<String>,
+    rating_change: f32,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum PuzzleButton {
+    Hint,
+    Next,
+}
+
+#[derive(Component)]
+struct PuzzleStatusText;
+
+#[derive(Component)]
+struct PuzzleStatsText;
+
+pub struct PuzzlePlugin;
+
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Puzzles>()
+            .init_resource::<PuzzleStats>()
+            .init_resource::<ActivePuzzle>()
+            .add_startup_system(load_puzzles)
+            .add_system(
+                pick_puzzle
+                    .before(start_game)
+                    .in_schedule(OnEnter(AppState::InGame)),
+            )
+            .add_system(
+                spawn_puzzle_panel
+                    .after(pick_puzzle)
+                    .in_schedule(OnEnter(AppState::InGame)),
+            )
+            .add_systems(
+                (
+                    check_answers,
+                    play_opponent_moves,
+                    puzzle_controls,
+                    update_puzzle_panel,
+                )
+                    .chain()
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn load_puzzles(
+    settings: Res<Settings>,
+    mut puzzles: ResMut<Puzzles>,
+    mut stats: ResMut<PuzzleStats>,
+) {
+    if let Some(path) = stats_path() {
+        *stats = PuzzleStats::load_from(&path);
+    }
+
+    if !settings.puzzle_file.is_empty() {
+        match File::open(&settings.puzzle_file) {
+            Ok(file) => puzzles.0 = read_puzzles(BufReader::new(file)),
+            Err(err) => log::error!("Failed to open {}: {}", settings.puzzle_file, err),
+        }
+    }
+    if puzzles.0.is_empty() {
+        puzzles.0 = read_puzzles(BUNDLED_PUZZLES.as_bytes());
+    }
+    log::info!("{} puzzles to play", puzzles.0.len());
+}
+
+/// Set the next game up as the puzzle to play, if a puzzle was asked for.
+fn pick_puzzle(
+    mut setup: ResMut<GameSetup>,
+    puzzles: Res<Puzzles>,
+    mut stats: ResMut<PuzzleStats>,
+    mut active: ResMut<ActivePuzzle>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    *active = ActivePuzzle::default();
+    let GameMode::Puzzle { .. } = setup.mode else {
+        return;
+    };
+
+    // Start over once every puzzle has been played
+    if stats.choose(&puzzles.0).is_none() {
+        stats.played.clear();
+    }
+    let Some(puzzle) = stats.choose(&puzzles.0).cloned() else {
+        log::error!("No puzzles to play");
+        setup.mode = GameMode::HumanVsHuman;
+        setup.position = None;
+        app_state.set(AppState::MainMenu);
+        return;
+    };
+
+    log::info!("Puzzle {} rated {}", puzzle.id, puzzle.rating);
+    setup.mode = GameMode::Puzzle {
+        player: puzzle.player(),
+    };
+    setup.position = Some(puzzle.position.clone());
+    active.puzzle = Some(puzzle);
+}
+
+fn spawn_puzzle_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    active: Res<ActivePuzzle>,
+) {
+    let Some(puzzle) = &active.puzzle else {
+        return;
+    };
+
+    let font = asset_server.load(FONT);
+    let text_style = |color| TextStyle {
+        font: font.clone(),
+        font_size: 18.,
+        color,
+    };
+    let spawn_button = |parent: &mut ChildBuilder, button: PuzzleButton, label: &str| {
+        parent
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Auto, Val::Px(26.)),
+                        flex_grow: 1.,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: BUTTON_COLOR.into(),
+                    ..default()
+                },
+                button,
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, text_style(TEXT_COLOR)));
+            });
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(
+                        Val::Px(SIDE_PANEL_WIDTH - 20.),
+                        Val::Px(ACTIONS_PANEL_HEIGHT),
+                    ),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        bottom: Val::Px(10.),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::FlexEnd,
+                    padding: UiRect::all(Val::Px(8.)),
+                    gap: Size::all(Val::Px(6.)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                format!("Puzzle {}, rated {}", puzzle.id, puzzle.rating),
+                text_style(TEXT_COLOR),
+            ));
+            parent.spawn(
+                TextBundle::from_section(puzzle.themes.join(", "), text_style(THEMES_COLOR))
+                    .with_style(Style {
+                        max_size: Size::width(Val::Px(SIDE_PANEL_WIDTH - 36.)),
+                        ..default()
+                    }),
+            );
+            parent.spawn((
+                TextBundle::from_section("", text_style(TEXT_COLOR)),
+                PuzzleStatusText,
+            ));
+            parent.spawn((
+                TextBundle::from_section("", text_style(TEXT_COLOR)),
+                PuzzleStatsText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        gap: Size::all(Val::Px(6.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, PuzzleButton::Hint, "Hint");
+                    spawn_button(parent, PuzzleButton::Next, "Next");
+                });
+        });
+}
+
+/// Compare the moves played since the last frame with the solution, and
+/// count the result once the puzzle is solved or failed.
+fn check_answers(
+    history: Res<GameHistory>,
+    mut active: ResMut<ActivePuzzle>,
+    mut stats: ResMut<PuzzleStats>,
+    mut highlights: ResMut<Highlights>,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    let ActivePuzzle {
+        puzzle: Some(puzzle),
+        status,
+        checked,
+        hints,
+        hinted,
+        answer,
+        rating_change,
+    } = &mut *active
+    else {
+        return;
+    };
+
+    for (ply, entry) in history.moves.iter().enumerate().skip(*checked) {
+        *checked = ply + 1;
+        if *status != PuzzleStatus::Solving {
+            continue;
+        }
+        *hints = 0;
+        highlights.clear(HighlightLayer::Hint);
+
+        // Moves of the opponent come from the solution
+        let player_move = !ply.is_multiple_of(2);
+        if player_move && !puzzle.is_answer(ply, entry) {
+            let before = history.position_at(ply);
+            *answer = Some(notation::san(before, puzzle.moves[ply]));
+            *status = PuzzleStatus::Failed;
+        } else if entry.position.is_checkmate() || ply + 1 >= puzzle.moves.len() {
+            *status = PuzzleStatus::Solved;
+        } else {
+            continue;
+        }
+
+        let solved = *status == PuzzleStatus::Solved;
+        log::info!("Puzzle {} solved: {}", puzzle.id, solved);
+        *rating_change = stats.record(puzzle, solved, *hinted);
+        stats.save();
+    }
+}
+
+/// Play the setup move and the answers of the opponent, a moment after the
+/// move before them.
+#[allow(clippy::too_many_arguments)]
+fn play_opponent_moves(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut waited: Local<f32>,
+    active: Res<ActivePuzzle>,
+    animation_speed: Res<AnimationSpeed>,
+    clock: Res<ChessClock>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<GameHistory>,
+    mut outcome: ResMut<GameOutcome>,
+) {
+    let Some(puzzle) = &active.puzzle else {
+        return;
+    };
+    let ply = history.moves.len();
+    let opponent_to_move = ply.is_multiple_of(2) && active.checked == ply;
+    if active.status != PuzzleStatus::Solving || !opponent_to_move || clock.paused {
+        *waited = 0.;
+        return;
+    }
+    let Some(&m) = puzzle.moves.get(ply) else {
+        return;
+    };
+
+    *waited += time.delta_seconds();
+    if *waited < MOVE_DELAY {
+        return;
+    }
+    *waited = 0.;
+
+    move_piece(
+        &mut commands,
+        *animation_speed,
+        m,
+        &mut board,
+        &mut history,
+        &mut outcome,
+    );
+}
+
+/// Give hints and go to the next puzzle, from the buttons of the panel or
+/// with `H` and `N`.
+#[allow(clippy::type_complexity)]
+fn puzzle_controls(
+    keys: Res<Input<KeyCode>>,
+    mut buttons: Query<(&Interaction, &PuzzleButton, &mut BackgroundColor), Changed<Interaction>>,
+    history: Res<GameHistory>,
+    mut active: ResMut<ActivePuzzle>,
+    mut stats: ResMut<PuzzleStats>,
+    mut highlights: ResMut<Highlights>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if active.puzzle.is_none() {
+        return;
+    }
+
+    let mut pressed = Vec::new();
+    if keys.just_pressed(KeyCode::H) {
+        pressed.push(PuzzleButton::Hint);
+    }
+    if keys.just_pressed(KeyCode::N) {
+        pressed.push(PuzzleButton::Next);
+    }
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => pressed.push(*button),
+            Interaction::Hovered => *color = BUTTON_HOVERED_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+
+    let active = &mut *active;
+    let Some(puzzle) = &active.puzzle else {
+        return;
+    };
+    for button in pressed {
+        match button {
+            PuzzleButton::Hint => {
+                let ply = history.moves.len();
+                let Some(m) = puzzle.moves.get(ply) else {
+                    continue;
+                };
+                if active.status != PuzzleStatus::Solving || ply.is_multiple_of(2) {
+                    continue;
+                }
+
+                active.hints = (active.hints + 1).min(2);
+                active.hinted = true;
+                let squares = [m.from(), m.to()];
+                highlights.set(
+                    HighlightLayer::Hint,
+                    squares[..active.hints].iter().copied(),
+                );
+            }
+            PuzzleButton::Next => {
+                // Skipping a puzzle counts as failing it
+                if active.status == PuzzleStatus::Solving && !history.moves.is_empty() {
+                    active.status = PuzzleStatus::Failed;
+                    active.rating_change = stats.record(puzzle, false, active.hinted);
+                    stats.save();
+                }
+                app_state.set(AppState::InGame);
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_puzzle_panel(
+    active: Res<ActivePuzzle>,
+    stats: Res<PuzzleStats>,
+    history: Res<GameHistory>,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<PuzzleStatusText>>,
+        Query<&mut Text, With<PuzzleStatsText>>,
+    )>,
+) {
+    if !active.is_changed() && !stats.is_changed() && !history.is_changed() {
+        return;
+    }
+    let Some(puzzle) = &active.puzzle else {
+        return;
+    };
+
+    let (status, color) = match active.status {
+        PuzzleStatus::Solving if !history.moves.len().is_multiple_of(2) => (
+            format!("Find the best move for {:?}", puzzle.player()),
+            TEXT_COLOR,
+        ),
+        PuzzleStatus::Solving if history.moves.is_empty() => {
+            ("Watch the opponent's move".to_string(), TEXT_COLOR)
+        }
+        PuzzleStatus::Solving => ("Best move, keep going".to_string(), SOLVED_COLOR),
+        PuzzleStatus::Solved if active.hinted => ("Solved with a hint".to_string(), SOLVED_COLOR),
+        PuzzleStatus::Solved => (
+            format!("Solved! {:+.0}", active.rating_change),
+            SOLVED_COLOR,
+        ),
+        PuzzleStatus::Failed => (
+            match &active.answer {
+                Some(answer) => format!("Wrong, {} was the move", answer),
+                None => "Skipped".to_string(),
+            },
+            FAILED_COLOR,
+        ),
+    };
+    for mut text in texts.p0().iter_mut() {
+        text.sections[0].value = status.clone();
+        text.sections[0].style.color = color;
+    }
+
+    for mut text in texts.p1().iter_mut() {
+        text.sections[0].value = format!(
+            "Rating {:.0}  Streak {} (best {})",
+            stats.rating, stats.streak, stats.best_streak
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_puzzles_can_be_solved() {
+        let lines = BUNDLED_PUZZLES.lines().skip(1);
+        let puzzles = read_puzzles(BUNDLED_PUZZLES.as_bytes());
+        assert_eq!(puzzles.len(), lines.count());
+
+        for puzzle in &puzzles {
+            assert!(puzzle.moves.len().is_multiple_of(2), "{}", puzzle.id);
+
+            let mut position = puzzle.position.clone();
+            for &m in &puzzle.moves {
+                position.make_move(m);
+            }
+            if puzzle.themes.iter().any(|theme| theme == "mate") {
+                assert!(position.is_checkmate(), "{}", puzzle.id);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_lines_are_explained() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let puzzle = parse_puzzle(&format!("a1,{},e2e4 e7e5,1200,75,90,10,opening", start));
+        assert_eq!(puzzle.unwrap().themes, vec!["opening".to_string()]);
+
+        assert_eq!(
+            parse_puzzle("a2,,e2e4 e7e5,1200").unwrap_err(),
+            PuzzleError::MissingField("FEN")
+        );
+        assert_eq!(
+            parse_puzzle(&format!("a3,{},e2e4 e2e4,1200", start)).unwrap_err(),
+            PuzzleError::Move {
+                uci: "e2e4".to_string(),
+                error: MoveError::Illegal
+            }
+        );
+        assert_eq!(
+            parse_puzzle(&format!("a4,{},e2e4,1200", start)).unwrap_err(),
+            PuzzleError::TooShort
+        );
+        assert_eq!(
+            parse_puzzle(&format!("a5,{},e2e4 e7e5,high", start)).unwrap_err(),
+            PuzzleError::Rating("high".to_string())
+        );
+    }
+
+    #[test]
+    fn rating_follows_results() {
+        let puzzles = read_puzzles(BUNDLED_PUZZLES.as_bytes());
+        let mut stats = PuzzleStats::default();
+
+        // The hardest puzzle is the closest to a new player
+        let first = stats.choose(&puzzles).unwrap();
+        assert_eq!(first.rating, 1250);
+        let gain = stats.record(first, true, false);
+        assert!(gain > 0. && gain < RATING_K / 2.);
+        assert_eq!(stats.streak, 1);
+        assert_ne!(stats.choose(&puzzles).unwrap().id, first.id);
+
+        let rating = stats.rating;
+        let second = stats.choose(&puzzles).unwrap();
+        assert_eq!(stats.record(second, true, true), 0.);
+        assert_eq!(
+            (stats.rating, stats.streak, stats.best_streak),
+            (rating, 0, 1)
+        );
+
+        let third = stats.choose(&puzzles).unwrap();
+        assert!(stats.record(third, false, false) < -RATING_K / 2.);
+        assert!(stats.rating < rating);
+    }
+}
@@ -0,0 +1,725 @@
+//! Replays of games read from PGN files, opened from the main menu or by
+//! passing the file on the command line. The arrow keys step through the
+//! moves, `Home` and `End` jump to the start and the end, `Space` plays the
+//! moves one after another and `[` and `]` change how fast. `Up` and `Down`
+//! switch between a move and its side variations.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{log, prelude::*};
+
+use crate::{
+    animation::PIECE_Z,
+    highlight::{HighlightLayer, Highlights},
+    history,
+    layout::{BoardLayout, SIDE_PANEL_WIDTH},
+    pgn::{self, PgnGame},
+    rules::PieceColor,
+    settings::Settings,
+    spawn_piece, spawn_tiles,
+    theme::BoardTheme,
+    AppState, GameEntity, FONT,
+};
+
+/// Seconds between moves played automatically, from slowest to fastest.
+const DELAYS: [f32; 5] = [5., 3., 2., 1., 0.5];
+
+/// Saved games listed when no file is given.
+const LISTED_FILES: usize = 12;
+
+/// Moves of the line shown before and after the current one.
+const SHOWN_MOVES: usize = 24;
+
+const PANEL_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const DIM_TEXT_COLOR: Color = Color::rgb(0.55, 0.55, 0.55);
+const CURRENT_MOVE_COLOR: Color = Color::rgb(0.95, 0.8, 0.3);
+const ERROR_COLOR: Color = Color::rgb(0.95, 0.3, 0.2);
+
+/// PGN file to open when the replay starts, given on the command line.
+#[derive(Resource, Debug, Default)]
+pub struct ReplayFile(pub Option<PathBuf>);
+
+/// The game being replayed and the move shown.
+#[derive(Resource, Debug)]
+struct Replay {
+    game: Option<PgnGame>,
+    /// Move after which the board is shown, `None` for the start position.
+    node: Option<usize>,
+    autoplay: bool,
+    /// Index in [`DELAYS`].
+    speed: usize,
+    error: Option<String>,
+    /// Saved games to choose from while no game is open.
+    files: Vec<PathBuf>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            game: None,
+            node: None,
+            autoplay: false,
+            speed: 3,
+            error: None,
+            files: Vec::new(),
+        }
+    }
+}
+
+impl Replay {
+    fn open(&mut self, path: &Path) {
+        let result = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| pgn::parse_pgn(&text).map_err(|err| err.to_string()));
+
+        match result {
+            Ok(mut games) => {
+                if games.len() > 1 {
+                    log::info!(
+                        "{} has {} games, replaying the first",
+                        path.display(),
+                        games.len()
+                    );
+                }
+                log::info!("Replaying {}", path.display());
+                self.game = Some(games.swap_remove(0));
+                self.node = None;
+                self.error = None;
+            }
+            Err(err) => {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                self.error = Some(format!("{}: {}", path.display(), err));
+            }
+        }
+    }
+
+    fn go_to(&mut self, node: Option<usize>) {
+        if self.node != node {
+            self.node = node;
+        }
+    }
+
+    fn forward(&mut self) {
+        let next = self
+            .game
+            .as_ref()
+            .and_then(|game| game.children(self.node).first().copied());
+        if next.is_some() {
+            self.go_to(next);
+        }
+    }
+
+    fn back(&mut self) {
+        let previous = self.game.as_ref().and_then(|game| game.parent(self.node));
+        if self.node.is_some() {
+            self.go_to(previous);
+        }
+    }
+
+    fn end(&mut self) {
+        let end = self.game.as_ref().and_then(|game| game.line_end(self.node));
+        self.go_to(end);
+    }
+
+    /// Switch to the next or previous move played instead of the current
+    /// one, in a side variation or the main line.
+    fn switch_variation(&mut self, step: isize) {
+        let (Some(game), Some(node)) = (&self.game, self.node) else {
+            return;
+        };
+        let siblings = game.children(game.parent(Some(node)));
+        let Some(index) = siblings.iter().position(|&i| i == node) else {
+            return;
+        };
+
+        let next = (index as isize + step).rem_euclid(siblings.len() as isize) as usize;
+        let sibling = siblings[next];
+        self.go_to(Some(sibling));
+    }
+
+    fn set_speed(&mut self, speed: usize) {
+        self.speed = speed.min(DELAYS.len() - 1);
+    }
+}
+
+/// Saved games, the newest first.
+fn saved_games() -> Vec<PathBuf> {
+    let Some(entries) = history::export_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pgn"))
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, path)
+        })
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    files
+        .into_iter()
+        .take(LISTED_FILES)
+        .map(|(_, path)| path)
+        .collect()
+}
+
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+enum ReplayButton {
+    Start,
+    Back,
+    Play,
+    Forward,
+    End,
+    Slower,
+    Faster,
+    Variation(usize),
+    Open(PathBuf),
+}
+
+/// Sprite of a piece of the position replayed.
+#[derive(Component)]
+struct ReplayPiece;
+
+#[derive(Component)]
+struct TagsText;
+
+#[derive(Component)]
+struct MovesText;
+
+#[derive(Component)]
+struct CommentText;
+
+#[derive(Component)]
+struct SpeedText;
+
+#[derive(Component)]
+struct PlayText;
+
+/// Row of the buttons of the moves which can be played next.
+#[derive(Component)]
+struct VariationList;
+
+/// Column of the saved games to choose from.
+#[derive(Component)]
+struct FileList;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayFile>()
+            .init_resource::<Replay>()
+            .add_startup_system(open_replay_file)
+            .add_system(enter_replay.in_schedule(OnEnter(AppState::Replay)))
+            .add_systems(
+                (
+                    replay_keys,
+                    replay_buttons,
+                    autoplay,
+                    show_replay_position,
+                    update_replay_panel,
+                )
+                    .chain()
+                    .in_set(OnUpdate(AppState::Replay)),
+            );
+    }
+}
+
+/// Start with the replay of the file given on the command line.
+fn open_replay_file(file: Res<ReplayFile>, mut app_state: ResMut<NextState<AppState>>) {
+    if file.0.is_some() {
+        app_state.set(AppState::Replay);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn enter_replay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    theme: Res<BoardTheme>,
+    mut layout: ResMut<BoardLayout>,
+    mut highlights: ResMut<Highlights>,
+    mut file: ResMut<ReplayFile>,
+    mut replay: ResMut<Replay>,
+) {
+    *replay = Replay::default();
+    if let Some(path) = file.0.take() {
+        replay.open(&path);
+    }
+    replay.files = saved_games();
+
+    *highlights = Highlights::default();
+    layout.flipped = settings.flipped;
+    spawn_tiles(&mut commands, &layout, &theme);
+    spawn_replay_panel(&mut commands, &asset_server);
+}
+
+fn spawn_replay_panel(commands: &mut Commands, asset_server: &AssetServer) {
+    let font = asset_server.load(FONT);
+    let text_style = |size, color| TextStyle {
+        font: font.clone(),
+        font_size: size,
+        color,
+    };
+    let row = || NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::Wrap,
+            align_items: AlignItems::Center,
+            gap: Size::all(Val::Px(4.)),
+            ..default()
+        },
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(SIDE_PANEL_WIDTH - 20.), Val::Auto),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        top: Val::Px(56.),
+                        bottom: Val::Px(10.),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8.)),
+                    gap: Size::all(Val::Px(8.)),
+                    overflow: Overflow::Hidden,
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", text_style(16., DIM_TEXT_COLOR)),
+                TagsText,
+            ));
+
+            parent.spawn(row()).with_children(|parent| {
+                for (button, label) in [
+                    (ReplayButton::Start, "|<"),
+                    (ReplayButton::Back, "<"),
+                    (ReplayButton::Play, ""),
+                    (ReplayButton::Forward, ">"),
+                    (ReplayButton::End, ">|"),
+                ] {
+                    let is_play = button == ReplayButton::Play;
+                    spawn_button(parent, button, |parent| {
+                        let mut text = parent
+                            .spawn(TextBundle::from_section(label, text_style(18., TEXT_COLOR)));
+                        if is_play {
+                            text.insert(PlayText);
+                        }
+                    });
+                }
+            });
+
+            parent.spawn(row()).with_children(|parent| {
+                spawn_button(parent, ReplayButton::Slower, |parent| {
+                    parent.spawn(TextBundle::from_section("-", text_style(18., TEXT_COLOR)));
+                });
+                parent.spawn((
+                    TextBundle::from_section("", text_style(16., TEXT_COLOR)),
+                    SpeedText,
+                ));
+                spawn_button(parent, ReplayButton::Faster, |parent| {
+                    parent.spawn(TextBundle::from_section("+", text_style(18., TEXT_COLOR)));
+                });
+            });
+
+            parent.spawn((row(), VariationList));
+
+            parent.spawn((
+                TextBundle::from_section("", text_style(16., CURRENT_MOVE_COLOR)),
+                CommentText,
+            ));
+
+            parent.spawn((
+                TextBundle::from_sections([
+                    TextSection::new("", text_style(18., TEXT_COLOR)),
+                    TextSection::new("", text_style(18., CURRENT_MOVE_COLOR)),
+                    TextSection::new("", text_style(18., DIM_TEXT_COLOR)),
+                ]),
+                MovesText,
+            ));
+
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        gap: Size::all(Val::Px(4.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                FileList,
+            ));
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    button: ReplayButton,
+    label: impl FnOnce(&mut ChildBuilder),
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Auto, Val::Px(28.)),
+                    min_size: Size::new(Val::Px(36.), Val::Auto),
+                    padding: UiRect::horizontal(Val::Px(6.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            button,
+        ))
+        .with_children(label);
+}
+
+fn replay_keys(keys: Res<Input<KeyCode>>, mut replay: ResMut<Replay>) {
+    for key in keys.get_just_pressed() {
+        match key {
+            KeyCode::Right => replay.forward(),
+            KeyCode::Left => replay.back(),
+            KeyCode::Home => replay.go_to(None),
+            KeyCode::End => replay.end(),
+            KeyCode::Down => replay.switch_variation(1),
+            KeyCode::Up => replay.switch_variation(-1),
+            KeyCode::Space => replay.autoplay = !replay.autoplay,
+            KeyCode::LBracket => {
+                let speed = replay.speed.saturating_sub(1);
+                replay.set_speed(speed);
+            }
+            KeyCode::RBracket => {
+                let speed = replay.speed + 1;
+                replay.set_speed(speed);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn replay_buttons(
+    mut buttons: Query<(&Interaction, &ReplayButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut replay: ResMut<Replay>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {}
+            Interaction::Hovered => {
+                *color = BUTTON_HOVERED_COLOR.into();
+                continue;
+            }
+            Interaction::None => {
+                *color = BUTTON_COLOR.into();
+                continue;
+            }
+        }
+
+        match button {
+            ReplayButton::Start => replay.go_to(None),
+            ReplayButton::Back => replay.back(),
+            ReplayButton::Play => replay.autoplay = !replay.autoplay,
+            ReplayButton::Forward => replay.forward(),
+            ReplayButton::End => replay.end(),
+            ReplayButton::Slower => {
+                let speed = replay.speed.saturating_sub(1);
+                replay.set_speed(speed);
+            }
+            ReplayButton::Faster => {
+                let speed = replay.speed + 1;
+                replay.set_speed(speed);
+            }
+            ReplayButton::Variation(node) => replay.go_to(Some(*node)),
+            ReplayButton::Open(path) => replay.open(path),
+        }
+    }
+}
+
+/// Play the next move of the line every few seconds, stopping at its end.
+fn autoplay(time: Res<Time>, mut replay: ResMut<Replay>, mut waited: Local<f32>) {
+    if !replay.autoplay {
+        *waited = 0.;
+        return;
+    }
+
+    *waited += time.delta_seconds();
+    if *waited < DELAYS[replay.speed] {
+        return;
+    }
+    *waited = 0.;
+
+    let node = replay.node;
+    replay.forward();
+    if replay.node == node {
+        replay.autoplay = false;
+    }
+}
+
+/// Put the pieces of the position replayed on the board.
+#[allow(clippy::too_many_arguments)]
+fn show_replay_position(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<BoardTheme>,
+    layout: Res<BoardLayout>,
+    replay: Res<Replay>,
+    mut highlights: ResMut<Highlights>,
+    pieces: Query<Entity, With<ReplayPiece>>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+    let Some(game) = &replay.game else {
+        return;
+    };
+
+    for entity in pieces.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let position = game.position(replay.node);
+    for piece in position.pieces() {
+        let translation = layout.square_translation(piece.x, piece.y).extend(PIECE_Z);
+        let entity = spawn_piece(&mut commands, &asset_server, &theme, piece, translation);
+        commands.entity(entity).insert(ReplayPiece);
+    }
+
+    match replay.node {
+        Some(node) => {
+            let m = game.nodes[node].m;
+            highlights.set(HighlightLayer::LastMove, [m.from(), m.to()]);
+        }
+        None => highlights.clear(HighlightLayer::LastMove),
+    }
+
+    let side = position.side_to_move;
+    match position.king_square(side) {
+        Some(king) if position.is_in_check(side) => {
+            highlights.set(HighlightLayer::Check, [king]);
+        }
+        _ => highlights.clear(HighlightLayer::Check),
+    }
+}
+
+/// Moves of the line leading to the current one, the current one, and the
+/// main line after it, numbered as in PGN.
+fn line_text(game: &PgnGame, node: Option<usize>) -> [String; 3] {
+    let before = game.line(node);
+    let mut after = Vec::new();
+    let mut next = node;
+    while let Some(&child) = game.children(next).first() {
+        if after.len() == SHOWN_MOVES {
+            break;
+        }
+        after.push(child);
+        next = Some(child);
+    }
+
+    let write = |nodes: &[usize], text: &mut String, first: bool| {
+        for (i, &n) in nodes.iter().enumerate() {
+            let white_to_move =
+                game.position(game.nodes[n].parent).side_to_move == PieceColor::White;
+            if white_to_move || (first && i == 0) {
+                text.push_str(&game.numbered_move(n));
+            } else {
+                text.push_str(&game.nodes[n].san);
+            }
+            text.push(' ');
+        }
+    };
+
+    let start = before.len().saturating_sub(SHOWN_MOVES);
+    let (earlier, current) = match before.split_last() {
+        Some((&current, earlier)) => (&earlier[start.min(earlier.len())..], Some(current)),
+        None => (&before[..], None),
+    };
+
+    let mut texts = [String::new(), String::new(), String::new()];
+    if start > 0 {
+        texts[0].push_str("... ");
+    }
+    write(earlier, &mut texts[0], true);
+    if let Some(current) = current {
+        write(&[current], &mut texts[1], earlier.is_empty());
+    }
+    write(&after, &mut texts[2], false);
+
+    texts
+}
+
+/// Show the tags, the moves and the buttons of the game, or the saved games
+/// to choose from when none is open.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn update_replay_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    replay: Res<Replay>,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<TagsText>>,
+        Query<&mut Text, With<MovesText>>,
+        Query<&mut Text, With<CommentText>>,
+        Query<&mut Text, With<SpeedText>>,
+        Query<&mut Text, With<PlayText>>,
+    )>,
+    variation_lists: Query<Entity, With<VariationList>>,
+    file_lists: Query<Entity, With<FileList>>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+    let font = asset_server.load(FONT);
+    let style = |color| TextStyle {
+        font: font.clone(),
+        font_size: 16.,
+        color,
+    };
+
+    for mut text in texts.p3().iter_mut() {
+        text.sections[0].value = format!("{} s/move", DELAYS[replay.speed]);
+    }
+    for mut text in texts.p4().iter_mut() {
+        text.sections[0].value = if replay.autoplay { "||" } else { "Play" }.to_string();
+    }
+
+    for list in file_lists.iter() {
+        let mut list = commands.entity(list);
+        list.despawn_descendants();
+        if replay.game.is_some() {
+            continue;
+        }
+
+        list.with_children(|parent| {
+            if let Some(error) = &replay.error {
+                parent.spawn(TextBundle::from_section(error.clone(), style(ERROR_COLOR)));
+            }
+            let title = if replay.files.is_empty() {
+                "No saved games. Press E during a game to save it, or pass a PGN file on the command line."
+            } else {
+                "Saved games:"
+            };
+            parent.spawn(TextBundle::from_section(title, style(TEXT_COLOR)));
+
+            for path in &replay.files {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                spawn_button(parent, ReplayButton::Open(path.clone()), |parent| {
+                    parent.spawn(TextBundle::from_section(name, style(TEXT_COLOR)));
+                });
+            }
+        });
+    }
+
+    let Some(game) = &replay.game else {
+        return;
+    };
+
+    for mut text in texts.p0().iter_mut() {
+        text.sections[0].value = game
+            .tags
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    for mut text in texts.p1().iter_mut() {
+        for (section, value) in text.sections.iter_mut().zip(line_text(game, replay.node)) {
+            section.value = value;
+        }
+    }
+
+    for mut text in texts.p2().iter_mut() {
+        text.sections[0].value = game.comment(replay.node).unwrap_or_default().to_string();
+    }
+
+    // Choices of the next move when there are variations
+    let next_moves = game.children(replay.node);
+    for list in variation_lists.iter() {
+        let mut list = commands.entity(list);
+        list.despawn_descendants();
+        if next_moves.len() < 2 {
+            continue;
+        }
+
+        list.with_children(|parent| {
+            for &node in next_moves {
+                spawn_button(parent, ReplayButton::Variation(node), |parent| {
+                    parent.spawn(TextBundle::from_section(
+                        game.numbered_move(node),
+                        style(TEXT_COLOR),
+                    ));
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_steps_through_lines_and_variations() {
+        let mut replay = Replay {
+            game: Some(
+                pgn::parse_pgn("1. e4 e5 (1... c5 2. Nf3) 2. Nf3 *")
+                    .unwrap()
+                    .remove(0),
+            ),
+            ..default()
+        };
+        let san = |replay: &Replay| {
+            let game = replay.game.as_ref().unwrap();
+            replay.node.map(|node| game.nodes[node].san.clone())
+        };
+
+        replay.end();
+        assert_eq!(san(&replay).as_deref(), Some("Nf3"));
+
+        replay.back();
+        replay.switch_variation(1);
+        assert_eq!(san(&replay).as_deref(), Some("c5"));
+        replay.forward();
+        assert_eq!(san(&replay).as_deref(), Some("Nf3"));
+
+        let [before, current, after] = line_text(replay.game.as_ref().unwrap(), replay.node);
+        assert_eq!(
+            (before.as_str(), current.as_str(), after.as_str()),
+            ("1. e4 c5 ", "2. Nf3 ", "")
+        );
+
+        replay.go_to(None);
+        assert_eq!(san(&replay), None);
+        replay.back();
+        assert_eq!(san(&replay), None);
+    }
+}
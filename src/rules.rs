@@ -0,0 +1,1259 @@
+//! Rules of chess: how pieces move, check detection and the position on the
+//! board. Nothing in here knows about entities or sprites, so the same rules
+//! can be run on copies of the position.
+
+use std::{fmt, ops::ControlFlow};
+
+use bevy::prelude::Component;
+use serde::Deserialize;
+
+pub const COLS: usize = 8;
+pub const ROWS: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PieceColor {
+    White,
+    Black,
+}
+
+impl PieceColor {
+    #[inline]
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::White => Self::Black,
+            Self::Black => Self::White,
+        }
+    }
+
+    /// Direction in which the pawns of this color move along the rows.
+    #[inline]
+    const fn pawn_direction(self) -> isize {
+        match self {
+            Self::White => 1,
+            Self::Black => -1,
+        }
+    }
+
+    /// Row on which the pieces of this color start.
+    #[inline]
+    pub const fn back_row(self) -> usize {
+        match self {
+            Self::White => 0,
+            Self::Black => ROWS - 1,
+        }
+    }
+
+    /// Row on which the pawns of this color start.
+    #[inline]
+    pub const fn pawns_row(self) -> usize {
+        match self {
+            Self::White => 1,
+            Self::Black => ROWS - 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub enum PieceType {
+    Pawn,
+    Rook,
+    Knight,
+    Bishop,
+    Queen,
+    King,
+}
+
+/// A piece standing on row `x` and column `y` of the board.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    pub piece_type: PieceType,
+    pub piece_color: PieceColor,
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Piece {
+    pub const fn new(piece_type: PieceType, color: PieceColor, x: usize, y: usize) -> Self {
+        Self {
+            piece_type,
+            piece_color: color,
+            x,
+            y,
+        }
+    }
+
+    pub const fn rook(color: PieceColor, x: usize, y: usize) -> Self {
+        Self::new(PieceType::Rook, color, x, y)
+    }
+
+    pub const fn knight(color: PieceColor, x: usize, y: usize) -> Self {
+        Self::new(PieceType::Knight, color, x, y)
+    }
+
+    pub const fn bishop(color: PieceColor, x: usize, y: usize) -> Self {
+        Self::new(PieceType::Bishop, color, x, y)
+    }
+
+    pub const fn queen(color: PieceColor, x: usize, y: usize) -> Self {
+        Self::new(PieceType::Queen, color, x, y)
+    }
+
+    pub const fn king(color: PieceColor, x: usize, y: usize) -> Self {
+        Self::new(PieceType::King, color, x, y)
+    }
+
+    pub const fn pawn(color: PieceColor, x: usize, y: usize) -> Self {
+        Self::new(PieceType::Pawn, color, x, y)
+    }
+
+    /// Moves the piece could make if it did not matter whether its own king
+    /// is left in check.
+    #[inline]
+    pub fn possible_moves(&self, position: &Position) -> Vec<Move> {
+        self.piece_type
+            .possible_moves(self.piece_color, self.x, self.y, position)
+    }
+
+    /// Moves the piece might be able to make once the other side has moved,
+    /// whatever it does: lines run to the edge of the board, pawns may also
+    /// go diagonally, and any square can be the target, since the piece on
+    /// it may be taken or move away. Used to choose premoves, which are
+    /// checked when their turn comes.
+    pub fn premove_targets(&self, position: &Position) -> Vec<Move> {
+        let (x, y, color) = (self.x, self.y, self.piece_color);
+        let lines = |directions: &[(isize, isize)]| {
+            directions
+                .iter()
+                .flat_map(|&(dx, dy)| {
+                    std::iter::successors(offset(x, y, dx, dy), move |&(x, y)| offset(x, y, dx, dy))
+                })
+                .collect::<Vec<_>>()
+        };
+        let around = |offsets: &[(isize, isize)]| {
+            offsets
+                .iter()
+                .filter_map(|&(dx, dy)| offset(x, y, dx, dy))
+                .collect::<Vec<_>>()
+        };
+
+        let squares = match self.piece_type {
+            PieceType::Pawn => {
+                let direction = color.pawn_direction();
+                let mut squares = around(&[(direction, -1), (direction, 0), (direction, 1)]);
+                if x == color.pawns_row() {
+                    squares.extend(offset(x, y, 2 * direction, 0));
+                }
+                squares
+            }
+            PieceType::Knight => around(&KNIGHT_OFFSETS),
+            PieceType::Bishop => lines(&DIAGONAL_DIRECTIONS),
+            PieceType::Rook => lines(&STRAIGHT_DIRECTIONS),
+            PieceType::Queen => [lines(&STRAIGHT_DIRECTIONS), lines(&DIAGONAL_DIRECTIONS)].concat(),
+            PieceType::King => around(&KING_OFFSETS),
+        };
+
+        let mut moves: Vec<Move> = squares
+            .into_iter()
+            .map(|(to_x, to_y)| {
+                let move_type = match position.squares[to_x][to_y] {
+                    Some(piece) if piece.piece_color != color => MoveType::Capture,
+                    _ => MoveType::Move,
+                };
+                Move::new((x, y), to_x, to_y, move_type)
+            })
+            .collect();
+
+        if self.piece_type == PieceType::King && (x, y) == (color.back_row(), 4) {
+            for (king_side, to_y) in [(true, 6), (false, 2)] {
+                if position.castling.get(color, king_side) {
+                    moves.push(Move::new((x, y), x, to_y, MoveType::Castle));
+                }
+            }
+        }
+
+        if self.piece_type == PieceType::Pawn && x.abs_diff(color.opposite().back_row()) == 1 {
+            moves = moves
+                .into_iter()
+                .flat_map(|m| {
+                    [
+                        PieceType::Queen,
+                        PieceType::Rook,
+                        PieceType::Bishop,
+                        PieceType::Knight,
+                    ]
+                    .map(|promotion| Move {
+                        promotion: Some(promotion),
+                        ..m
+                    })
+                })
+                .collect();
+        }
+
+        moves
+    }
+}
+
+/// Move of the piece from row `from_x` and column `from_y` to row `x` and
+/// column `y`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from_x: usize,
+    pub from_y: usize,
+    pub x: usize,
+    pub y: usize,
+    pub move_type: MoveType,
+    /// The piece a pawn turns into when it reaches the last row.
+    pub promotion: Option<PieceType>,
+}
+
+impl Move {
+    #[inline]
+    pub const fn new(from: (usize, usize), x: usize, y: usize, move_type: MoveType) -> Self {
+        Self {
+            from_x: from.0,
+            from_y: from.1,
+            x,
+            y,
+            move_type,
+            promotion: None,
+        }
+    }
+
+    #[inline]
+    pub const fn from(&self) -> (usize, usize) {
+        (self.from_x, self.from_y)
+    }
+
+    #[inline]
+    pub const fn to(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    /// Square of the piece taken by this move, if any.
+    pub fn captured_square(&self) -> Option<(usize, usize)> {
+        match self.move_type {
+            MoveType::Capture => Some(self.to()),
+            MoveType::EnPassant => Some((self.from_x, self.y)),
+            MoveType::Move | MoveType::Castle => None,
+        }
+    }
+
+    /// Squares the rook moves between when this move is castling.
+    pub fn castling_rook(&self) -> Option<((usize, usize), (usize, usize))> {
+        if self.move_type != MoveType::Castle {
+            return None;
+        }
+
+        if self.y > self.from_y {
+            Some(((self.x, COLS - 1), (self.x, self.y - 1)))
+        } else {
+            Some(((self.x, 0), (self.x, self.y + 1)))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MoveType {
+    Move,
+    Capture,
+    /// Pawn capture of a pawn which has just moved two rows past it.
+    EnPassant,
+    /// King move of two columns towards one of its rooks.
+    Castle,
+}
+
+/// Which sides may still castle and in which direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl Default for CastlingRights {
+    fn default() -> Self {
+        Self {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+        }
+    }
+}
+
+impl CastlingRights {
+    pub fn get(&self, color: PieceColor, king_side: bool) -> bool {
+        match (color, king_side) {
+            (PieceColor::White, true) => self.white_king_side,
+            (PieceColor::White, false) => self.white_queen_side,
+            (PieceColor::Black, true) => self.black_king_side,
+            (PieceColor::Black, false) => self.black_queen_side,
+        }
+    }
+
+    fn revoke(&mut self, color: PieceColor, king_side: bool) {
+        let right = match (color, king_side) {
+            (PieceColor::White, true) => &mut self.white_king_side,
+            (PieceColor::White, false) => &mut self.white_queen_side,
+            (PieceColor::Black, true) => &mut self.black_king_side,
+            (PieceColor::Black, false) => &mut self.black_queen_side,
+        };
+        *right = false;
+    }
+
+    /// Revoke the rights lost by a piece leaving or arriving at the square.
+    fn touch(&mut self, x: usize, y: usize) {
+        for color in [PieceColor::White, PieceColor::Black] {
+            if x != color.back_row() {
+                continue;
+            }
+            match y {
+                0 => self.revoke(color, false),
+                4 => {
+                    self.revoke(color, false);
+                    self.revoke(color, true);
+                }
+                7 => self.revoke(color, true),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Win(PieceColor),
+    Draw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndReason {
+    Checkmate,
+    Stalemate,
+    /// The player ran out of time.
+    Timeout,
+    /// The player ran out of time, but the opponent could not have
+    /// checkmated.
+    TimeoutVsInsufficientMaterial,
+    Resignation,
+    /// Both players agreed to a draw.
+    Agreement,
+    /// A player claimed a draw as the position occurred for the third time.
+    ThreefoldRepetition,
+    /// A player claimed a draw after fifty moves by each side without a
+    /// capture or pawn move.
+    FiftyMoveRule,
+}
+
+impl fmt::Display for GameResult {
+    /// The result as written in game records: `1-0`, `0-1` or `1/2-1/2`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Win(PieceColor::White) => write!(f, "1-0"),
+            Self::Win(PieceColor::Black) => write!(f, "0-1"),
+            Self::Draw => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+impl fmt::Display for EndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Checkmate => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::Timeout => "timeout",
+            Self::TimeoutVsInsufficientMaterial => "timeout vs insufficient material",
+            Self::Resignation => "resignation",
+            Self::Agreement => "agreement",
+            Self::ThreefoldRepetition => "threefold repetition",
+            Self::FiftyMoveRule => "fifty-move rule",
+        })
+    }
+}
+
+/// How a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    pub result: GameResult,
+    pub reason: EndReason,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.result, self.reason)
+    }
+}
+
+/// Everything about the game needed to tell which moves are legal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub squares: [[Option<Piece>; COLS]; ROWS],
+    pub side_to_move: PieceColor,
+    pub castling: CastlingRights,
+    /// Square a pawn can be taken on en passant.
+    pub en_passant: Option<(usize, usize)>,
+    /// Number of moves since the last capture or pawn move.
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::STARTING
+    }
+}
+
+impl Position {
+    pub const STARTING: Self = Self {
+        squares: Self::init_positions(),
+        side_to_move: PieceColor::White,
+        castling: CastlingRights {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+        },
+        en_passant: None,
+        halfmove_clock: 0,
+        fullmove_number: 1,
+    };
+
+    const fn init_positions() -> [[Option<Piece>; COLS]; ROWS] {
+        let blacks = Self::init_side(PieceColor::Black);
+        let whites = Self::init_side(PieceColor::White);
+
+        [
+            whites[0],
+            whites[1],
+            [None, None, None, None, None, None, None, None],
+            [None, None, None, None, None, None, None, None],
+            [None, None, None, None, None, None, None, None],
+            [None, None, None, None, None, None, None, None],
+            blacks[1],
+            blacks[0],
+        ]
+    }
+
+    const fn init_side(color: PieceColor) -> [[Option<Piece>; COLS]; 2] {
+        let row = color.back_row();
+        let pawns_row = color.pawns_row();
+
+        [
+            [
+                Some(Piece::rook(color, row, 0)),
+                Some(Piece::knight(color, row, 1)),
+                Some(Piece::bishop(color, row, 2)),
+                Some(Piece::queen(color, row, 3)),
+                Some(Piece::king(color, row, 4)),
+                Some(Piece::bishop(color, row, 5)),
+                Some(Piece::knight(color, row, 6)),
+                Some(Piece::rook(color, row, 7)),
+            ],
+            [
+                Some(Piece::pawn(color, pawns_row, 0)),
+                Some(Piece::pawn(color, pawns_row, 1)),
+                Some(Piece::pawn(color, pawns_row, 2)),
+                Some(Piece::pawn(color, pawns_row, 3)),
+                Some(Piece::pawn(color, pawns_row, 4)),
+                Some(Piece::pawn(color, pawns_row, 5)),
+                Some(Piece::pawn(color, pawns_row, 6)),
+                Some(Piece::pawn(color, pawns_row, 7)),
+            ],
+        ]
+    }
+
+    #[inline]
+    pub fn piece_at(&self, x: usize, y: usize) -> Option<Piece> {
+        self.squares[x][y]
+    }
+
+    pub fn pieces(&self) -> impl Iterator<Item = Piece> + '_ {
+        self.squares.iter().flatten().flatten().copied()
+    }
+
+    /// Total value of the pieces of the color on the board.
+    pub fn material(&self, color: PieceColor) -> u32 {
+        self.pieces()
+            .filter(|p| p.piece_color == color)
+            .map(|p| p.piece_type.value())
+            .sum()
+    }
+
+    pub fn king_square(&self, color: PieceColor) -> Option<(usize, usize)> {
+        self.pieces()
+            .find(|p| p.piece_type == PieceType::King && p.piece_color == color)
+            .map(|p| (p.x, p.y))
+    }
+
+    /// Legal moves of the piece on the square, empty if there is no piece of
+    /// the side to move on it.
+    pub fn legal_moves_from(&self, x: usize, y: usize) -> Vec<Move> {
+        let Some(piece) = self.squares[x][y] else {
+            return vec![];
+        };
+        if piece.piece_color != self.side_to_move {
+            return vec![];
+        }
+
+        piece
+            .possible_moves(self)
+            .into_iter()
+            .filter(|m| !self.leaves_king_in_check(*m))
+            .collect()
+    }
+
+    /// All legal moves of the side to move.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.pieces()
+            .filter(|p| p.piece_color == self.side_to_move)
+            .flat_map(|p| self.legal_moves_from(p.x, p.y))
+            .collect()
+    }
+
+    fn leaves_king_in_check(&self, m: Move) -> bool {
+        let mut after = self.clone();
+        after.make_move(m);
+        after.is_in_check(self.side_to_move)
+    }
+
+    pub fn is_in_check(&self, color: PieceColor) -> bool {
+        self.king_square(color)
+            .map(|(x, y)| self.is_square_attacked(x, y, color.opposite()))
+            .unwrap_or(false)
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check(self.side_to_move) && self.legal_moves().is_empty()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_in_check(self.side_to_move) && self.legal_moves().is_empty()
+    }
+
+    /// Whether the color is left with too little material to ever checkmate:
+    /// a lone king, or a king with a single knight or bishop.
+    pub fn has_insufficient_material(&self, color: PieceColor) -> bool {
+        let mut pieces = self
+            .pieces()
+            .filter(|p| p.piece_color == color && p.piece_type != PieceType::King);
+
+        match (pieces.next(), pieces.next()) {
+            (None, _) => true,
+            (Some(piece), None) => {
+                matches!(piece.piece_type, PieceType::Knight | PieceType::Bishop)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether fifty moves have been played by each side without a capture
+    /// or a pawn move, so that a draw can be claimed.
+    pub fn fifty_move_rule_applies(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    /// Whether both positions count as the same one for repetitions: the
+    /// same pieces on the same squares with the same side to move, castling
+    /// rights and possible en passant captures.
+    pub fn repeats(&self, other: &Self) -> bool {
+        let en_passant = |position: &Self| {
+            position
+                .legal_moves()
+                .into_iter()
+                .any(|m| m.move_type == MoveType::EnPassant)
+                .then_some(position.en_passant)
+                .flatten()
+        };
+
+        self.squares == other.squares
+            && self.side_to_move == other.side_to_move
+            && self.castling == other.castling
+            && en_passant(self) == en_passant(other)
+    }
+
+    /// How the game ended if the side to move is checkmated or stalemated.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.is_checkmate() {
+            Some(Outcome {
+                result: GameResult::Win(self.side_to_move.opposite()),
+                reason: EndReason::Checkmate,
+            })
+        } else if self.is_stalemate() {
+            Some(Outcome {
+                result: GameResult::Draw,
+                reason: EndReason::Stalemate,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Whether any piece of color `by` attacks the square.
+    pub fn is_square_attacked(&self, x: usize, y: usize, by: PieceColor) -> bool {
+        let is = |dx: isize, dy: isize, types: &[PieceType]| -> bool {
+            offset(x, y, dx, dy)
+                .and_then(|(x, y)| self.squares[x][y])
+                .map(|p| p.piece_color == by && types.contains(&p.piece_type))
+                .unwrap_or(false)
+        };
+
+        // Pawns attack the square from the row behind it
+        let pawn_dx = -by.pawn_direction();
+        if is(pawn_dx, 1, &[PieceType::Pawn]) || is(pawn_dx, -1, &[PieceType::Pawn]) {
+            return true;
+        }
+
+        if KNIGHT_OFFSETS
+            .iter()
+            .any(|&(dx, dy)| is(dx, dy, &[PieceType::Knight]))
+        {
+            return true;
+        }
+
+        if KING_OFFSETS
+            .iter()
+            .any(|&(dx, dy)| is(dx, dy, &[PieceType::King]))
+        {
+            return true;
+        }
+
+        let slides = |directions: &[(isize, isize)], types: &[PieceType]| {
+            directions.iter().any(|&(dx, dy)| {
+                let mut square = offset(x, y, dx, dy);
+                while let Some((sx, sy)) = square {
+                    if let Some(piece) = self.squares[sx][sy] {
+                        return piece.piece_color == by && types.contains(&piece.piece_type);
+                    }
+                    square = offset(sx, sy, dx, dy);
+                }
+                false
+            })
+        };
+
+        slides(&STRAIGHT_DIRECTIONS, &[PieceType::Rook, PieceType::Queen])
+            || slides(&DIAGONAL_DIRECTIONS, &[PieceType::Bishop, PieceType::Queen])
+    }
+
+    /// Play the move without checking that it is legal. Returns the captured
+    /// piece.
+    pub fn make_move(&mut self, m: Move) -> Option<Piece> {
+        let mut piece = self.squares[m.from_x][m.from_y]
+            .take()
+            .expect("No piece to move");
+
+        let captured = m
+            .captured_square()
+            .and_then(|(x, y)| self.squares[x][y].take());
+
+        if let Some(((rook_x, rook_y), (x, y))) = m.castling_rook() {
+            if let Some(mut rook) = self.squares[rook_x][rook_y].take() {
+                rook.x = x;
+                rook.y = y;
+                self.squares[x][y] = Some(rook);
+            }
+        }
+
+        piece.x = m.x;
+        piece.y = m.y;
+        if let Some(promotion) = m.promotion {
+            piece.piece_type = promotion;
+        }
+        self.squares[m.x][m.y] = Some(piece);
+
+        self.castling.touch(m.from_x, m.from_y);
+        self.castling.touch(m.x, m.y);
+
+        self.en_passant = (piece.piece_type == PieceType::Pawn && m.x.abs_diff(m.from_x) == 2)
+            .then_some(((m.x + m.from_x) / 2, m.y));
+
+        if piece.piece_type == PieceType::Pawn || captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if self.side_to_move == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = self.side_to_move.opposite();
+
+        captured
+    }
+}
+
+const KNIGHT_OFFSETS: [(isize, isize); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+
+const KING_OFFSETS: [(isize, isize); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+const STRAIGHT_DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+const DIAGONAL_DIRECTIONS: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+/// Square `dx` rows and `dy` columns away, if it is on the board.
+#[inline]
+fn offset(x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+    let x = x.checked_add_signed(dx)?;
+    let y = y.checked_add_signed(dy)?;
+    (x < ROWS && y < COLS).then_some((x, y))
+}
+
+impl PieceType {
+    /// Value of the piece in pawns, used to weigh material. The king is not
+    /// counted.
+    pub const fn value(self) -> u32 {
+        match self {
+            Self::Pawn => 1,
+            Self::Knight | Self::Bishop => 3,
+            Self::Rook => 5,
+            Self::Queen => 9,
+            Self::King => 0,
+        }
+    }
+
+    /// Return a list of possible tiles to which the piece can move.
+    #[inline]
+    pub fn possible_moves(
+        &self,
+        color: PieceColor,
+        x: usize,
+        y: usize,
+        position: &Position,
+    ) -> Vec<Move> {
+        match self {
+            Self::Pawn => Self::pawn_moves(color, x, y, position),
+            Self::Rook => Self::rook_moves(color, x, y, position),
+            Self::Knight => Self::knight_moves(color, x, y, position),
+            Self::Bishop => Self::bishop_moves(color, x, y, position),
+            Self::Queen => Self::queen_moves(color, x, y, position),
+            Self::King => Self::king_moves(color, x, y, position),
+        }
+    }
+
+    #[inline]
+    fn pawn_moves(color: PieceColor, x: usize, y: usize, position: &Position) -> Vec<Move> {
+        let mut moves = vec![];
+        let direction = color.pawn_direction();
+
+        let Some((move_x, _)) = offset(x, y, direction, 0) else {
+            return moves;
+        };
+
+        // Check if the pawn can move forward
+        if position.squares[move_x][y].is_none() {
+            moves.push(Move::new((x, y), move_x, y, MoveType::Move));
+
+            // Check if the pawn can move two tiles forward
+            if x == color.pawns_row() {
+                let double_x = (move_x as isize + direction) as usize;
+                if position.squares[double_x][y].is_none() {
+                    moves.push(Move::new((x, y), double_x, y, MoveType::Move));
+                }
+            }
+        }
+
+        // Check if the pawn can capture a piece
+        for dy in [-1, 1] {
+            let Some((capture_x, capture_y)) = offset(x, y, direction, dy) else {
+                continue;
+            };
+
+            match position.squares[capture_x][capture_y] {
+                Some(piece) if piece.piece_color != color => {
+                    moves.push(Move::new((x, y), capture_x, capture_y, MoveType::Capture));
+                }
+                None if position.en_passant == Some((capture_x, capture_y)) => {
+                    moves.push(Move::new((x, y), capture_x, capture_y, MoveType::EnPassant));
+                }
+                _ => {}
+            }
+        }
+
+        // A pawn reaching the last row has to turn into another piece
+        if move_x == color.opposite().back_row() {
+            moves = moves
+                .into_iter()
+                .flat_map(|m| {
+                    [
+                        PieceType::Queen,
+                        PieceType::Rook,
+                        PieceType::Bishop,
+                        PieceType::Knight,
+                    ]
+                    .map(|promotion| Move {
+                        promotion: Some(promotion),
+                        ..m
+                    })
+                })
+                .collect();
+        }
+
+        moves
+    }
+
+    #[inline]
+    fn rook_moves(color: PieceColor, x: usize, y: usize, position: &Position) -> Vec<Move> {
+        let mut moves = vec![];
+
+        for (dx, dy) in STRAIGHT_DIRECTIONS {
+            slide(color, position, (x, y), dx, dy, &mut moves);
+        }
+
+        moves
+    }
+
+    #[inline]
+    fn bishop_moves(color: PieceColor, x: usize, y: usize, position: &Position) -> Vec<Move> {
+        let mut moves = vec![];
+
+        for (dx, dy) in DIAGONAL_DIRECTIONS {
+            slide(color, position, (x, y), dx, dy, &mut moves);
+        }
+
+        moves
+    }
+
+    #[inline]
+    fn knight_moves(color: PieceColor, x: usize, y: usize, position: &Position) -> Vec<Move> {
+        let mut moves = vec![];
+
+        for (dx, dy) in KNIGHT_OFFSETS {
+            if let Some((to_x, to_y)) = offset(x, y, dx, dy) {
+                let _ = add_move(color, position, (x, y), to_x, to_y, &mut moves);
+            }
+        }
+
+        moves
+    }
+
+    #[inline]
+    fn queen_moves(color: PieceColor, x: usize, y: usize, position: &Position) -> Vec<Move> {
+        let mut moves = vec![];
+
+        moves.append(&mut Self::rook_moves(color, x, y, position));
+        moves.append(&mut Self::bishop_moves(color, x, y, position));
+
+        moves
+    }
+
+    #[inline]
+    fn king_moves(color: PieceColor, x: usize, y: usize, position: &Position) -> Vec<Move> {
+        let mut moves = vec![];
+
+        for (dx, dy) in KING_OFFSETS {
+            if let Some((to_x, to_y)) = offset(x, y, dx, dy) {
+                let _ = add_move(color, position, (x, y), to_x, to_y, &mut moves);
+            }
+        }
+
+        // Check if the king can castle to either side
+        if (x, y) != (color.back_row(), 4) || position.is_in_check(color) {
+            return moves;
+        }
+        let enemy = color.opposite();
+
+        for king_side in [true, false] {
+            if !position.castling.get(color, king_side) {
+                continue;
+            }
+
+            let (rook_y, between, passed): (usize, &[usize], [usize; 2]) = if king_side {
+                (7, &[5, 6], [5, 6])
+            } else {
+                (0, &[1, 2, 3], [3, 2])
+            };
+
+            let has_rook = position.squares[x][rook_y]
+                .map(|p| p.piece_type == PieceType::Rook && p.piece_color == color)
+                .unwrap_or(false);
+            let is_clear = between
+                .iter()
+                .all(|&col| position.squares[x][col].is_none());
+            let is_safe = passed
+                .iter()
+                .all(|&col| !position.is_square_attacked(x, col, enemy));
+
+            if has_rook && is_clear && is_safe {
+                moves.push(Move::new((x, y), x, passed[1], MoveType::Castle));
+            }
+        }
+
+        moves
+    }
+}
+
+/// Add moves along the direction until the piece hits another one.
+#[inline]
+fn slide(
+    color: PieceColor,
+    position: &Position,
+    from: (usize, usize),
+    dx: isize,
+    dy: isize,
+    moves: &mut Vec<Move>,
+) {
+    let mut square = offset(from.0, from.1, dx, dy);
+    while let Some((x, y)) = square {
+        if let ControlFlow::Break(_) = add_move(color, position, from, x, y, moves) {
+            break;
+        }
+        square = offset(x, y, dx, dy);
+    }
+}
+
+#[inline]
+fn add_move(
+    color: PieceColor,
+    position: &Position,
+    from: (usize, usize),
+    row: usize,
+    col: usize,
+    moves: &mut Vec<Move>,
+) -> ControlFlow<()> {
+    if let Some(piece) = position.squares[row][col] {
+        if piece.piece_color != color {
+            moves.push(Move::new(from, row, col, MoveType::Capture));
+        }
+        return ControlFlow::Break(());
+    }
+    moves.push(Move::new(from, row, col, MoveType::Move));
+    ControlFlow::Continue(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Row and column of a square given in algebraic notation, e.g. `e4`.
+    fn square(name: &str) -> (usize, usize) {
+        let bytes = name.as_bytes();
+        ((bytes[1] - b'1') as usize, (bytes[0] - b'a') as usize)
+    }
+
+    /// Position with only the given pieces on the board and no castling
+    /// rights.
+    fn position(pieces: &[(PieceType, PieceColor, &str)], side: PieceColor) -> Position {
+        let mut position = Position {
+            squares: [[None; COLS]; ROWS],
+            side_to_move: side,
+            castling: CastlingRights {
+                white_king_side: false,
+                white_queen_side: false,
+                black_king_side: false,
+                black_queen_side: false,
+            },
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        };
+
+        for &(piece_type, color, name) in pieces {
+            let (x, y) = square(name);
+            position.squares[x][y] = Some(Piece::new(piece_type, color, x, y));
+        }
+
+        position
+    }
+
+    fn play(position: &mut Position, from: &str, to: &str) {
+        let (x, y) = square(from);
+        let m = position
+            .legal_moves_from(x, y)
+            .into_iter()
+            .find(|m| m.to() == square(to))
+            .unwrap_or_else(|| panic!("{from}{to} is not legal"));
+        position.make_move(m);
+    }
+
+    fn perft(position: &Position, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        position
+            .legal_moves()
+            .into_iter()
+            .map(|m| {
+                let mut after = position.clone();
+                after.make_move(m);
+                perft(&after, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn starting_position_move_counts() {
+        assert_eq!(perft(&Position::STARTING, 1), 20);
+        assert_eq!(perft(&Position::STARTING, 2), 400);
+        assert_eq!(perft(&Position::STARTING, 3), 8902);
+    }
+
+    #[test]
+    fn premove_targets_ignore_what_may_change() {
+        let mut position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Rook, PieceColor::White, "a1"),
+                (PieceType::Pawn, PieceColor::White, "a2"),
+                (PieceType::Pawn, PieceColor::White, "e4"),
+                (PieceType::Knight, PieceColor::White, "d4"),
+                (PieceType::King, PieceColor::Black, "e8"),
+            ],
+            PieceColor::Black,
+        );
+        position.castling.white_queen_side = true;
+        let targets = |name: &str| {
+            let (x, y) = square(name);
+            let piece = position.piece_at(x, y).unwrap();
+            piece
+                .premove_targets(&position)
+                .into_iter()
+                .map(|m| m.to())
+                .collect::<Vec<_>>()
+        };
+
+        // Diagonally onto empty squares, in case a piece comes to be taken
+        assert!(targets("e4").contains(&square("d5")));
+        // Past and onto the pieces of the player, which may be taken
+        assert!(targets("a1").contains(&square("a2")));
+        assert!(targets("a1").contains(&square("a8")));
+        // Castling while the rights are kept
+        assert!(targets("e1").contains(&square("c1")));
+        assert!(!targets("e1").contains(&square("g1")));
+        assert!(targets("d4").contains(&square("e6")));
+        assert!(!targets("d4").contains(&square("d5")));
+    }
+
+    #[test]
+    fn castling_moves_the_rook() {
+        let mut position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Rook, PieceColor::White, "h1"),
+                (PieceType::King, PieceColor::Black, "e8"),
+            ],
+            PieceColor::White,
+        );
+        position.castling.white_king_side = true;
+
+        play(&mut position, "e1", "g1");
+
+        assert_eq!(
+            position.piece_at(0, 5).map(|p| p.piece_type),
+            Some(PieceType::Rook)
+        );
+        assert_eq!(position.piece_at(0, 7), None);
+        assert!(!position.castling.white_king_side);
+    }
+
+    #[test]
+    fn cannot_castle_through_or_out_of_check() {
+        let mut position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Rook, PieceColor::White, "a1"),
+                (PieceType::Rook, PieceColor::White, "h1"),
+                (PieceType::Rook, PieceColor::Black, "f8"),
+                (PieceType::King, PieceColor::Black, "a8"),
+            ],
+            PieceColor::White,
+        );
+        position.castling.white_king_side = true;
+        position.castling.white_queen_side = true;
+
+        let castles = |position: &Position| -> Vec<(usize, usize)> {
+            position
+                .legal_moves_from(0, 4)
+                .into_iter()
+                .filter(|m| m.move_type == MoveType::Castle)
+                .map(|m| m.to())
+                .collect()
+        };
+
+        // The rook on f8 covers f1, which the king passes on the king side
+        assert_eq!(castles(&position), vec![square("c1")]);
+
+        // A king in check cannot castle at all
+        position.squares[7][5] = None;
+        position.squares[7][4] = Some(Piece::rook(PieceColor::Black, 7, 4));
+        assert!(castles(&position).is_empty());
+    }
+
+    #[test]
+    fn en_passant_only_right_after_double_push() {
+        let mut position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Pawn, PieceColor::White, "e5"),
+                (PieceType::King, PieceColor::Black, "e8"),
+                (PieceType::Pawn, PieceColor::Black, "d7"),
+                (PieceType::Pawn, PieceColor::Black, "h7"),
+            ],
+            PieceColor::Black,
+        );
+
+        play(&mut position, "d7", "d5");
+        let en_passant = position
+            .legal_moves_from(4, 4)
+            .into_iter()
+            .find(|m| m.move_type == MoveType::EnPassant)
+            .expect("en passant is legal");
+        assert_eq!(en_passant.to(), square("d6"));
+
+        let mut taken = position.clone();
+        taken.make_move(en_passant);
+        assert_eq!(taken.piece_at(4, 3), None);
+
+        // Any other move gives up the right to take en passant
+        play(&mut position, "e1", "e2");
+        play(&mut position, "h7", "h6");
+        assert!(position
+            .legal_moves_from(4, 4)
+            .iter()
+            .all(|m| m.move_type != MoveType::EnPassant));
+    }
+
+    #[test]
+    fn pawn_on_last_row_is_promoted() {
+        let mut position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Pawn, PieceColor::White, "a7"),
+                (PieceType::King, PieceColor::Black, "e8"),
+            ],
+            PieceColor::White,
+        );
+
+        let moves = position.legal_moves_from(6, 0);
+        let mut promotions: Vec<_> = moves.iter().filter_map(|m| m.promotion).collect();
+        promotions.sort_by_key(|p| *p as u8);
+        assert_eq!(
+            promotions,
+            vec![
+                PieceType::Rook,
+                PieceType::Knight,
+                PieceType::Bishop,
+                PieceType::Queen
+            ]
+        );
+
+        let knight = moves
+            .into_iter()
+            .find(|m| m.promotion == Some(PieceType::Knight))
+            .unwrap();
+        position.make_move(knight);
+        assert_eq!(
+            position.piece_at(7, 0).map(|p| p.piece_type),
+            Some(PieceType::Knight)
+        );
+    }
+
+    #[test]
+    fn pinned_piece_cannot_leave_the_pin() {
+        let position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Knight, PieceColor::White, "e2"),
+                (PieceType::Rook, PieceColor::Black, "e8"),
+                (PieceType::King, PieceColor::Black, "a8"),
+            ],
+            PieceColor::White,
+        );
+
+        assert!(position.legal_moves_from(1, 4).is_empty());
+    }
+
+    #[test]
+    fn fools_mate_is_checkmate() {
+        let mut position = Position::STARTING;
+        play(&mut position, "f2", "f3");
+        play(&mut position, "e7", "e5");
+        play(&mut position, "g2", "g4");
+        play(&mut position, "d8", "h4");
+
+        assert!(position.is_checkmate());
+        assert!(!position.is_stalemate());
+        assert_eq!(
+            position.outcome(),
+            Some(Outcome {
+                result: GameResult::Win(PieceColor::Black),
+                reason: EndReason::Checkmate,
+            })
+        );
+    }
+
+    #[test]
+    fn cornered_king_without_moves_is_stalemate() {
+        let position = position(
+            &[
+                (PieceType::King, PieceColor::Black, "a8"),
+                (PieceType::Queen, PieceColor::White, "b6"),
+                (PieceType::King, PieceColor::White, "c6"),
+            ],
+            PieceColor::Black,
+        );
+
+        assert!(position.is_stalemate());
+        assert!(!position.is_checkmate());
+    }
+
+    #[test]
+    fn lone_minor_piece_cannot_mate() {
+        let position = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Bishop, PieceColor::White, "c1"),
+                (PieceType::King, PieceColor::Black, "e8"),
+                (PieceType::Pawn, PieceColor::Black, "a7"),
+            ],
+            PieceColor::White,
+        );
+
+        assert!(position.has_insufficient_material(PieceColor::White));
+        assert!(!position.has_insufficient_material(PieceColor::Black));
+        assert!(!Position::STARTING.has_insufficient_material(PieceColor::White));
+    }
+
+    #[test]
+    fn repetition_ignores_en_passant_which_cannot_be_taken() {
+        let mut double_push = Position::default();
+        play(&mut double_push, "e2", "e4");
+        assert_eq!(double_push.en_passant, Some(square("e3")));
+
+        let mut same_squares = double_push.clone();
+        same_squares.en_passant = None;
+        assert!(double_push.repeats(&same_squares));
+
+        let mut capturable = position(
+            &[
+                (PieceType::King, PieceColor::White, "e1"),
+                (PieceType::Pawn, PieceColor::White, "d2"),
+                (PieceType::King, PieceColor::Black, "e8"),
+                (PieceType::Pawn, PieceColor::Black, "e4"),
+            ],
+            PieceColor::White,
+        );
+        play(&mut capturable, "d2", "d4");
+        let mut without = capturable.clone();
+        without.en_passant = None;
+        assert!(!capturable.repeats(&without));
+    }
+}
@@ -0,0 +1,391 @@
+//! Preferences kept between runs in `settings.toml` in the platform config
+//! directory. The file is read once at startup and written back shortly
+//! after any setting changes, or when the game is closed. A missing or
+//! unreadable file gives the defaults, and a file that could not be read is
+//! never overwritten.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, log, prelude::*, window::WindowResized};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::AnimationSpeed,
+    clock::TimeControl,
+    layout::BoardLayout,
+    menu::GameSetup,
+    theme::{Themes, DEFAULT_THEME},
+};
+
+const FILE_NAME: &str = "settings.toml";
+
+/// Windows are never restored smaller than this.
+const MIN_WINDOW_SIZE: (f32, f32) = (400., 300.);
+
+/// How long to wait after a change before writing the file, so that
+/// resizing the window does not write it on every frame.
+const SAVE_DELAY: f32 = 0.5;
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Path of the theme file, relative to the assets folder.
+    pub theme: String,
+    pub animation_speed: AnimationSpeed,
+    /// Play sound effects.
+    pub sounds: bool,
+    /// Loudness of the sound effects, from 0 to 1.
+    pub volume: f32,
+    /// Always promote pawns to queens, whichever piece is picked with `P`.
+    pub auto_queen: bool,
+    /// Queue several premoves instead of replacing the one made before.
+    pub multiple_premoves: bool,
+    /// Draw the board with black at the bottom in games between two people,
+    /// or in every game if `auto_orient` is off.
+    pub flipped: bool,
+    /// Turn the board toward the player in games against the computer and
+    /// in puzzles.
+    pub auto_orient: bool,
+    /// Time control of new games, as written in the new game dialog.
+    pub time_control: String,
+    /// Puzzle file in the Lichess CSV format, or empty for the puzzles that
+    /// come with the game.
+    pub puzzle_file: String,
+    /// Size of the window when the game was last closed.
+    pub window_size: (f32, f32),
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: DEFAULT_THEME.to_string(),
+            animation_speed: AnimationSpeed::default(),
+            sounds: true,
+            volume: 0.8,
+            auto_queen: false,
+            multiple_premoves: false,
+            flipped: false,
+            auto_orient: true,
+            time_control: time_control_name(&None),
+            puzzle_file: String::new(),
+            window_size: (1280., 720.),
+        }
+    }
+}
+
+/// The file the settings were read from, which they are written back to.
+/// Without one, as in apps embedding the board, changes only last until the
+/// app is closed.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsFile(pub Option<PathBuf>);
+
+impl Settings {
+    /// Settings read from the settings file, or the defaults if there is no
+    /// file or it cannot be read, and the file to save them to.
+    pub fn load() -> (Self, SettingsFile) {
+        let Some(path) = settings_path() else {
+            log::warn!("No config directory, settings will not be kept");
+            return (Self::default(), SettingsFile(None));
+        };
+
+        match Self::load_from(&path) {
+            Some(settings) => (settings, SettingsFile(Some(path))),
+            None => {
+                log::warn!("Settings will not be saved to {}", path.display());
+                (Self::default(), SettingsFile(None))
+            }
+        }
+    }
+
+    /// Settings read from `path`, the defaults if there is no such file or
+    /// it is invalid, or `None` if it cannot be read.
+    fn load_from(path: &Path) -> Option<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Some(Self::default()),
+            Err(err) => {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                return None;
+            }
+        };
+
+        Some(match toml::from_str::<Self>(&text) {
+            Ok(settings) => settings.sanitized(),
+            Err(err) => {
+                // Keep the broken file around instead of overwriting it with
+                // the defaults
+                let backup = path.with_extension("toml.bak");
+                log::warn!(
+                    "Invalid settings in {}, moved to {}: {}",
+                    path.display(),
+                    backup.display(),
+                    err
+                );
+                if let Err(err) = fs::rename(path, &backup) {
+                    log::warn!("Failed to move {}: {}", path.display(), err);
+                }
+                Self::default()
+            }
+        })
+    }
+
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self).map_err(std::io::Error::other)?;
+        fs::write(path, text)
+    }
+
+    /// Replace values the game cannot use with their defaults.
+    fn sanitized(mut self) -> Self {
+        let defaults = Self::default();
+
+        let (width, height) = self.window_size;
+        self.window_size = if width.is_finite() && height.is_finite() {
+            (width.max(MIN_WINDOW_SIZE.0), height.max(MIN_WINDOW_SIZE.1))
+        } else {
+            defaults.window_size
+        };
+
+        self.volume = if self.volume.is_finite() {
+            self.volume.clamp(0., 1.)
+        } else {
+            defaults.volume
+        };
+
+        if self.time_control_index().is_none() {
+            self.time_control = defaults.time_control;
+        }
+
+        self
+    }
+
+    /// Index in [`TimeControl::presets`] of the time control of new games.
+    fn time_control_index(&self) -> Option<usize> {
+        TimeControl::presets()
+            .iter()
+            .position(|control| time_control_name(control) == self.time_control)
+    }
+}
+
+fn time_control_name(control: &Option<TimeControl>) -> String {
+    match control {
+        Some(control) => control.to_string(),
+        None => "untimed".to_string(),
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(FILE_NAME))
+}
+
+/// Counts down from the last change to writing the settings file, running
+/// while there are changes not saved yet.
+#[derive(Resource, Default)]
+struct SaveTimer(Option<Timer>);
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>()
+            .init_resource::<SettingsFile>()
+            .init_resource::<SaveTimer>()
+            .add_startup_system(apply_settings.in_base_set(StartupSet::PreStartup))
+            .add_system(record_settings)
+            // Last, to see the exit however late in the frame it is sent
+            .add_system(save_settings.in_base_set(CoreSet::Last));
+    }
+}
+
+/// Start with the resources the settings are about set from them.
+fn apply_settings(
+    settings: Res<Settings>,
+    mut animation_speed: ResMut<AnimationSpeed>,
+    mut layout: ResMut<BoardLayout>,
+    mut setup: ResMut<GameSetup>,
+) {
+    *animation_speed = settings.animation_speed;
+    layout.flipped = settings.flipped;
+    layout.auto_orient = settings.auto_orient;
+    setup.time_control = settings.time_control_index().unwrap_or(0);
+}
+
+/// Copy changes of the resources the settings are about into [`Settings`].
+#[allow(clippy::too_many_arguments)]
+fn record_settings(
+    mut settings: ResMut<Settings>,
+    animation_speed: Res<AnimationSpeed>,
+    themes: Res<Themes>,
+    asset_server: Res<AssetServer>,
+    layout: Res<BoardLayout>,
+    setup: Res<GameSetup>,
+    mut resized: EventReader<WindowResized>,
+) {
+    if animation_speed.is_changed() && settings.animation_speed != *animation_speed {
+        settings.animation_speed = *animation_speed;
+    }
+
+    if themes.is_changed() {
+        if let Some(path) = themes
+            .selected()
+            .and_then(|handle| asset_server.get_handle_path(handle))
+        {
+            let theme = path.path().to_string_lossy().replace('\\', "/");
+            if settings.theme != theme {
+                settings.theme = theme;
+            }
+        }
+    }
+
+    if layout.is_changed() && settings.auto_orient != layout.auto_orient {
+        settings.auto_orient = layout.auto_orient;
+    }
+
+    // Games against the computer and puzzles are turned to the human's side,
+    // which is not a preference, unless auto-orient is off
+    let oriented = layout.auto_orient && setup.opponent().is_some();
+    if layout.is_changed() && !oriented && settings.flipped != layout.flipped {
+        settings.flipped = layout.flipped;
+    }
+
+    if setup.is_changed() {
+        // The chosen time control is kept while puzzles are played untimed
+        let name = time_control_name(&TimeControl::presets()[setup.time_control]);
+        if settings.time_control != name {
+            settings.time_control = name;
+        }
+    }
+
+    if let Some(event) = resized.iter().last() {
+        let size = (event.width, event.height);
+        if settings.window_size != size {
+            settings.window_size = size;
+        }
+    }
+}
+
+/// Write changed settings back to their file once no setting has changed
+/// for a moment, or right away when the game is closed.
+fn save_settings(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    file: Res<SettingsFile>,
+    mut timer: ResMut<SaveTimer>,
+    exit: EventReader<AppExit>,
+) {
+    if settings.is_changed() && !settings.is_added() {
+        timer.0 = Some(Timer::from_seconds(SAVE_DELAY, TimerMode::Once));
+    }
+
+    let Some(running) = timer.0.as_mut() else {
+        return;
+    };
+    if !running.tick(time.delta()).finished() && exit.is_empty() {
+        return;
+    }
+    timer.0 = None;
+
+    let Some(path) = &file.0 else {
+        return;
+    };
+    match settings.save_to(path) {
+        Ok(()) => log::info!("Settings saved to {}", path.display()),
+        Err(err) => log::error!("Failed to save settings to {}: {}", path.display(), err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "{}-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(FILE_NAME)
+    }
+
+    #[test]
+    fn settings_survive_a_round_trip() {
+        let path = temp_file("round-trip");
+        let settings = Settings {
+            theme: "themes/pixel.theme.ron".to_string(),
+            animation_speed: AnimationSpeed::Fast,
+            sounds: false,
+            volume: 0.5,
+            auto_queen: true,
+            multiple_premoves: true,
+            flipped: true,
+            auto_orient: false,
+            time_control: "3 +2s".to_string(),
+            puzzle_file: "/tmp/lichess_db_puzzle.csv".to_string(),
+            window_size: (900., 700.),
+        };
+
+        settings.save_to(&path).unwrap();
+        assert_eq!(Settings::load_from(&path), Some(settings));
+    }
+
+    #[test]
+    fn missing_and_invalid_values_fall_back_to_defaults() {
+        let path = temp_file("partial");
+        fs::write(
+            &path,
+            "auto_queen = true\nvolume = 3.0\ntime_control = \"7 +7s\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load_from(&path).unwrap();
+        assert!(settings.auto_queen);
+        assert_eq!(settings.volume, 1.);
+        assert_eq!(settings.time_control, Settings::default().time_control);
+        assert_eq!(settings.theme, DEFAULT_THEME);
+    }
+
+    #[test]
+    fn corrupt_file_is_set_aside() {
+        let path = temp_file("corrupt");
+        fs::write(&path, "animation_speed = [[[").unwrap();
+
+        assert_eq!(Settings::load_from(&path), Some(Settings::default()));
+        assert!(!path.exists());
+        assert!(path.with_extension("toml.bak").exists());
+    }
+
+    /// Change a setting, close the game in the same frame and return
+    /// whether the settings were written to `file`.
+    fn change_and_exit(name: &str, file: impl FnOnce(PathBuf) -> SettingsFile) -> bool {
+        let path = temp_file(name);
+        let _ = fs::remove_file(&path);
+
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Settings>()
+            .insert_resource(file(path.clone()))
+            .init_resource::<SaveTimer>()
+            .add_event::<AppExit>()
+            .add_system(save_settings);
+        app.update();
+
+        app.world.resource_mut::<Settings>().auto_queen = true;
+        app.world.send_event(AppExit);
+        app.update();
+
+        Settings::load_from(&path).is_some_and(|settings| settings.auto_queen)
+    }
+
+    #[test]
+    fn changes_are_saved_on_exit_to_the_file_loaded() {
+        assert!(change_and_exit("exit", |path| SettingsFile(Some(path))));
+        assert!(!change_and_exit("embedded", |_| SettingsFile(None)));
+    }
+}
@@ -0,0 +1,221 @@
+//! Sound effects for moves, captures, castling, checks, promotions, illegal
+//! moves, low time and the end of the game. `S` mutes them, `-` and `=`
+//! change the volume. Without an audio device, or without Bevy's audio in
+//! headless runs, the game simply stays silent.
+
+use std::time::Duration;
+
+use bevy::{log, prelude::*, utils::HashMap};
+
+use crate::{
+    clock::ChessClock,
+    history::{GameHistory, HistoryEntry},
+    rules::{MoveType, PieceColor},
+    settings::Settings,
+    AppState, GameOutcome, IllegalMoveAttempted,
+};
+
+/// Remaining time under which the player to move is warned, once per game.
+const LOW_TIME: Duration = Duration::from_secs(10);
+
+/// Change of the volume per key press.
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SoundEffect {
+    Move,
+    Capture,
+    Castle,
+    Check,
+    Promotion,
+    Illegal,
+    LowTime,
+    GameEnd,
+}
+
+impl SoundEffect {
+    const ALL: [Self; 8] = [
+        Self::Move,
+        Self::Capture,
+        Self::Castle,
+        Self::Check,
+        Self::Promotion,
+        Self::Illegal,
+        Self::LowTime,
+        Self::GameEnd,
+    ];
+
+    /// Path of the sound, relative to the assets folder.
+    fn path(self) -> &'static str {
+        match self {
+            Self::Move => "sounds/move.wav",
+            Self::Capture => "sounds/capture.wav",
+            Self::Castle => "sounds/castle.wav",
+            Self::Check => "sounds/check.wav",
+            Self::Promotion => "sounds/promotion.wav",
+            Self::Illegal => "sounds/illegal.wav",
+            Self::LowTime => "sounds/low_time.wav",
+            Self::GameEnd => "sounds/game_end.wav",
+        }
+    }
+
+    /// Sound of the move recorded in `entry`. Checks are the most important
+    /// to hear, then promotions, captures and castling.
+    fn for_move(entry: &HistoryEntry) -> Self {
+        let position = &entry.position;
+        if position.is_in_check(position.side_to_move) {
+            Self::Check
+        } else if entry.m.promotion.is_some() {
+            Self::Promotion
+        } else {
+            match entry.m.move_type {
+                MoveType::Capture | MoveType::EnPassant => Self::Capture,
+                MoveType::Castle => Self::Castle,
+                MoveType::Move => Self::Move,
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Sounds(HashMap<SoundEffect, Handle<AudioSource>>);
+
+/// What has already been heard in the current game.
+#[derive(Resource, Debug, Default)]
+struct SoundState {
+    moves_played: usize,
+    low_time_warned: Vec<PieceColor>,
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoundState>()
+            .add_startup_system(load_sounds)
+            .add_system(reset_sound_state.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(adjust_volume)
+            .add_system(play_sounds.in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+fn load_sounds(mut commands: Commands, asset_server: Option<Res<AssetServer>>) {
+    let Some(asset_server) = asset_server else {
+        return;
+    };
+
+    let handles = SoundEffect::ALL
+        .into_iter()
+        .map(|effect| (effect, asset_server.load(effect.path())))
+        .collect();
+    commands.insert_resource(Sounds(handles));
+}
+
+fn reset_sound_state(mut state: ResMut<SoundState>) {
+    *state = SoundState::default();
+}
+
+fn adjust_volume(keys: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keys.just_pressed(KeyCode::S) {
+        settings.sounds = !settings.sounds;
+        log::info!("Sounds: {}", settings.sounds);
+    }
+
+    let step = if keys.just_pressed(KeyCode::Equals) {
+        VOLUME_STEP
+    } else if keys.just_pressed(KeyCode::Minus) {
+        -VOLUME_STEP
+    } else {
+        return;
+    };
+    settings.volume = (settings.volume + step).clamp(0., 1.);
+    log::info!("Volume: {:.0}%", settings.volume * 100.);
+}
+
+/// Play the sounds of what happened in the game since the last frame.
+#[allow(clippy::too_many_arguments)]
+fn play_sounds(
+    audio: Option<Res<Audio>>,
+    sounds: Option<Res<Sounds>>,
+    settings: Res<Settings>,
+    history: Res<GameHistory>,
+    outcome: Res<GameOutcome>,
+    clock: Res<ChessClock>,
+    mut illegal_moves: EventReader<IllegalMoveAttempted>,
+    mut state: ResMut<SoundState>,
+) {
+    let mut effects = Vec::new();
+
+    if illegal_moves.iter().count() > 0 {
+        effects.push(SoundEffect::Illegal);
+    }
+
+    if history.is_changed() && history.moves.len() != state.moves_played {
+        // Moves which end the game only get the game end sound
+        if history.moves.len() > state.moves_played && outcome.0.is_none() {
+            effects.extend(history.moves.last().map(SoundEffect::for_move));
+        }
+        state.moves_played = history.moves.len();
+    }
+
+    if outcome.is_changed() && outcome.0.is_some() {
+        effects.push(SoundEffect::GameEnd);
+    }
+
+    if let Some(running) = clock.clock.as_ref().filter(|_| outcome.0.is_none()) {
+        let side = running.active();
+        if running.remaining(side) < LOW_TIME && !state.low_time_warned.contains(&side) {
+            state.low_time_warned.push(side);
+            effects.push(SoundEffect::LowTime);
+        }
+    }
+
+    let (Some(audio), Some(sounds)) = (audio, sounds) else {
+        return;
+    };
+    if !settings.sounds {
+        return;
+    }
+
+    for effect in effects {
+        audio.play_with_settings(
+            sounds.0[&effect].clone(),
+            PlaybackSettings::ONCE.with_volume(settings.volume),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation, rules::Position};
+
+    fn sound_of(fen: &str, san: &str) -> SoundEffect {
+        let position = notation::parse_fen(fen).unwrap();
+        let m = notation::parse_move(&position, san).unwrap();
+        let mut history = GameHistory {
+            start: position.clone(),
+            moves: Vec::new(),
+        };
+        history.record(&position, m);
+        SoundEffect::for_move(&history.moves[0])
+    }
+
+    #[test]
+    fn moves_sound_after_what_they_do() {
+        let start = notation::fen(&Position::default());
+        assert_eq!(sound_of(&start, "e4"), SoundEffect::Move);
+
+        let kings_and_rooks = "r3k3/8/8/8/8/8/8/R3K2R w KQq - 0 1";
+        assert_eq!(sound_of(kings_and_rooks, "O-O"), SoundEffect::Castle);
+        assert_eq!(sound_of(kings_and_rooks, "Rxa8+"), SoundEffect::Check);
+        assert_eq!(sound_of(kings_and_rooks, "Ra2"), SoundEffect::Move);
+
+        let pawn = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(sound_of(pawn, "axb8=N"), SoundEffect::Promotion);
+        assert_eq!(
+            sound_of("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1", "Kxd2"),
+            SoundEffect::Capture
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Board colors and piece sets loaded from the theme files in
+//! `assets/themes`. Themes can be switched at runtime and are reloaded when
+//! their files change on disk.
+
+use std::path::Path;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    log,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{is_dark_square, rules::PieceColor, settings::Settings, Piece, PieceType, Tile};
+
+/// Folder of the theme files, relative to the assets folder.
+const THEMES_FOLDER: &str = "themes";
+
+/// Theme selected when no other theme is set in the settings.
+pub const DEFAULT_THEME: &str = "themes/classic.theme.ron";
+
+/// Placeholder in the image paths of [`PieceSet::Images`] replaced by the
+/// name of the piece type.
+const PIECE_PLACEHOLDER: &str = "{piece}";
+
+/// Colors of the board and the images its pieces are drawn with. Themes are
+/// read from `*.theme.ron` or `*.theme.toml` files.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "6c2b7f0e-3f1d-4d8a-9a49-2b8f4f1f6c35"]
+pub struct Theme {
+    pub name: String,
+    /// RGB color of the light squares.
+    pub light_square: [f32; 3],
+    /// RGB color of the dark squares.
+    pub dark_square: [f32; 3],
+    pub pieces: PieceSet,
+}
+
+impl Default for Theme {
+    /// Same as `assets/themes/classic.theme.ron`, used until the theme files
+    /// are loaded.
+    fn default() -> Self {
+        Self {
+            name: "Classic".to_string(),
+            light_square: [0.9, 0.9, 0.9],
+            dark_square: [0.1, 0.1, 0.1],
+            pieces: PieceSet::Images {
+                white: "W_{piece}.png".to_string(),
+                black: "B_{piece}.png".to_string(),
+            },
+        }
+    }
+}
+
+impl Theme {
+    pub fn light_color(&self) -> Color {
+        let [r, g, b] = self.light_square;
+        Color::rgb(r, g, b)
+    }
+
+    pub fn dark_color(&self) -> Color {
+        let [r, g, b] = self.dark_square;
+        Color::rgb(r, g, b)
+    }
+
+    /// Color of the tile at `row` and `col`.
+    pub fn square_color(&self, row: usize, col: usize) -> Color {
+        if is_dark_square(row, col) {
+            self.dark_color()
+        } else {
+            self.light_color()
+        }
+    }
+
+    /// Texture and the region of it the piece is drawn with.
+    pub fn piece_sprite(
+        &self,
+        asset_server: &AssetServer,
+        color: PieceColor,
+        piece_type: PieceType,
+    ) -> (Handle<Image>, Option<Rect>) {
+        match &self.pieces {
+            PieceSet::Images { white, black } => {
+                let path = match color {
+                    PieceColor::White => white,
+                    PieceColor::Black => black,
+                };
+                let path = path.replace(PIECE_PLACEHOLDER, &format!("{:?}", piece_type));
+
+                (asset_server.load(path), None)
+            }
+            PieceSet::SpriteSheet {
+                white,
+                black,
+                frame_size: [width, height],
+                order,
+            } => {
+                let path = match color {
+                    PieceColor::White => white,
+                    PieceColor::Black => black,
+                };
+                let index = order.iter().position(|&t| t == piece_type).unwrap_or(0);
+                let min = Vec2::new(index as f32 * width, 0.);
+
+                (
+                    asset_server.load(path.as_str()),
+                    Some(Rect::from_corners(min, min + Vec2::new(*width, *height))),
+                )
+            }
+        }
+    }
+}
+
+/// Where the piece images of a theme come from. Paths are relative to the
+/// assets folder.
+#[derive(Debug, Clone, Deserialize)]
+pub enum PieceSet {
+    /// One image per piece. `{piece}` in the paths is replaced by the name of
+    /// the piece type, e.g. `W_{piece}.png` becomes `W_Knight.png`.
+    Images { white: String, black: String },
+    /// One sprite sheet per side, with the frames of `order` side by side in
+    /// a single row.
+    SpriteSheet {
+        white: String,
+        black: String,
+        /// Width and height of a single frame in pixels.
+        frame_size: [f32; 2],
+        order: Vec<PieceType>,
+    },
+}
+
+#[derive(Default)]
+struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let theme = parse_theme(load_context.path(), bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(theme));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron", "theme.toml"]
+    }
+}
+
+/// Parse a theme file, as TOML if the file has a `.toml` extension and as
+/// RON otherwise.
+fn parse_theme(path: &Path, bytes: &[u8]) -> Result<Theme, bevy::asset::Error> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "toml")
+    {
+        Ok(toml::from_str(std::str::from_utf8(bytes)?)?)
+    } else {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+}
+
+/// The theme files found in the themes folder, sorted by path, and which one
+/// of them is selected.
+#[derive(Resource, Debug, Default)]
+pub struct Themes {
+    handles: Vec<Handle<Theme>>,
+    selected: usize,
+}
+
+impl Themes {
+    pub fn selected(&self) -> Option<&Handle<Theme>> {
+        self.handles.get(self.selected)
+    }
+}
+
+/// The theme the board is currently drawn with. Replaced whenever another
+/// theme is selected or the selected theme file changes.
+#[derive(Resource, Debug, Default, Deref)]
+pub struct BoardTheme(Theme);
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_resource::<Themes>()
+            .init_resource::<BoardTheme>()
+            .add_startup_system(load_themes)
+            .add_system(cycle_theme)
+            .add_system(update_board_theme.after(cycle_theme))
+            .add_system(apply_tile_colors.after(update_board_theme))
+            .add_system(apply_piece_sprites.after(update_board_theme));
+    }
+}
+
+/// Load every theme file and select the one of the settings, or the default
+/// theme if it is not found.
+fn load_themes(
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    mut themes: ResMut<Themes>,
+) {
+    let handles = match asset_server.load_folder(THEMES_FOLDER) {
+        Ok(handles) => handles,
+        Err(err) => {
+            log::error!("Failed to load themes: {}", err);
+            return;
+        }
+    };
+
+    let mut handles: Vec<_> = handles
+        .into_iter()
+        .map(|handle| handle.typed::<Theme>())
+        .collect();
+    handles.sort_by_key(|handle| {
+        asset_server
+            .get_handle_path(handle)
+            .map(|path| path.path().to_path_buf())
+    });
+
+    let find = |theme: &str| {
+        handles.iter().position(|handle| {
+            asset_server
+                .get_handle_path(handle)
+                .is_some_and(|path| path.path().ends_with(theme))
+        })
+    };
+    themes.selected = find(&settings.theme)
+        .or_else(|| find(DEFAULT_THEME))
+        .unwrap_or(0);
+    themes.handles = handles;
+}
+
+fn cycle_theme(keys: Res<Input<KeyCode>>, mut themes: ResMut<Themes>) {
+    if keys.just_pressed(KeyCode::T) && !themes.handles.is_empty() {
+        themes.selected = (themes.selected + 1) % themes.handles.len();
+    }
+}
+
+/// Copy the selected theme into [`BoardTheme`] when the selection changes or
+/// when its file is loaded or modified.
+fn update_board_theme(
+    themes: Res<Themes>,
+    assets: Res<Assets<Theme>>,
+    mut events: EventReader<AssetEvent<Theme>>,
+    mut board_theme: ResMut<BoardTheme>,
+) {
+    let Some(selected) = themes.selected() else {
+        return;
+    };
+
+    let reloaded = events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle == selected,
+        AssetEvent::Removed { .. } => false,
+    });
+    if !themes.is_changed() && !reloaded {
+        return;
+    }
+
+    if let Some(theme) = assets.get(selected) {
+        log::info!("Theme: {}", theme.name);
+        board_theme.0 = theme.clone();
+    }
+}
+
+fn apply_tile_colors(theme: Res<BoardTheme>, mut tiles: Query<(&Tile, &mut Sprite)>) {
+    if !theme.is_changed() {
+        return;
+    }
+
+    for (tile, mut sprite) in tiles.iter_mut() {
+        sprite.color = theme.square_color(tile.x, tile.y);
+    }
+}
+
+/// Draw pieces with the sprites of the theme, including pawns which have
+/// just been promoted.
+#[allow(clippy::type_complexity)]
+fn apply_piece_sprites(
+    theme: Res<BoardTheme>,
+    asset_server: Res<AssetServer>,
+    mut pieces: Query<(Ref<Piece>, &mut Sprite, &mut Handle<Image>)>,
+) {
+    for (piece, mut sprite, mut texture) in pieces.iter_mut() {
+        if !theme.is_changed() && !piece.is_changed() {
+            continue;
+        }
+
+        let (image, rect) = theme.piece_sprite(&asset_server, piece.piece_color, piece.piece_type);
+        *texture = image;
+        sprite.rect = rect;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_themes_parse() {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets")
+            .join(THEMES_FOLDER);
+
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(folder).unwrap() {
+            let path = entry.unwrap().path();
+            let theme = parse_theme(&path, &std::fs::read(&path).unwrap())
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            names.push(theme.name);
+        }
+        names.sort();
+
+        assert_eq!(names, ["Classic", "Pixel", "Walnut"]);
+    }
+}
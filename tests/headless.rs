@@ -0,0 +1,207 @@
+//! Games played without a window: the tests send the events clicks on the
+//! tiles are turned into, advance frames, and check the board, the piece
+//! components and the events the game sends back.
+
+use bevy::{ecs::event::Event, prelude::*};
+use bevy_chess::{
+    notation,
+    rules::{EndReason, GameResult},
+    AppState, Board, BoardPlugin, CheckGiven, GameEnded, GameSetup, IllegalMoveAttempted, MoveMade,
+    Piece, PieceColor, PieceSelected, PieceType, RequestMove, SelectSquare,
+};
+
+/// An app running the game on [`MinimalPlugins`], in a game from `fen`.
+struct Harness {
+    app: App,
+}
+
+impl Harness {
+    fn new(fen: &str) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(BoardPlugin)
+            .insert_resource(GameSetup {
+                position: Some(notation::parse_fen(fen).expect("valid FEN")),
+                ..default()
+            });
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::InGame);
+        app.update();
+
+        Self { app }
+    }
+
+    fn starting_position() -> Self {
+        Self::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+    }
+
+    /// Click the tile of the square named `name`, like `e4`.
+    fn click(&mut self, name: &str) {
+        let (x, y) = square(name);
+        self.app.world.send_event(SelectSquare {
+            x,
+            y,
+            promotion: None,
+        });
+        self.advance();
+    }
+
+    fn request(&mut self, from: &str, to: &str) {
+        self.app.world.send_event(RequestMove {
+            from: square(from),
+            to: square(to),
+            promotion: None,
+        });
+        self.advance();
+    }
+
+    /// Run two frames, so that the systems reading the board see the changes
+    /// of the first one.
+    fn advance(&mut self) {
+        self.app.update();
+        self.app.update();
+    }
+
+    /// The events of type `E` sent since they were last taken, at most two
+    /// frames ago.
+    fn events<E: Event + Clone>(&mut self) -> Vec<E> {
+        self.app.world.resource_mut::<Events<E>>().drain().collect()
+    }
+
+    fn board(&self) -> &Board {
+        self.app.world.resource::<Board>()
+    }
+
+    fn entity_on(&self, name: &str) -> Option<Entity> {
+        let (x, y) = square(name);
+        self.board().state[x][y]
+    }
+
+    /// The piece component of the entity on the square named `name`.
+    fn piece_on(&self, name: &str) -> Option<Piece> {
+        let entity = self.entity_on(name)?;
+        self.app.world.get::<Piece>(entity).copied()
+    }
+}
+
+fn square(name: &str) -> (usize, usize) {
+    notation::parse_square(name).expect("valid square name")
+}
+
+#[test]
+fn clicking_a_piece_of_the_side_to_move_selects_it() {
+    let mut harness = Harness::starting_position();
+
+    harness.click("g1");
+    let selected = harness.events::<PieceSelected>();
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].square, square("g1"));
+    assert_eq!(selected[0].piece.piece_type, PieceType::Knight);
+
+    harness.click("e7");
+    assert!(harness.events::<PieceSelected>().is_empty());
+}
+
+#[test]
+fn clicked_moves_are_played() {
+    let mut harness = Harness::starting_position();
+    let pawn = harness.entity_on("e2");
+
+    harness.click("e2");
+    harness.click("e4");
+
+    assert_eq!(harness.entity_on("e2"), None);
+    assert_eq!(harness.entity_on("e4"), pawn);
+    let piece = harness.piece_on("e4").expect("the pawn is on e4");
+    assert_eq!((piece.x, piece.y), square("e4"));
+    assert_eq!(harness.board().position.side_to_move, PieceColor::Black);
+
+    let moves = harness.events::<MoveMade>();
+    assert_eq!(moves.len(), 1);
+    assert_eq!(moves[0].from, square("e2"));
+    assert_eq!(moves[0].to, square("e4"));
+    assert_eq!(moves[0].piece.piece_type, PieceType::Pawn);
+    assert_eq!(moves[0].captured, None);
+    assert_eq!(moves[0].san, "e4");
+}
+
+#[test]
+fn captured_pieces_leave_the_board() {
+    let mut harness = Harness::new("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1");
+    let captured = harness.entity_on("d5").expect("a pawn on d5");
+
+    harness.click("e4");
+    harness.click("d5");
+
+    let piece = harness.piece_on("d5").expect("the pawn took on d5");
+    assert_eq!(piece.piece_color, PieceColor::White);
+    assert!(harness.app.world.get::<Piece>(captured).is_none());
+    assert_eq!(harness.board().position.pieces().count(), 3);
+
+    let moves = harness.events::<MoveMade>();
+    assert_eq!(moves.len(), 1);
+    assert_eq!(
+        moves[0].captured.map(|piece| piece.piece_color),
+        Some(PieceColor::Black)
+    );
+    assert_eq!(moves[0].san, "exd5");
+}
+
+#[test]
+fn illegal_moves_are_refused() {
+    let mut harness = Harness::starting_position();
+    let before = harness.board().position.clone();
+
+    harness.click("e2");
+    harness.click("e5");
+
+    assert_eq!(harness.board().position, before);
+    assert!(harness.piece_on("e2").is_some());
+    assert!(harness.events::<MoveMade>().is_empty());
+    let refused = harness.events::<IllegalMoveAttempted>();
+    assert_eq!(refused.len(), 1);
+    assert!(refused[0].reason.contains("e5"), "{}", refused[0].reason);
+
+    harness.request("e2", "e5");
+    assert_eq!(harness.board().position, before);
+    assert_eq!(harness.events::<IllegalMoveAttempted>().len(), 1);
+}
+
+#[test]
+fn requested_moves_are_played_until_the_game_ends() {
+    let mut harness = Harness::starting_position();
+
+    let mut sans = Vec::new();
+    for (from, to) in [("f2", "f3"), ("e7", "e5"), ("g2", "g4"), ("d8", "h4")] {
+        harness.request(from, to);
+        sans.extend(
+            harness
+                .events::<MoveMade>()
+                .into_iter()
+                .map(|made| made.san),
+        );
+    }
+
+    assert_eq!(sans, ["f3", "e5", "g4", "Qh4#"]);
+    assert_eq!(
+        harness.events::<CheckGiven>(),
+        [CheckGiven {
+            color: PieceColor::White
+        }]
+    );
+    assert_eq!(
+        harness.events::<GameEnded>(),
+        [GameEnded {
+            result: GameResult::Win(PieceColor::Black),
+            reason: EndReason::Checkmate,
+        }]
+    );
+
+    harness.request("a2", "a3");
+    assert!(harness.events::<MoveMade>().is_empty());
+    assert_eq!(
+        harness.events::<IllegalMoveAttempted>()[0].reason,
+        "the game is over"
+    );
+}